@@ -0,0 +1,50 @@
+use crate::services::operators::BinaryOperator;
+use crate::typ::typ::Typ;
+
+/// An expression of the source program. `pos` is the offset of its first character in the lexer input.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+    Number { value: i64, pos: usize },
+    Float { value: f64, pos: usize },
+    Bool { value: bool, pos: usize },
+    Identifier { name: String, pos: usize },
+    Minus { operand: Box<Expr>, pos: usize },
+    Not { operand: Box<Expr>, pos: usize },
+    /// `to` is IntBot or BoolBot
+    Cast { to: Typ, operand: Box<Expr>, pos: usize },
+    /// `op_pos` is the offset of the operator and `end` the offset just after `rhs`
+    Binary { operator: BinaryOperator, lhs: Box<Expr>, rhs: Box<Expr>, pos: usize, op_pos: usize, end: usize },
+    /// `new Name`. `typ` is the Struct.
+    New { typ: Typ, pos: usize },
+    /// `new int[len]`. `elem` is IntBot or BoolBot.
+    NewArray { elem: Typ, len: Box<Expr>, pos: usize },
+    /// `array[index]`
+    Index { array: Box<Expr>, index: Box<Expr>, pos: usize },
+    /// `name(args)`
+    Call { name: String, args: Vec<Expr>, pos: usize },
+    /// `object.field`
+    Field { object: Box<Expr>, field: String, pos: usize },
+    /// `if (cond) then else otherwise`
+    If { cond: Box<Expr>, then: Box<Expr>, otherwise: Box<Expr>, pos: usize },
+}
+
+impl Expr {
+    pub fn pos(&self) -> usize {
+        match self {
+            Expr::Number { pos, .. }
+            | Expr::Float { pos, .. }
+            | Expr::Bool { pos, .. }
+            | Expr::Identifier { pos, .. }
+            | Expr::Minus { pos, .. }
+            | Expr::Not { pos, .. }
+            | Expr::Cast { pos, .. }
+            | Expr::Binary { pos, .. }
+            | Expr::New { pos, .. }
+            | Expr::Call { pos, .. }
+            | Expr::NewArray { pos, .. }
+            | Expr::Index { pos, .. }
+            | Expr::Field { pos, .. }
+            | Expr::If { pos, .. } => *pos,
+        }
+    }
+}
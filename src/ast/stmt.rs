@@ -0,0 +1,43 @@
+use crate::ast::expr::Expr;
+use crate::typ::typ::Typ;
+
+/// A statement of the source program. `pos` is the offset of its first character in the lexer input.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Stmt {
+    /// `declared_typ` is IntBot, BoolBot, a Struct or an Array. A const variable can't be assigned.
+    Decl { declared_typ: Typ, name: String, value: Expr, is_const: bool, pos: usize },
+    Assign { name: String, value: Expr, pos: usize },
+    /// `object.field = value;`
+    FieldAssign { object: Expr, field: String, value: Expr, pos: usize },
+    /// `array[index] = value;`
+    IndexAssign { array: Expr, index: Expr, value: Expr, pos: usize },
+    /// `ret_typ name(params) { body }`
+    Function { ret_typ: Typ, name: String, params: Vec<(String, Typ)>, body: Vec<Stmt>, pos: usize },
+    /// `struct Name { ... }`. `typ` is the declared Struct.
+    Struct { typ: Typ, pos: usize },
+    Return { value: Expr, pos: usize },
+    Print { value: Expr, pos: usize },
+    Block { stmts: Vec<Stmt>, pos: usize },
+    /// `#showGraph;` appends the current graph to the debug output
+    ShowGraph { pos: usize },
+    /// a lone `;`
+    Empty { pos: usize },
+}
+
+impl Stmt {
+    pub fn pos(&self) -> usize {
+        match self {
+            Stmt::Decl { pos, .. }
+            | Stmt::Assign { pos, .. }
+            | Stmt::FieldAssign { pos, .. }
+            | Stmt::IndexAssign { pos, .. }
+            | Stmt::Struct { pos, .. }
+            | Stmt::Function { pos, .. }
+            | Stmt::Return { pos, .. }
+            | Stmt::Print { pos, .. }
+            | Stmt::Block { pos, .. }
+            | Stmt::ShowGraph { pos }
+            | Stmt::Empty { pos } => *pos,
+        }
+    }
+}
@@ -0,0 +1,104 @@
+use crate::errors::son_error::{ErrorWithContext, SoNError};
+use crate::services::parser::{Parser, ParserBuilder};
+use std::io::{self, BufRead, Read, Write};
+
+pub const USAGE: &str = "usage: ruson [--optimize | --no-optimize] [--arg <int>] [--repl | -o <output.dot> [program]]";
+
+/// Options of the ruson binary. Without an input the program is read from stdin
+/// and without an output the dotfile is written to stdout.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CliOptions {
+    pub input: Option<String>,
+    pub output: Option<String>,
+    /// the value of `arg`. It is unknown if not given.
+    pub arg: Option<i64>,
+    pub optimize: bool,
+    /// read and evaluate one line at a time instead of compiling a program
+    pub repl: bool,
+}
+
+impl Default for CliOptions {
+    fn default() -> Self {
+        CliOptions { input: None, output: None, arg: None, optimize: true, repl: false }
+    }
+}
+
+impl CliOptions {
+    /// parses the command line arguments without the program name
+    pub fn parse(args: impl IntoIterator<Item=String>) -> Result<CliOptions, String> {
+        let mut options = CliOptions::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--optimize" => options.optimize = true,
+                "--no-optimize" => options.optimize = false,
+                "--repl" => options.repl = true,
+                "--arg" => {
+                    let value = args.next().ok_or("--arg requires a value")?;
+                    options.arg = Some(value.parse().map_err(|_| format!("--arg expects an integer but got {}", value))?);
+                }
+                "-o" => options.output = Some(args.next().ok_or("-o requires a path")?),
+                _ if arg.starts_with('-') => return Err(format!("unknown option {}", arg)),
+                _ if options.input.is_none() => options.input = Some(arg),
+                _ => return Err(format!("unexpected argument {}", arg)),
+            }
+        }
+        Ok(options)
+    }
+}
+
+fn build_parser(program: &str, options: &CliOptions) -> Result<Parser, ErrorWithContext> {
+    let builder = ParserBuilder::new().do_optimize(options.optimize);
+    let parser = match options.arg {
+        Some(arg) => builder.build(program, arg),
+        None => builder.build_noarg(program),
+    };
+    parser.map_err(|error| ErrorWithContext { error, line: 0, col: 0, source_line: None })
+}
+
+/// parses the program and renders its graph as a dotfile
+pub fn compile_to_dot(program: &str, options: &CliOptions) -> Result<String, ErrorWithContext> {
+    let mut parser = build_parser(program, options)?;
+    parser.parse()?;
+    Ok(parser.as_dotfile())
+}
+
+/// like [compile_to_dot] but reads the whole program from `reader`, e.g. stdin
+pub fn compile_reader_to_dot(reader: impl Read, options: &CliOptions) -> Result<String, ErrorWithContext> {
+    let program = io::read_to_string(reader)
+        .map_err(|e| ErrorWithContext { error: SoNError::Io { path: "<reader>".into(), message: e.to_string() }, line: 0, col: 0, source_line: None })?;
+    compile_to_dot(&program, options)
+}
+
+/// Parses one line of the REPL and returns the folded source followed by the evaluated result.
+/// A line that doesn't end with `;` or `}` is a bare expression and is returned. `arg` is 0 unless given with `--arg`.
+pub fn evaluate_line(line: &str, options: &CliOptions) -> String {
+    let line = line.trim();
+    let program = if line.ends_with(';') || line.ends_with('}') { line.to_string() } else { format!("return {};", line) };
+    let mut parser = match build_parser(&program, options) {
+        Ok(parser) => parser,
+        Err(e) => return e.to_string(),
+    };
+    if let Err(e) = parser.parse() {
+        return e.to_string();
+    }
+    match parser.evaluate_args(&[options.arg.unwrap_or(0)]) {
+        Ok(typ) => format!("{}\n=> {}", parser.to_source(), typ),
+        Err(e) => format!("{}\n=> {:?}", parser.to_source(), e),
+    }
+}
+
+/// Evaluates each line of `input` with [evaluate_line] until the input ends. Errors are printed and the loop goes on.
+pub fn repl(input: impl BufRead, mut output: impl Write, options: &CliOptions) -> io::Result<()> {
+    write!(output, "> ")?;
+    output.flush()?;
+    for line in input.lines() {
+        let line = line?;
+        if !line.trim().is_empty() {
+            writeln!(output, "{}", evaluate_line(&line, options))?;
+        }
+        write!(output, "> ")?;
+        output.flush()?;
+    }
+    writeln!(output)
+}
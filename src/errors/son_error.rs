@@ -6,11 +6,21 @@ pub struct ErrorWithContext {
     pub error: SoNError,
     pub line: usize,
     pub col: usize,
+    /// the source line the error points into. None, if the position is out of bounds.
+    pub source_line: Option<String>,
 }
 
 impl Display for ErrorWithContext {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "Error@{}:{} {:?}", self.line, self.col, self.error)
+        write!(f, "Error@{}:{} {:?}", self.line, self.col, self.error)?;
+        if let Some(source_line) = &self.source_line {
+            let gutter = " ".repeat(self.line.to_string().len());
+            write!(f, "\n{} |\n{} | {}\n{} | {}^", gutter, self.line, source_line, gutter, " ".repeat(self.col.saturating_sub(1)))?;
+        }
+        if let SoNError::VariableUndefined { suggestion: Some(suggestion), .. } = &self.error {
+            write!(f, "\ndid you mean '{}'?", suggestion)?;
+        }
+        Ok(())
     }
 }
 
@@ -20,14 +30,113 @@ pub enum SoNError {
     NumberCannotStartWith0,
     SyntaxExpected { expected: String, but_got: String },
     TypTransitionNotAllowed,
+    TypeMismatch { variable: Option<String>, expected: String, but_got: String },
     VariableRedefinition { variable: String },
-    VariableUndefined { variable: String },
+    /// `suggestion` is the closest variable in scope, if one is close enough to be a typo
+    VariableUndefined { variable: String, suggestion: Option<String> },
+    AssignToConst { variable: String },
+    ReservedKeyword { keyword: String },
+    InvalidOperator { lexeme: String },
+    StructRedefinition { name: String },
+    StructUndefined { name: String },
+    FieldRedefinition { struct_name: String, field: String },
+    FieldUndefined { struct_name: String, field: String },
+    FunctionRedefinition { name: String },
+    UnknownFunction { name: String },
+    /// the last statement of the body of `function` isn't a return
+    MissingReturn { function: String },
+    IndexOutOfBounds { index: i64, len: usize },
+    Io { path: String, message: String },
+    UnterminatedComment,
+    ArgumentCountMismatch { expected: usize, but_got: usize },
+    DivisionByZero,
+    /// an int power with a negative exponent or a result that doesn't fit into an int
+    PowOutOfRange,
+    /// `abs` of the smallest int, whose absolute value doesn't fit into an int
+    IntegerOverflow,
+    /// adding a node would exceed [crate::services::parser::ParserOptions::max_nodes] live nodes
+    GraphSizeLimitExceeded { max_nodes: usize },
+    /// expressions or blocks are nested deeper than [crate::services::parser::ParserOptions::max_nesting_depth]
+    NestingTooDeep { max_depth: usize },
+    /// nid would be its own input. Only a Phi may do that.
+    SelfReference { nid: usize },
+    /// the control of nid doesn't lead back to the Start, or the evaluator can't follow it any further
+    ControlDisconnected { nid: usize },
+    StepBudgetExceeded,
+    /// the evaluator ran more than [crate::services::evaluator::MAX_CALL_DEPTH] calls inside each other
+    ExecutionLimitExceeded,
+    /// the evaluator has no value for nid, like for memory or control
+    NotEvaluable { nid: usize },
     DebugPropagateControlFlowUpward,
 }
 
 impl SoNError {
+    /// the error at the position of the lexer
     pub fn attach_context(&self, parser: &Parser) -> ErrorWithContext {
-        let (line, col) = parser.lexer.dbg_position().unwrap_or((0, 0));
-        ErrorWithContext { error: self.clone(), line, col }
+        self.attach_context_at(parser, parser.lexer.position())
     }
-}
\ No newline at end of file
+
+    /// the error at the source `position`, like the one of the lowered statement or expression
+    pub fn attach_context_at(&self, parser: &Parser, position: usize) -> ErrorWithContext {
+        let (line, col) = parser.lexer.line_col_for(position).unwrap_or((0, 0));
+        let source_line = line.checked_sub(1).and_then(|i| parser.lexer.source_lines().nth(i)).map(|(_, line)| line.to_string());
+        ErrorWithContext { error: self.clone(), line, col, source_line }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_render_source_line_with_caret() {
+        // Arrange
+        let mut parser = Parser::new_noarg("int a = 1;\nint b = ;").unwrap();
+
+        // Act
+        let error = parser.parse().unwrap_err();
+
+        // Assert
+        let message = format!("{}", error);
+        let lines: Vec<&str> = message.lines().collect();
+        assert_eq!(4, lines.len());
+        assert_eq!("  |", lines[1]);
+        assert_eq!("2 | int b = ;", lines[2]);
+        assert_eq!("  |         ^", lines[3]);
+    }
+
+    #[test]
+    fn should_render_caret_at_column_zero() {
+        // Arrange
+        let error = ErrorWithContext { error: SoNError::NodeIdNotExisting, line: 1, col: 0, source_line: Some("return 1;".into()) };
+
+        // Act
+        let message = format!("{}", error);
+
+        // Assert
+        assert_eq!(Some("  | ^"), message.lines().last());
+    }
+
+    #[test]
+    fn should_render_suggestion_for_undefined_variable() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return ar;").unwrap();
+
+        // Act
+        let error = parser.parse().unwrap_err();
+
+        // Assert
+        assert_eq!(Some("did you mean 'arg'?"), format!("{}", error).lines().last());
+    }
+
+    #[test]
+    fn should_render_only_the_header_when_out_of_bounds() {
+        // Arrange
+        let error = ErrorWithContext { error: SoNError::NodeIdNotExisting, line: 0, col: 0, source_line: None };
+
+        // Act
+        let message = format!("{}", error);
+
+        // Assert
+        assert_eq!("Error@0:0 NodeIdNotExisting", message);
+    }
+}
@@ -0,0 +1,6 @@
+pub mod ast;
+pub mod cli;
+pub mod errors;
+pub mod nodes;
+pub mod services;
+pub mod typ;
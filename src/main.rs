@@ -1,17 +1,49 @@
 extern crate core;
-use crate::services::parser::Parser;
-use std::fs;
+use ruson::cli::{compile_reader_to_dot, compile_to_dot, repl, CliOptions, USAGE};
+use std::process::ExitCode;
+use std::{env, fs, io};
 
-pub mod nodes;
-pub mod services;
-pub mod typ;
-mod errors;
 
-
-fn main() {
-    let mut parser = Parser::new_noarg("return 1 ^ 1 ^ 1213 & 11111111;").unwrap();
-    parser.do_optimize = true;
-    let r = parser.parse().unwrap();
-
-    fs::write("target/output.dot", parser.as_dotfile()).expect("Unable to write file");
+fn main() -> ExitCode {
+    let options = match CliOptions::parse(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}\n{}", message, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    if options.repl {
+        return match repl(io::stdin().lock(), io::stdout(), &options) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(e) => {
+                eprintln!("{}", e);
+                ExitCode::FAILURE
+            }
+        };
+    }
+    let dot = match &options.input {
+        Some(path) => match fs::read_to_string(path) {
+            Ok(program) => compile_to_dot(&program, &options),
+            Err(e) => {
+                eprintln!("Unable to read {}: {}", path, e);
+                return ExitCode::FAILURE;
+            }
+        },
+        None => compile_reader_to_dot(io::stdin().lock(), &options),
+    };
+    let dot = match dot {
+        Ok(dot) => dot,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    match &options.output {
+        Some(path) => if let Err(e) = fs::write(path, dot) {
+            eprintln!("Unable to write {}: {}", path, e);
+            return ExitCode::FAILURE;
+        },
+        None => print!("{}", dot),
+    }
+    ExitCode::SUCCESS
 }
@@ -1,22 +1,40 @@
 use crate::nodes::graph::Graph;
 use crate::nodes::node::{CompNodeKind, Node, NodeKind};
+use crate::services::operators::{Associativity, BinaryOperator, PREFIX_PRECEDENCE};
 use crate::typ::typ::Typ;
+use std::collections::HashMap;
 use std::fmt::{Display, Formatter};
 use std::ops::Deref;
-use NodeKind::{Add, Comp, Constant, Div, KeepAlive, Minus, Mul, Not, Proj, Return, Scope, Start, Sub};
+use NodeKind::{Abs, Add, AddSat, Call, CallEnd, Cast, Comp, Constant, Div, Fun, If, KeepAlive, Load, Max, Min, Minus, Mul, MulSat, New, NewArray, Not, Offset, Phi, Pow, Print, Proj, Region, Return, Scope, Start, Store, Sub, SubSat};
 
 pub struct BoundNode<'a> {
     node: &'a Node,
     graph: &'a Graph,
+    /// see [BoundNode::graph_form]
+    graph_form: bool,
+    /// see [BoundNode::with_names]
+    names: Option<&'a HashMap<usize, String>>,
 }
 
 impl<'a> BoundNode<'a> {
     pub fn new(node: &'a Node, graph: &'a Graph) -> BoundNode<'a> {
-        BoundNode { node, graph }
+        BoundNode { node, graph, graph_form: false, names: None }
+    }
+
+    /// Renders the control flow as nodes instead of source, e.g. `phi(Region(True, False); 1, 2)` instead of
+    /// `if (arg) 1 else 2`, Regions as `Region(preds)` and Ifs as `if(cond)`. Applies to the operands as well.
+    pub fn graph_form(self) -> BoundNode<'a> {
+        BoundNode { graph_form: true, ..self }
+    }
+
+    /// Renders the nodes that have a name, e.g. because they were bound to a variable, as that name
+    /// instead of their expression. Applies to the operands as well.
+    pub fn with_names(self, names: &'a HashMap<usize, String>) -> BoundNode<'a> {
+        BoundNode { names: Some(names), ..self }
     }
 
     pub fn from(&self, other: &'a Node) -> BoundNode<'a> {
-        Self::new(other, self.graph)
+        BoundNode { node: other, graph: self.graph, graph_form: self.graph_form, names: self.names }
     }
 
     /// returns whether this node is associated with the control flow graph
@@ -24,21 +42,103 @@ impl<'a> BoundNode<'a> {
         match self.node_kind {
             Return
             | Start
-            | Comp { .. }
-            | Not
+            | Print
+            | Fun { .. }
+            | Call { .. }
+            | If
+            | Region
             => true,
             Constant
+            | Comp { .. }
+            | Not
+            | Cast { .. }
             | KeepAlive
             | Add
             | Sub
             | Mul
             | Div
+            | AddSat
+            | SubSat
+            | MulSat
+            | Pow
+            | Abs
+            | Min
+            | Max
             | Minus
             | Scope { .. }
+            | Load { .. }
+            | Store { .. }
+            | New
+            | CallEnd
+            | NewArray
+            | Offset
+            | Phi
             => false,
-            Proj { proj_index, _dbg_proj_label: _ } => proj_index == 0 /*&& matches!(self.graph.get_node(*self.inputs.get(proj_index).unwrap()).unwrap().node_kind, NodeKind::If)*/,
+            Proj { proj_index, _dbg_proj_label: _ } => proj_index == 0 || matches!(self.graph.get_node(*self.inputs.first().unwrap()).unwrap().node_kind, If),
+        }
+    }
+}
+
+impl BoundNode<'_> {
+    /// the default operator this node is parsed from. The lexeme of `&` and `|` depends on the typ.
+    fn binary_operator(&self) -> Option<BinaryOperator> {
+        let lexeme = match (&self.node_kind, self.typ()) {
+            (Add, _) => "+",
+            (Sub, _) => "-",
+            (Mul, _) => "*",
+            (Div, _) => "/",
+            (AddSat, _) => "+|",
+            (SubSat, _) => "-|",
+            (MulSat, _) => "*|",
+            (Pow, _) => "**",
+            (Comp { kind: CompNodeKind::LT }, _) => "<",
+            (Comp { kind: CompNodeKind::LEQ }, _) => "<=",
+            (Comp { kind: CompNodeKind::EQ }, _) => "==",
+            (Comp { kind: CompNodeKind::LogXor }, _) => "^",
+            (Comp { kind: CompNodeKind::LogAnd }, Typ::Int { .. } | Typ::IntTop | Typ::IntBot) => "&",
+            (Comp { kind: CompNodeKind::LogAnd }, Typ::Bool { .. } | Typ::BoolTop | Typ::BoolBot) => "&&",
+            (Comp { kind: CompNodeKind::LogOr }, Typ::Int { .. } | Typ::IntTop | Typ::IntBot) => "|",
+            (Comp { kind: CompNodeKind::LogOr }, Typ::Bool { .. } | Typ::BoolTop | Typ::BoolBot) => "||",
+            _ => return None,
+        };
+        BinaryOperator::default_for(lexeme)
+    }
+
+    fn precedence(&self) -> u8 {
+        match self.node_kind {
+            Minus | Not => PREFIX_PRECEDENCE,
+            // the else branch extends as far as possible
+            Phi => 0,
+            _ => self.binary_operator().map(|operator| operator.precedence).unwrap_or(u8::MAX),
         }
     }
+
+    fn name(&self) -> Option<&String> {
+        self.names.and_then(|names| names.get(&self.nid))
+    }
+
+    /// writes the operand and only wraps it in parens if it binds weaker than `min_precedence`
+    fn fmt_operand(&self, f: &mut Formatter<'_>, operand: &Node, min_precedence: u8) -> std::fmt::Result {
+        let operand = self.from(operand);
+        if operand.precedence() < min_precedence && operand.name().is_none() {
+            write!(f, "({})", operand)
+        } else {
+            write!(f, "{}", operand)
+        }
+    }
+
+    fn fmt_binary(&self, f: &mut Formatter<'_>, separator: &str) -> std::fmt::Result {
+        let operator = self.binary_operator().expect("node is not a binary operator");
+        let node_lhs = self.graph.get_node(*self.inputs.first().unwrap()).unwrap();
+        let node_rhs = self.graph.get_node(*self.inputs.get(1).unwrap()).unwrap();
+        let (lhs_precedence, rhs_precedence) = match operator.associativity {
+            Associativity::Left => (operator.precedence, operator.precedence + 1),
+            Associativity::Right => (operator.precedence + 1, operator.precedence),
+        };
+        self.fmt_operand(f, node_lhs, lhs_precedence)?;
+        write!(f, "{}{}{}", separator, operator.lexeme, separator)?;
+        self.fmt_operand(f, node_rhs, rhs_precedence)
+    }
 }
 
 impl Deref for BoundNode<'_> {
@@ -50,54 +150,33 @@ impl Deref for BoundNode<'_> {
 
 impl Display for BoundNode<'_> {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if let Some(name) = self.name() {
+            return write!(f, "{}", name);
+        }
         match self.node.clone().node_kind {
             Constant => {
                 match self.typ() {
                     Typ::Int { constant } => write!(f, "{}", constant)?,
+                    Typ::Bool { constant } => write!(f, "{}", constant)?,
+                    Typ::Float { constant } => write!(f, "{:?}", constant)?,
                     _ => panic!("Type {:?} for NodeKind::Constant unsupported", self.typ()),
                 }
             }
             Return => {
                 let lhs = self.inputs.get(1).unwrap();
                 let node_lhs = self.graph.get_node(*lhs).unwrap();
-                write!(f, "return {};", format!("{}", self.from(node_lhs)))?
+                write!(f, "return {};", self.from(node_lhs))?
             }
             Start => write!(f, "Start()")?,
             KeepAlive => write!(f, "KeepAlive()")?,
-            Add => {
-                let lhs = self.inputs.get(0).unwrap();
-                let rhs = self.inputs.get(1).unwrap();
-                let node_lhs = self.graph.get_node(*lhs).unwrap();
-                let node_rhs = self.graph.get_node(*rhs).unwrap();
-                write!(f, "({}+{})", format!("{}", self.from(&node_lhs)), format!("{}", self.from(&node_rhs)))?
-            }
-            Sub => {
-                let lhs = self.inputs.get(0).unwrap();
-                let rhs = self.inputs.get(1).unwrap();
-                let node_lhs = self.graph.get_node(*lhs).unwrap();
-                let node_rhs = self.graph.get_node(*rhs).unwrap();
-                write!(f, "({}-{})", format!("{}", self.from(&node_lhs)), format!("{}", self.from(&node_rhs)))?
-            }
-            Mul => {
-                let lhs = self.inputs.get(0).unwrap();
-                let rhs = self.inputs.get(1).unwrap();
-                let node_lhs = self.graph.get_node(*lhs).unwrap();
-                let node_rhs = self.graph.get_node(*rhs).unwrap();
-                write!(f, "({}*{})", format!("{}", self.from(&node_lhs)), format!("{}", self.from(&node_rhs)))?
-            }
-            Div => {
-                let lhs = self.inputs.get(0).unwrap();
-                let rhs = self.inputs.get(1).unwrap();
-                let node_lhs = self.graph.get_node(*lhs).unwrap();
-                let node_rhs = self.graph.get_node(*rhs).unwrap();
-                write!(f, "({}/{})", format!("{}", self.from(&node_lhs)), format!("{}", self.from(&node_rhs)))?
-            }
+            Add | Sub | Mul | Div | AddSat | SubSat | MulSat | Pow => self.fmt_binary(f, "")?,
             Minus => {
-                let lhs = self.inputs.get(0).unwrap();
+                let lhs = self.inputs.first().unwrap();
                 let node_lhs = self.graph.get_node(*lhs).unwrap();
-                write!(f, "(-{})", format!("{}", self.from(&node_lhs)))?
+                write!(f, "-")?;
+                self.fmt_operand(f, node_lhs, PREFIX_PRECEDENCE)?
             }
-            Scope { scopes } => {
+            Scope { scopes, .. } => {
                 write!(f, "Scope(")?;
                 for scope in scopes {
                     let mut entries: Vec<_> = scope.iter().collect();
@@ -111,7 +190,7 @@ impl Display for BoundNode<'_> {
                         }
                         first = false;
                         let node_lhs = self.graph.get_node(*v).unwrap().clone();
-                        write!(f, "{}: {}", k, format!("{}", self.from(&node_lhs)))?;
+                        write!(f, "{}: {}", k, self.from(&node_lhs))?;
                     }
                     write!(f, "]")?;
                 }
@@ -121,40 +200,101 @@ impl Display for BoundNode<'_> {
                 write!(f, "{}", _dbg_proj_label)?
             },
             Comp { kind } => {
-                let lhs = self.inputs.get(0).unwrap();
-                let rhs = self.inputs.get(1).unwrap();
+                if self.binary_operator().is_none() {
+                    write!(f, "Unsupported {:?} comparison Typ", kind)?
+                } else {
+                    self.fmt_binary(f, " ")?
+                }
+            }
+            Not => {
+                let lhs = self.inputs.first().unwrap();
                 let node_lhs = self.graph.get_node(*lhs).unwrap();
-                let node_rhs = self.graph.get_node(*rhs).unwrap();
-                match kind {
-                    CompNodeKind::LT => {
-                        write!(f, "{} < {}", format!("{}", self.from(&node_lhs)), format!("{}", self.from(&node_rhs)))?
-                    }
-                    CompNodeKind::LEQ => {
-                        write!(f, "{} <= {}", format!("{}", self.from(&node_lhs)), format!("{}", self.from(&node_rhs)))?
+                write!(f, "!")?;
+                self.fmt_operand(f, node_lhs, PREFIX_PRECEDENCE)?
+            }
+            Cast { ref to } => {
+                let lhs = self.inputs.first().unwrap();
+                let node_lhs = self.graph.get_node(*lhs).unwrap();
+                match to {
+                    Typ::IntBot => write!(f, "int({})", self.from(node_lhs))?,
+                    Typ::BoolBot => write!(f, "bool({})", self.from(node_lhs))?,
+                    _ => write!(f, "Unsupported Cast Typ")?
+                }
+            }
+            Print => {
+                let value = self.graph.get_node(*self.inputs.get(2).unwrap()).unwrap();
+                write!(f, "print({});", self.from(value))?
+            }
+            Load { ref alias } => {
+                let ptr = self.graph.get_node(*self.inputs.get(1).unwrap()).unwrap();
+                if matches!(ptr.node_kind, Offset) {
+                    write!(f, "{}", self.from(ptr))?
+                } else {
+                    write!(f, "{}.{}", self.from(ptr), alias)?
+                }
+            }
+            NewArray => {
+                let len = self.graph.get_node(*self.inputs.get(1).unwrap()).unwrap();
+                match self.typ() {
+                    Typ::Array { elem, .. } if *elem == Typ::BoolBot => write!(f, "new bool[{}]", self.from(len))?,
+                    _ => write!(f, "new int[{}]", self.from(len))?,
+                }
+            }
+            Offset => {
+                let array = self.graph.get_node(*self.inputs.first().unwrap()).unwrap();
+                let index = self.graph.get_node(*self.inputs.get(1).unwrap()).unwrap();
+                write!(f, "{}[{}]", self.from(array), self.from(index))?
+            }
+            Fun { ref name } => write!(f, "fun {}", name)?,
+            Call { ref name, .. } => {
+                write!(f, "{}(", name)?;
+                for (i, arg) in self.inputs.iter().skip(3).enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
                     }
-                    CompNodeKind::EQ => {
-                        write!(f, "{} == {}", format!("{}", self.from(&node_lhs)), format!("{}", self.from(&node_rhs)))?
-                    },
-                    CompNodeKind::LogAnd => {
-                        match self.typ() {
-                            Typ::Int { .. } | Typ::IntTop | Typ::IntBot => write!(f, "{} & {}", format!("{}", self.from(&node_lhs)), format!("{}", self.from(&node_rhs)))?,
-                            Typ::Bool { .. } | Typ::BoolTop | Typ::BoolBot => write!(f, "{} && {}", format!("{}", self.from(&node_lhs)), format!("{}", self.from(&node_rhs)))?,
-                            _ => write!(f, "Unsupported LogAnd comparison Typ")?
-                        }
-                    },
-                    CompNodeKind::LogOr => {
-                        match self.typ() {
-                            Typ::Int { .. } | Typ::IntTop | Typ::IntBot => write!(f, "{} | {}", format!("{}", self.from(&node_lhs)), format!("{}", self.from(&node_rhs)))?,
-                            Typ::Bool { .. } | Typ::BoolTop | Typ::BoolBot => write!(f, "{} || {}", format!("{}", self.from(&node_lhs)), format!("{}", self.from(&node_rhs)))?,
-                            _ => write!(f, "Unsupported LogOr comparison Typ")?
-                        }
-                    },
-                    CompNodeKind::LogXor => {
-                        write!(f, "{} ^ {}", format!("{}", self.from(&node_lhs)), format!("{}", self.from(&node_rhs)))?
-                    },
+                    write!(f, "{}", self.from(self.graph.get_node(*arg).unwrap()))?;
                 }
+                write!(f, ")")?
+            }
+            Abs => write!(f, "abs({})", self.from(self.graph.get_node(self.inputs[0]).unwrap()))?,
+            Min | Max => {
+                let name = if matches!(self.node_kind, Min) { "min" } else { "max" };
+                let (lhs, rhs) = (self.graph.get_node(self.inputs[0]).unwrap(), self.graph.get_node(self.inputs[1]).unwrap());
+                write!(f, "{}({}, {})", name, self.from(lhs), self.from(rhs))?
+            }
+            CallEnd => write!(f, "{}", self.from(self.graph.get_node(*self.inputs.first().unwrap()).unwrap()))?,
+            New => match self.typ() {
+                Typ::Struct { name, .. } => write!(f, "new {}", name)?,
+                typ => panic!("Type {:?} for NodeKind::New unsupported", typ),
+            },
+            Store { ref alias } => {
+                let value = self.graph.get_node(*self.inputs.get(2).unwrap()).unwrap();
+                write!(f, "store({}, {})", alias, self.from(value))?
+            }
+            If => {
+                let pred = self.graph.get_node(*self.inputs.get(1).unwrap()).unwrap();
+                if self.graph_form {
+                    write!(f, "if({})", self.from(pred))?
+                } else {
+                    write!(f, "if ({})", self.from(pred))?
+                }
+            }
+            Region if self.graph_form => {
+                let preds = self.inputs.iter().map(|&pred| self.from(self.graph.get_node(pred).unwrap()).to_string()).collect::<Vec<_>>();
+                write!(f, "Region({})", preds.join(", "))?
+            }
+            Region => write!(f, "Region")?,
+            Phi if self.graph_form => {
+                let region = self.graph.get_node(*self.inputs.first().unwrap()).unwrap();
+                let values = self.inputs.iter().skip(1).map(|&value| self.from(self.graph.get_node(value).unwrap()).to_string()).collect::<Vec<_>>();
+                write!(f, "phi({}; {})", self.from(region), values.join(", "))?
+            }
+            Phi => {
+                let iff = self.graph.get_node(self.graph.region_if(*self.inputs.first().unwrap()).unwrap()).unwrap();
+                let then = self.graph.get_node(*self.inputs.get(1).unwrap()).unwrap();
+                let otherwise = self.graph.get_node(*self.inputs.get(2).unwrap()).unwrap();
+                write!(f, "{} {} else {}", self.from(iff), self.from(then), self.from(otherwise))?
             }
-            Not => {}
         }
         Ok(())
     }
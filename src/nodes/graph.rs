@@ -1,13 +1,32 @@
 use crate::errors::son_error::SoNError;
-use crate::nodes::node::{Node, NodeKind};
+use crate::nodes::node::{node_icon, Node, NodeKind};
 use crate::typ::typ::Typ;
+use std::collections::{HashMap, HashSet};
 use std::ops::{Deref, DerefMut};
 
+/// the typ of each node and the typs of its inputs by uid, see [Graph::typs_by_uid]
+pub type TypSnapshot = HashMap<usize, (Typ, Vec<Typ>)>;
+
 #[derive(Debug)]
 #[derive(Clone)]
 pub struct Graph {
     _graph: Vec<Option<Node>>,
     _node_id_counter: usize,
+    /// the node of each constant typ. Floats compare by their bits, so -0.0 and 0.0 stay distinct.
+    /// Entries aren't removed when their node is dropped, so they are validated on lookup.
+    constants: HashMap<Typ, usize>,
+    /// nodes that must not be collected even without users, with the number of times each is kept
+    kept: HashMap<usize, usize>,
+    /// empty slots to reuse, the most recently freed last. Slots emptied without [Graph::remove_node] aren't listed.
+    free: Vec<usize>,
+    /// see [crate::services::parser::ParserOptions::disabled_folds]
+    pub(crate) disabled_folds: Vec<NodeKind>,
+    /// the [Node::span] of nodes created from now on
+    pub(crate) span: (usize, usize),
+    /// see [crate::services::parser::ParserOptions::max_nodes]
+    pub(crate) max_nodes: usize,
+    /// live nodes added by [Graph::new_node] and not removed by [Graph::remove_node]. Slots emptied otherwise are still counted.
+    live: usize,
 }
 
 impl Deref for Graph {
@@ -23,20 +42,50 @@ impl DerefMut for Graph {
     }
 }
 
+impl Default for Graph {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Graph {
     pub fn from(g: Vec<Option<Node>>) -> Graph {
-        Graph { _graph: g, _node_id_counter: 0 }
+        let free = g.iter().enumerate().rev().filter(|(_, x)| x.is_none()).map(|(i, _)| i).collect();
+        let live = g.iter().flatten().count();
+        Graph { _graph: g, _node_id_counter: 0, constants: HashMap::new(), kept: HashMap::new(), free, disabled_folds: vec![], span: (0, 0), max_nodes: usize::MAX, live }
     }
 
     pub fn new() -> Graph {
         Self::from(vec![])
     }
 
+    /// Constants are interned, so every use of the same constant shares one node.
     pub fn new_node(&mut self, inputs: Vec<usize>, node_kind: NodeKind, typ: Typ) -> Result<usize, SoNError> {
         assert_eq!(node_kind.arity(), inputs.len());
+        let constant_key = (matches!(node_kind, NodeKind::Constant) && typ.is_constant()).then(|| typ.clone());
+        if let Some(key) = &constant_key
+            && let Some(&nid) = self.constants.get(key)
+            && matches!(self.get(nid), Some(Some(node)) if matches!(node.node_kind, NodeKind::Constant) && node.typ() == *key) {
+            return Ok(nid);
+        }
+        if self.live >= self.max_nodes {
+            // recount, as slots may have been emptied without remove_node
+            self.live = self.live_count();
+            if self.live >= self.max_nodes {
+                return Err(SoNError::GraphSizeLimitExceeded { max_nodes: self.max_nodes });
+            }
+        }
         let index = self.find_first_empty_cell();
+        if let Err(e) = Self::check_self_reference(index, &node_kind, &inputs) {
+            // give the slot back
+            if index < self.len() {
+                self.free.push(index);
+            }
+            return Err(e);
+        }
 
-        let node = Node::new(node_kind, self._node_id_counter, index, typ);
+        let mut node = Node::new(node_kind, self._node_id_counter, index, typ);
+        node.span = self.span;
         self._node_id_counter += 1;
         let inputs_c = inputs.clone();
         self.add_reverse_dependencies_br(index, &inputs_c)?;
@@ -44,6 +93,7 @@ impl Graph {
             self.push(None);
         }
         self[index] = Some(node.clone());
+        self.live += 1;
         self.add_dependencies_br(index, &inputs_c)?;
 
         // refine the node typ immediately. This sets the refined typ but doesn't optimize anything.
@@ -51,6 +101,9 @@ impl Graph {
         let typ = self.compute_refined_typ(n)?;
         self.get_node_mut(index)?.refine_typ(typ)?;
 
+        if let Some(key) = constant_key {
+            self.constants.insert(key, index);
+        }
         Ok(index)
     }
 
@@ -64,6 +117,32 @@ impl Graph {
         self.iter_mut().filter_map(|x| x.as_ref())
     }
 
+    /// nids of all existing nodes in increasing order
+    pub fn live_nids(&self) -> Vec<usize> {
+        self.graph_iter().map(|n| n.nid).collect()
+    }
+
+    /// number of existing nodes. In contrast to len() this doesn't count empty cells.
+    pub fn live_count(&self) -> usize {
+        self.graph_iter().count()
+    }
+
+    /// Dumps every slot on its own line, empty ones as `<free>` and live ones like [crate::services::parser::Parser::dump_ir]
+    /// with their uid, e.g. `3: #1() uid=5 : int(1)`. Shows the layout when debugging the GC or the free list.
+    pub fn dump_slots(&self) -> String {
+        let mut sb = String::new();
+        for (index, slot) in self.iter().enumerate() {
+            match slot {
+                None => sb.push_str(&format!("{}: <free>\n", index)),
+                Some(n) => {
+                    let inputs = n.inputs.iter().map(|i| format!("%{}", i)).collect::<Vec<_>>().join(", ");
+                    sb.push_str(&format!("{}: {}({}) uid={} : {}\n", index, node_icon(n), inputs, n.uid, n.typ()));
+                }
+            }
+        }
+        sb
+    }
+
     /// remove dependency dep_nid from nid so nid doesn't depend on dep_nid anymore.
     pub fn remove_dependency_br(&mut self, nid: usize, dep_nid: usize) -> Result<(), SoNError> {
         if !self.node_exists(nid) || !self.node_exists(nid) {
@@ -81,6 +160,121 @@ impl Graph {
         Ok(())
     }
 
+    /// keeps nid alive until it is unkept as often as it was kept
+    pub fn keep(&mut self, nid: usize) -> Result<(), SoNError> {
+        if !self.node_exists(nid) {
+            return Err(SoNError::NodeIdNotExisting);
+        }
+        *self.kept.entry(nid).or_default() += 1;
+        Ok(())
+    }
+
+    /// undoes one [Graph::keep] of nid. Unkeeping a node that isn't kept does nothing.
+    pub fn unkeep(&mut self, nid: usize) -> Result<(), SoNError> {
+        if !self.node_exists(nid) {
+            return Err(SoNError::NodeIdNotExisting);
+        }
+        if let Some(count) = self.kept.get_mut(&nid) {
+            *count -= 1;
+            if *count == 0 {
+                self.kept.remove(&nid);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn is_kept(&self, nid: usize) -> bool {
+        self.kept.contains_key(&nid)
+    }
+
+    /// a node can't be its own input, except a Phi merging its own value around a loop
+    fn check_self_reference(nid: usize, kind: &NodeKind, inputs: &[usize]) -> Result<(), SoNError> {
+        if !matches!(kind, NodeKind::Phi) && inputs.contains(&nid) {
+            return Err(SoNError::SelfReference { nid });
+        }
+        Ok(())
+    }
+
+    /// replaces the input at `index` of nid and moves the usage from the old input to the new one
+    pub fn set_input(&mut self, nid: usize, index: usize, input: usize) -> Result<(), SoNError> {
+        Self::check_self_reference(nid, &self.get_node(nid)?.node_kind, &[input])?;
+        let old = std::mem::replace(&mut self.get_node_mut(nid)?.inputs[index], input);
+        let old_node = self.get_node_mut(old)?;
+        if let Some(pos) = old_node.outputs.iter().position(|&x| x == nid) {
+            old_node.outputs.remove(pos);
+        }
+        self.get_node_mut(input)?.outputs.push(nid);
+        Ok(())
+    }
+
+    /// Turns nid into a different node without touching its users. Old inputs lose the usage and new inputs gain it.
+    /// The typ only moves the way [Node::refine_typ] allows.
+    pub fn replace_node(&mut self, nid: usize, new_kind: NodeKind, new_inputs: Vec<usize>, new_typ: Typ) -> Result<(), SoNError> {
+        if !new_inputs.iter().all(|&input| self.node_exists(input)) {
+            return Err(SoNError::NodeIdNotExisting);
+        }
+        Self::check_self_reference(nid, &new_kind, &new_inputs)?;
+        let node = self.get_node_mut(nid)?;
+        node.refine_typ(new_typ)?;
+        node.node_kind = new_kind;
+        let old_inputs = std::mem::replace(&mut node.inputs, new_inputs.clone());
+        for old in old_inputs {
+            let old_node = self.get_node_mut(old)?;
+            if let Some(pos) = old_node.outputs.iter().position(|&x| x == nid) {
+                old_node.outputs.remove(pos);
+            }
+        }
+        self.add_reverse_dependencies_br(nid, &new_inputs)
+    }
+
+    /// the If whose branches the Region merges. It is found by walking up the control of the true branch,
+    /// skipping over the Ifs of nested Regions.
+    pub fn region_if(&self, region: usize) -> Result<usize, SoNError> {
+        let mut ctrl = self.inputs_of(region)?[0];
+        loop {
+            let node = self.get_node(ctrl)?;
+            match node.node_kind {
+                NodeKind::Proj { .. } if matches!(self.get_node(node.inputs[0])?.node_kind, NodeKind::If) => return Ok(node.inputs[0]),
+                NodeKind::Region => ctrl = self.get_node(self.region_if(ctrl)?)?.inputs[0],
+                _ => ctrl = node.inputs[0],
+            }
+        }
+    }
+
+    /// Rewires the control nodes of the taken branch of every If with a constant condition to the control before the If,
+    /// so the If and its projections can be dropped. The Region merging the branches folds in the peephole, but it can't
+    /// rewire the users of the projection while their own peepholes are still running. Returns the number of rewired inputs.
+    pub fn bypass_constant_ifs(&mut self) -> Result<usize, SoNError> {
+        let mut rewired = 0;
+        for nid in self.live_nids() {
+            let iff = self.get_node(nid)?;
+            if !matches!(iff.node_kind, NodeKind::If) {
+                continue;
+            }
+            let Some(taken) = self.get_node(iff.inputs[1])?.typ().constant_condition() else {
+                continue;
+            };
+            let ctrl = iff.inputs[0];
+            let taken_index = if taken { 0 } else { 1 };
+            let Some(&proj) = iff.outputs.iter().find(|&&output| matches!(self.get_node(output).map(|n| &n.node_kind), Ok(NodeKind::Proj { proj_index, .. }) if *proj_index == taken_index)) else {
+                continue;
+            };
+            for user in self.users_of(proj)?.to_vec() {
+                // a Region still merging the branches hasn't been folded, and the Scope is updated by the parser
+                if matches!(self.get_node(user)?.node_kind, NodeKind::Region | NodeKind::Scope { .. }) {
+                    continue;
+                }
+                for index in 0..self.inputs_of(user)?.len() {
+                    if self.inputs_of(user)?[index] == proj {
+                        self.set_input(user, index, ctrl)?;
+                        rewired += 1;
+                    }
+                }
+            }
+        }
+        Ok(rewired)
+    }
+
     /// make the usages for all nodes in deps to point to nid
     pub fn add_reverse_dependencies_br(&mut self, nid: usize, deps: &Vec<usize>) -> Result<(), SoNError> {
         for id in deps {
@@ -99,6 +293,7 @@ impl Graph {
     pub fn add_dependencies_br(&mut self, nid: usize, deps: &Vec<usize>) -> Result<(), SoNError> {
         match self.get_mut(nid) {
             Some(Some(node)) => {
+                Self::check_self_reference(nid, &node.node_kind, deps)?;
                 node.inputs.extend(deps);
                 // node.inputs = node.inputs.clone().into_iter().unique().collect();
             }
@@ -107,15 +302,24 @@ impl Graph {
         Ok(())
     }
 
+    /// the most recently freed slot, or a new one at the end
     pub fn find_first_empty_cell(&mut self) -> usize {
-        let index = self.iter().enumerate().find_map(|(i, x)| {
-            if x.is_none() {
-                Some(i)
-            } else {
-                None
+        while let Some(index) = self.free.pop() {
+            if matches!(self.get(index), Some(None)) {
+                return index;
             }
-        }).unwrap_or_else(|| self.len());
-        index
+        }
+        self.len()
+    }
+
+    /// empties the slot of nid, so it can be reused. The edges of its neighbours aren't touched.
+    pub fn remove_node(&mut self, nid: usize) -> Option<Node> {
+        let node = self.get_mut(nid)?.take();
+        if node.is_some() {
+            self.free.push(nid);
+            self.live = self.live.saturating_sub(1);
+        }
+        node
     }
 
     pub fn get_node_mut(&mut self, nid: usize) -> Result<&mut Node, SoNError> {
@@ -130,10 +334,173 @@ impl Graph {
             .ok_or(SoNError::NodeIdNotExisting)
     }
 
+    /// the inputs of the live node nid
+    pub fn inputs_of(&self, nid: usize) -> Result<&[usize], SoNError> {
+        Ok(&self.get_node(nid)?.inputs)
+    }
+
+    /// the users of the live node nid, once for each input they use it as
+    pub fn outputs_of(&self, nid: usize) -> Result<&[usize], SoNError> {
+        Ok(&self.get_node(nid)?.outputs)
+    }
+
+    /// alias of [Graph::outputs_of]
+    pub fn users_of(&self, nid: usize) -> Result<&[usize], SoNError> {
+        self.outputs_of(nid)
+    }
+
     pub fn node_exists(&self, nid: usize) -> bool {
         self.get_node(nid).is_ok()
     }
 
+    /// checks that the node nid and the node other_nid of the other graph compute the same thing,
+    /// i.e. they have the same kind, typ and structurally equal inputs. Node ids may differ.
+    pub fn structurally_equal(&self, nid: usize, other: &Graph, other_nid: usize) -> bool {
+        self.structurally_equal_visited(nid, other, other_nid, &mut HashSet::new())
+    }
+
+    /// [Graph::structurally_equal] that compares each pair of nodes once. A pair that is visited again is assumed equal,
+    /// since a difference fails the whole comparison anyway. This keeps shared inputs linear and ends at back edges.
+    fn structurally_equal_visited(&self, nid: usize, other: &Graph, other_nid: usize, visited: &mut HashSet<(usize, usize)>) -> bool {
+        if !visited.insert((nid, other_nid)) {
+            return true;
+        }
+        let (Ok(node), Ok(other_node)) = (self.get_node(nid), other.get_node(other_nid)) else {
+            return false;
+        };
+        node.node_kind == other_node.node_kind
+            && node.typ() == other_node.typ()
+            && node.inputs.len() == other_node.inputs.len()
+            && node.inputs.iter().zip(other_node.inputs.iter())
+                .all(|(&i, &j)| self.structurally_equal_visited(i, other, j, visited))
+    }
+
+    /// nids reachable from root via inputs in reverse postorder, i.e. every node comes before its inputs.
+    /// Shared inputs are only listed once.
+    pub fn reverse_postorder(&self, root: usize) -> Vec<usize> {
+        let mut visited = HashSet::from([root]);
+        let mut postorder = vec![];
+        // (nid, index of the next input to visit)
+        let mut stack = vec![(root, 0)];
+        while let Some((nid, i)) = stack.pop() {
+            let inputs = &self.get_node(nid).expect("inputs of live nodes are live").inputs;
+            if let Some(&input) = inputs.get(i) {
+                stack.push((nid, i + 1));
+                if visited.insert(input) {
+                    stack.push((input, 0));
+                }
+            } else {
+                postorder.push(nid);
+            }
+        }
+        postorder.reverse();
+        postorder
+    }
+
+    /// Replaces every node whose refined typ is constant with a Constant node of that typ, until nothing changes.
+    /// Replaced nodes are removed. Nodes the parser still holds on to through a Scope or by keeping them are left alone.
+    /// Returns the number of folded nodes.
+    pub fn fold_constants(&mut self) -> usize {
+        let mut folded = 0;
+        loop {
+            let before = folded;
+            for nid in self.live_nids() {
+                let Ok(node) = self.get_node(nid) else {
+                    continue;
+                };
+                if matches!(node.node_kind, NodeKind::Constant) || self.is_kept(nid)
+                    || node.outputs.iter().any(|&o| matches!(self.get_node(o).map(|o| &o.node_kind), Ok(NodeKind::Scope { .. }))) {
+                    continue;
+                }
+                let Ok(typ) = self.compute_refined_typ(node) else {
+                    continue;
+                };
+                if !typ.is_constant() || typ == Typ::Top || !node.typ().transition_allowed(&typ) {
+                    continue;
+                }
+                // the constant stands for the folded node in the source
+                let node_span = node.span;
+                let span = std::mem::replace(&mut self.span, node_span);
+                let constant = self.new_node(vec![], NodeKind::Constant, typ);
+                self.span = span;
+                let Ok(constant) = constant else {
+                    continue;
+                };
+                let node = self.remove_node(nid).expect("node is live");
+                for user in node.outputs.iter().collect::<HashSet<_>>() {
+                    let user = self.get_node_mut(*user).expect("users are live");
+                    for input in user.inputs.iter_mut().filter(|input| **input == nid) {
+                        *input = constant;
+                    }
+                }
+                self.get_node_mut(constant).expect("constant is live").outputs.extend(&node.outputs);
+                for input in node.inputs {
+                    let input = self.get_node_mut(input).expect("inputs are live");
+                    if let Some(pos) = input.outputs.iter().position(|&x| x == nid) {
+                        input.outputs.remove(pos);
+                    }
+                }
+                folded += 1;
+            }
+            if folded == before {
+                return folded;
+            }
+        }
+    }
+
+    /// The typ of every live node and the typs of its inputs by its uid, which unlike the nid isn't reused.
+    /// See [Graph::verify_monotonic].
+    pub fn typs_by_uid(&self) -> TypSnapshot {
+        self.graph_iter()
+            .map(|n| (n.uid, (n.typ(), n.inputs.iter().filter_map(|&input| self.get_node(input).ok()).map(Node::typ).collect())))
+            .collect()
+    }
+
+    /// Checks that every node that is still live only moved its typ upwards since `before` was taken with
+    /// [Graph::typs_by_uid]. So do the typs its inputs provide, which catches a use rewired to a less precise node.
+    pub fn verify_monotonic(&self, before: &TypSnapshot) -> Result<(), SoNError> {
+        for node in self.graph_iter() {
+            let Some((old, old_inputs)) = before.get(&node.uid) else {
+                continue;
+            };
+            if !old.transition_allowed(&node.typ()) {
+                return Err(SoNError::TypTransitionNotAllowed);
+            }
+            if old_inputs.len() == node.inputs.len() {
+                for (old_input, &input) in old_inputs.iter().zip(&node.inputs) {
+                    if !old_input.transition_allowed(&self.get_node(input)?.typ()) {
+                        return Err(SoNError::TypTransitionNotAllowed);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that the control of every Return leads back to the Start, or to the entry of its function,
+    /// by walking control edges only. Fails with the first Return that doesn't.
+    pub fn verify_control(&self) -> Result<(), SoNError> {
+        for ret in self.graph_iter().filter(|n| matches!(n.node_kind, NodeKind::Return)) {
+            let mut visited = HashSet::new();
+            let mut work = vec![ret.inputs.first().copied().ok_or(SoNError::ControlDisconnected { nid: ret.nid })?];
+            while let Some(ctrl) = work.pop() {
+                if !visited.insert(ctrl) {
+                    continue;
+                }
+                let node = self.get_node(ctrl)?;
+                match &node.node_kind {
+                    NodeKind::Start | NodeKind::Fun { .. } => {}
+                    NodeKind::Proj { proj_index: 0, .. } => work.push(node.inputs[0]),
+                    NodeKind::Proj { .. } if matches!(self.get_node(node.inputs[0])?.node_kind, NodeKind::If) => work.push(node.inputs[0]),
+                    NodeKind::If | NodeKind::Print | NodeKind::Call { .. } => work.push(node.inputs[0]),
+                    NodeKind::Region => work.extend(&node.inputs),
+                    _ => return Err(SoNError::ControlDisconnected { nid: ret.nid }),
+                }
+            }
+        }
+        Ok(())
+    }
+
     /// checks that the node in slot nid exists and that the unique id matches
     pub fn node_exists_unique(&self, nid: usize, uid: usize) -> bool {
         self.get_node(nid).is_ok_and(|x| x.uid == uid)
@@ -1,9 +1,9 @@
 use crate::errors::son_error::SoNError;
 use crate::nodes::bound_node::BoundNode;
 pub(crate) use crate::nodes::graph::Graph;
-use crate::nodes::node::NodeKind::{Add, Comp, Constant, Div, KeepAlive, Minus, Mul, Proj, Return, Scope, Start, Sub};
+use crate::nodes::node::NodeKind::{Abs, Add, AddSat, Call, CallEnd, Cast, Comp, Constant, Div, Fun, If, KeepAlive, Load, Max, Min, Minus, Mul, MulSat, New, NewArray, Offset, Phi, Pow, Print, Proj, Region, Return, Scope, Start, Store, Sub, SubSat};
 use crate::typ::typ::Typ;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use NodeKind::Not;
 
 #[derive(Debug)]
@@ -17,6 +17,8 @@ pub struct Node {
     pub uid: usize,
     pub nid: usize,
     typ: Typ,
+    /// byte range of the source token the node was created for. Empty for nodes not created by the parser.
+    pub span: (usize, usize),
 }
 
 impl PartialEq for Node {
@@ -25,21 +27,60 @@ impl PartialEq for Node {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum NodeKind {
     Constant,
     Return,
     Start,
+    /// reserves the first slot. Nodes kept alive are counted by [Graph::keep], not by edges to it.
     KeepAlive,
     Add,
     Sub,
     Mul,
     Div,
+    /// like Add, Sub and Mul, but ints clamp at their bounds instead of wrapping around
+    AddSat,
+    SubSat,
+    MulSat,
+    /// `lhs ** rhs`. For ints the exponent must not be negative and the result must not overflow.
+    Pow,
+    /// the intrinsics `abs(x)`, `min(a, b)` and `max(a, b)`. `abs` of the smallest int is an IntegerOverflow.
+    Abs,
+    Min,
+    Max,
     Minus,
-    Scope { scopes: Vec<HashMap<String, usize>> },
+    /// the declared typ of a variable is IntBot or BoolBot. `consts` are the variables of each scope that can't be assigned.
+    Scope { scopes: Vec<HashMap<String, usize>>, declared_typs: Vec<HashMap<String, Typ>>, consts: Vec<HashSet<String>> },
     Proj { proj_index: usize, _dbg_proj_label: String },
     Comp { kind: CompNodeKind },
     Not,
+    /// converts between int and bool. `to` is IntBot or BoolBot.
+    Cast { to: Typ },
+    /// `print(value);` takes control, memory and the value and yields the new control and memory
+    Print,
+    /// allocates an instance of a struct. The input is control and the typ is the Struct.
+    New,
+    /// allocates an array. Inputs are control and the length. The typ is the Array.
+    NewArray,
+    /// address of an array element. Inputs are the array and the index. The typ is the Array.
+    Offset,
+    /// entry of the function `name`. Like Start, it projects control, memory and the parameters.
+    Fun { name: String },
+    /// calls `name` with `argc` arguments. Inputs are control, memory, the entry of the callee and the arguments.
+    /// Like a print, it yields the new control and memory.
+    Call { name: String, argc: usize },
+    /// the value returned by the call that is its input
+    CallEnd,
+    /// reads `alias` from memory. Inputs are memory and the pointer.
+    Load { alias: String },
+    /// writes `alias` to memory and yields the new memory. Inputs are memory, the pointer and the value.
+    Store { alias: String },
+    /// branches on the predicate. Inputs are control and the predicate. It projects the control of the true and the false branch.
+    If,
+    /// merges the control of the true and the false branch of an If, in this order
+    Region,
+    /// selects the value of the branch that was taken. Inputs are the Region and the value of each of its branches.
+    Phi,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -55,16 +96,18 @@ pub enum CompNodeKind {
 impl NodeKind {
     pub fn arity(&self) -> usize {
         match self {
-            Start | KeepAlive | Scope { .. } | Constant => 0,
-            Minus | Proj { .. } | Not => 1,
-            Return | Add | Sub | Mul | Div | Comp { .. } => 2,
+            Start | KeepAlive | Scope { .. } | Constant | Fun { .. } => 0,
+            Minus | Abs | Proj { .. } | Not | Cast { .. } | New | CallEnd => 1,
+            Return | Add | Sub | Mul | Div | AddSat | SubSat | MulSat | Pow | Min | Max | Comp { .. } | Load { .. } | NewArray | Offset | If | Region => 2,
+            Print | Store { .. } | Phi => 3,
+            Call { argc, .. } => 3 + argc,
         }
     }
 }
 
 impl Node {
     pub fn new(node_kind: NodeKind, uid: usize, nid: usize, typ: Typ) -> Node {
-        Node { node_kind, inputs: vec![], outputs: vec![], uid, nid, typ }
+        Node { node_kind, inputs: vec![], outputs: vec![], uid, nid, typ, span: (0, 0) }
     }
 
     pub fn typ(&self) -> Typ {
@@ -76,19 +119,82 @@ impl Node {
         if !self.typ.transition_allowed(&typ) {
             return Err(SoNError::TypTransitionNotAllowed);
         }
-        println!("Node {:?} ({:?}) node_kind: {:?}, typ: {:?} -> {:?}", self.nid, self.uid, self.node_kind, self.typ, typ);
         self.typ = typ;
         Ok(())
     }
 
     pub fn bind<'a>(&'a self, graph: &'a Graph) -> BoundNode<'a> {
-        BoundNode::new(&self, &graph)
+        BoundNode::new(self, graph)
+    }
+}
+
+/// the short label of a node in the dotfile, the IR dump and the slot dump, e.g. `+` or `#1`
+pub(crate) fn node_icon(node: &Node) -> String {
+    match node.node_kind {
+        NodeKind::Constant => {
+            match node.typ() {
+                Typ::Int { constant } => format!("#{}", constant),
+                Typ::Bool { constant } => format!("#{}", constant),
+                Typ::Float { constant } => format!("#{:?}", constant),
+                _ => panic!("Type {:?} for NodeKind::Constant unsupported", node.typ()),
+            }
+        }
+        NodeKind::Return => "Return".into(),
+        NodeKind::Start => "Start".into(),
+        NodeKind::KeepAlive => "KeepAlive".into(),
+        NodeKind::Add => "+".into(),
+        NodeKind::Sub => "-".into(),
+        NodeKind::Mul => "*".into(),
+        NodeKind::Div => "/".into(),
+        NodeKind::AddSat => "+|".into(),
+        NodeKind::SubSat => "-|".into(),
+        NodeKind::MulSat => "*|".into(),
+        NodeKind::Pow => "**".into(),
+        // unary, unlike the Sub
+        NodeKind::Minus => "neg".into(),
+        NodeKind::Abs => "abs".into(),
+        NodeKind::Min => "min".into(),
+        NodeKind::Max => "max".into(),
+        NodeKind::Scope { .. } => "Scope".into(),
+        NodeKind::Proj { ref _dbg_proj_label, .. } => _dbg_proj_label.into(),
+        NodeKind::Comp { ref kind } => match (kind, node.typ().is_bool()) {
+            (CompNodeKind::LT, _) => "<",
+            (CompNodeKind::LEQ, _) => "<=",
+            (CompNodeKind::EQ, _) => "==",
+            (CompNodeKind::LogAnd, true) => "&&",
+            (CompNodeKind::LogAnd, false) => "&",
+            (CompNodeKind::LogOr, true) => "||",
+            (CompNodeKind::LogOr, false) => "|",
+            (CompNodeKind::LogXor, _) => "^",
+        }.into(),
+        NodeKind::Not => "Not".into(),
+        NodeKind::Cast { ref to } => match to {
+            Typ::IntBot => "(int)".into(),
+            Typ::BoolBot => "(bool)".into(),
+            _ => panic!("Type {:?} for NodeKind::Cast unsupported", to),
+        },
+        NodeKind::Print => "Print".into(),
+        NodeKind::Fun { ref name } => format!("fun {}", name),
+        NodeKind::Call { ref name, .. } => format!("call {}", name),
+        NodeKind::CallEnd => "CallEnd".into(),
+        NodeKind::NewArray => "new []".into(),
+        NodeKind::Offset => "[]".into(),
+        NodeKind::New => match node.typ() {
+            Typ::Struct { name, .. } => format!("new {}", name),
+            typ => panic!("Type {:?} for NodeKind::New unsupported", typ),
+        },
+        NodeKind::Load { ref alias } => format!("ld {}", alias),
+        NodeKind::Store { ref alias } => format!("st {}", alias),
+        NodeKind::If => "If".into(),
+        NodeKind::Region => "Region".into(),
+        NodeKind::Phi => "Phi".into(),
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::services::parser::{Parser, ParserOptions, FIRST_ARG_PROJ_INDEX, START_NID};
 
     #[test]
     fn should_construct_constant_node() {
@@ -115,6 +221,21 @@ mod tests {
         assert!(matches!(graph.get(nid1).unwrap().as_ref().unwrap().node_kind, Constant));
     }
 
+    #[test]
+    fn should_recreate_dropped_constant() {
+        // Arrange
+        let mut graph = Graph::new();
+        let nid1 = graph.new_node(vec![], Constant, Typ::Int { constant: 42 }).unwrap();
+        graph[nid1] = None;
+        graph.new_node(vec![], Constant, Typ::Bool { constant: true }).unwrap();
+
+        // Act
+        let nid2 = graph.new_node(vec![], Constant, Typ::Int { constant: 42 }).unwrap();
+
+        // Assert
+        assert!(matches!(graph.get_node(nid2).unwrap().typ, Typ::Int { constant: 42 }));
+    }
+
     #[test]
     fn should_be_able_to_contain_same_dependency_multiple_times() {
         // Arrange
@@ -130,6 +251,220 @@ mod tests {
         assert_eq!(2, graph_br.get_node(nid1).unwrap().outputs.len());
     }
 
+    #[test]
+    fn should_list_all_users_of_shared_constant() {
+        // Arrange
+        let mut graph = Graph::new();
+        let x = graph.new_node(vec![], Constant, Typ::Bot).unwrap();
+        let two = graph.new_node(vec![], Constant, Typ::Int { constant: 2 }).unwrap();
+        let add = graph.new_node(vec![x, two], Add, Typ::Bot).unwrap();
+        let mul = graph.new_node(vec![two, x], Mul, Typ::Bot).unwrap();
+        let shared = graph.new_node(vec![], Constant, Typ::Int { constant: 2 }).unwrap();
+
+        // Act
+        let users = graph.outputs_of(shared).unwrap();
+
+        // Assert
+        assert_eq!(two, shared);
+        assert_eq!(&[add, mul], users);
+        assert_eq!(users, graph.users_of(two).unwrap());
+        assert_eq!(&[two, x], graph.inputs_of(mul).unwrap());
+        assert!(matches!(graph.outputs_of(99), Err(SoNError::NodeIdNotExisting)));
+    }
+
+    #[test]
+    fn should_dump_freed_slot() {
+        // Arrange
+        let mut graph = Graph::new();
+        let one = graph.new_node(vec![], Constant, Typ::Int { constant: 1 }).unwrap();
+        let two = graph.new_node(vec![], Constant, Typ::Int { constant: 2 }).unwrap();
+        let minus = graph.new_node(vec![one], Minus, Typ::Bot).unwrap();
+
+        // Act
+        graph.remove_node(two);
+        let dump = graph.dump_slots();
+
+        // Assert
+        let expected = format!("{}: #1() uid=0 : int(1)\n{}: <free>\n{}: neg(%{}) uid=2 : int(-1)\n", one, two, minus, one);
+        assert_eq!(expected, dump);
+    }
+
+    #[test]
+    fn should_reject_add_with_itself_as_input() {
+        // Arrange
+        let mut graph = Graph::new();
+        let x = graph.new_node(vec![], Constant, Typ::Bot).unwrap();
+        let freed = graph.new_node(vec![], Constant, Typ::Bot).unwrap();
+        let add = graph.new_node(vec![x, x], Add, Typ::Bot).unwrap();
+        graph.remove_node(freed);
+
+        // Act
+        let reused = graph.new_node(vec![x, freed], Add, Typ::Bot);
+        let set = graph.set_input(add, 1, add);
+
+        // Assert
+        assert!(matches!(reused, Err(SoNError::SelfReference { nid }) if nid == freed));
+        assert!(matches!(set, Err(SoNError::SelfReference { nid }) if nid == add));
+        assert_eq!(vec![x, x], graph.inputs_of(add).unwrap());
+        assert_eq!(freed, graph.find_first_empty_cell());
+    }
+
+    #[test]
+    fn should_replace_add_with_mul_in_place() {
+        // Arrange
+        let mut graph = Graph::new();
+        let x = graph.new_node(vec![], Constant, Typ::Bot).unwrap();
+        let y = graph.new_node(vec![], Constant, Typ::Bot).unwrap();
+        let add = graph.new_node(vec![x, y], Add, Typ::Bot).unwrap();
+        let user = graph.new_node(vec![add], Minus, Typ::Bot).unwrap();
+        let two = graph.new_node(vec![], Constant, Typ::Int { constant: 2 }).unwrap();
+
+        // Act
+        graph.replace_node(add, Mul, vec![x, two], Typ::IntBot).unwrap();
+
+        // Assert
+        let mul = graph.get_node(add).unwrap();
+        assert!(matches!(mul.node_kind, Mul));
+        assert_eq!(vec![x, two], mul.inputs);
+        assert_eq!(vec![user], mul.outputs);
+        assert_eq!(vec![add], graph.get_node(x).unwrap().outputs);
+        assert!(graph.get_node(y).unwrap().outputs.is_empty());
+        assert_eq!(vec![add], graph.get_node(two).unwrap().outputs);
+    }
+
+    #[test]
+    fn should_reject_replacement_moving_typ_down() {
+        // Arrange
+        let mut graph = Graph::new();
+        let x = graph.new_node(vec![], Constant, Typ::Int { constant: 1 }).unwrap();
+        let minus = graph.new_node(vec![x], Minus, Typ::Int { constant: -1 }).unwrap();
+
+        // Act
+        let result = graph.replace_node(minus, Not, vec![x], Typ::Bot);
+
+        // Assert
+        assert!(matches!(result, Err(SoNError::TypTransitionNotAllowed)));
+        assert!(matches!(graph.get_node(minus).unwrap().node_kind, Minus));
+    }
+
+    #[test]
+    fn should_fold_add_of_constants() {
+        // Arrange
+        let mut graph = Graph::new();
+        let start = graph.new_node(vec![], Start, Typ::Tuple { typs: vec![Typ::Ctrl] }).unwrap();
+        let ctrl = graph.new_node(vec![start], Proj { proj_index: 0, _dbg_proj_label: "$ctrl".into() }, Typ::Bot).unwrap();
+        let one = graph.new_node(vec![], Constant, Typ::Int { constant: 1 }).unwrap();
+        let two = graph.new_node(vec![], Constant, Typ::Int { constant: 2 }).unwrap();
+        let add = graph.new_node(vec![one, two], Add, Typ::Bot).unwrap();
+        let ret = graph.new_node(vec![ctrl, add], Return, Typ::Bot).unwrap();
+
+        // Act
+        let folded = graph.fold_constants();
+
+        // Assert
+        assert_eq!(1, folded);
+        assert!(!graph.node_exists(add));
+        let constant = graph.get_node(graph.get_node(ret).unwrap().inputs[1]).unwrap();
+        assert!(matches!(constant.node_kind, Constant));
+        assert!(matches!(constant.typ(), Typ::Int { constant: 3 }));
+        assert_eq!(vec![ret], constant.outputs);
+        assert!(graph.get_node(one).unwrap().outputs.is_empty());
+        assert!(graph.get_node(two).unwrap().outputs.is_empty());
+    }
+
+    #[test]
+    fn should_reject_use_rewired_to_less_precise_input() {
+        // Arrange
+        let mut graph = Graph::new();
+        let five = graph.new_node(vec![], Constant, Typ::Int { constant: 5 }).unwrap();
+        let unknown = graph.new_node(vec![], Constant, Typ::IntBot).unwrap();
+        let minus = graph.new_node(vec![five], Minus, Typ::Bot).unwrap();
+        let before = graph.typs_by_uid();
+
+        // Act
+        graph.set_input(minus, 0, unknown).unwrap();
+        let result = graph.verify_monotonic(&before);
+
+        // Assert
+        assert!(matches!(result, Err(SoNError::TypTransitionNotAllowed)));
+        assert!(graph.verify_monotonic(&graph.typs_by_uid()).is_ok());
+    }
+
+    #[test]
+    fn should_verify_control_of_parsed_program() {
+        // Arrange
+        let mut parser = Parser::new_noarg("int a=arg; print(a); return if(a<1) 2 else a;").unwrap();
+        parser.parse().unwrap();
+
+        // Act
+        let result = parser.graph.verify_control();
+
+        // Assert
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn should_reject_return_without_control() {
+        // Arrange
+        let mut graph = Graph::new();
+        graph.new_node(vec![], Start, Typ::Tuple { typs: vec![Typ::Ctrl] }).unwrap();
+        let value = graph.new_node(vec![], Constant, Typ::Int { constant: 1 }).unwrap();
+        let ret = graph.new_node(vec![value, value], Return, Typ::Bot).unwrap();
+
+        // Act
+        let result = graph.verify_control();
+
+        // Assert
+        assert!(matches!(result, Err(SoNError::ControlDisconnected { nid }) if nid == ret));
+    }
+
+    #[test]
+    fn should_reject_cast_of_control() {
+        // Arrange
+        let mut graph = Graph::new();
+        let start = graph.new_node(vec![], Start, Typ::Tuple { typs: vec![Typ::Ctrl] }).unwrap();
+
+        // Act
+        let result = graph.new_node(vec![start], Cast { to: Typ::IntBot }, Typ::Bot);
+
+        // Assert
+        assert!(matches!(result, Err(SoNError::TypeMismatch { .. })));
+    }
+
+    #[test]
+    fn should_reuse_removed_slots_last_in_first_out() {
+        // Arrange
+        let mut graph = Graph::new();
+        let nids: Vec<usize> = (0..4).map(|_| graph.new_node(vec![], Constant, Typ::Bot).unwrap()).collect();
+        graph.remove_node(nids[0]);
+        graph.remove_node(nids[2]);
+        graph.remove_node(nids[1]);
+
+        // Act
+        let reused: Vec<usize> = (0..4).map(|_| graph.new_node(vec![], Constant, Typ::Bot).unwrap()).collect();
+
+        // Assert
+        assert_eq!(vec![nids[1], nids[2], nids[0], 4], reused);
+    }
+
+    #[test]
+    fn should_list_live_nids_without_empty_cells() {
+        // Arrange
+        let mut graph = Graph::new();
+        let nid1 = graph.new_node(vec![], Constant, Typ::Bot).unwrap();
+        let nid2 = graph.new_node(vec![], Constant, Typ::Bot).unwrap();
+        let nid3 = graph.new_node(vec![], Constant, Typ::Bot).unwrap();
+        graph[nid2] = None;
+
+        // Act
+        let live_nids = graph.live_nids();
+
+        // Assert
+        assert_eq!(vec![nid1, nid3], live_nids);
+        assert_eq!(2, graph.live_count());
+        assert_eq!(3, graph.len());
+    }
+
     #[test]
     fn should_remove_dependency_from_the_back() {
         // Arrange
@@ -148,4 +483,87 @@ mod tests {
         assert!(matches!(graph_br.get_node( nid2).unwrap().inputs.as_slice(), [i] if i == &nid1));
         assert!(matches!(graph_br.get_node( nid1).unwrap().outputs.as_slice(), [i, j] if i == &nid2 && j == &nid3));
     }
+
+    #[test]
+    fn should_intern_signed_zeros_separately() {
+        // Arrange
+        let mut graph = Graph::new();
+        let zero = graph.new_node(vec![], Constant, Typ::Float { constant: 0.0 }).unwrap();
+        let negative_zero = graph.new_node(vec![], Constant, Typ::Float { constant: -0.0 }).unwrap();
+
+        // Act
+        let zero_again = graph.new_node(vec![], Constant, Typ::Float { constant: 0.0 }).unwrap();
+        let negative_zero_again = graph.new_node(vec![], Constant, Typ::Float { constant: -0.0 }).unwrap();
+
+        // Assert
+        assert_ne!(zero, negative_zero);
+        assert_eq!(zero, zero_again);
+        assert_eq!(negative_zero, negative_zero_again);
+    }
+
+    #[test]
+    fn should_compare_shared_inputs_structurally_once() {
+        // Arrange
+        let src = format!("int a = arg;{} return a;", " a = a + a;".repeat(64));
+        let options = || ParserOptions { do_optimize: false, ..Default::default() };
+        let mut parser = Parser::with_options(&src, options()).unwrap();
+        let mut other = Parser::with_options(&src, options()).unwrap();
+        let result = parser.parse().unwrap();
+        let other_result = other.parse().unwrap();
+
+        // Act
+        let equal = parser.graph.structurally_equal(result, &other.graph, other_result);
+
+        // Assert
+        assert!(equal);
+    }
+
+    /// the graph of `int s=0; int i=0; while(i<10){ s = s + term; i=i+1; } return s;` with the loop header and term.
+    /// The language has no loops yet, so it is built by hand. `term` builds its nodes from `arg`.
+    fn summing_loop(term: impl FnOnce(&mut Graph, usize) -> usize) -> (Graph, usize, usize) {
+        let mut graph = Parser::new_noarg("").unwrap().graph;
+        let ctrl = graph.new_node(vec![START_NID], Proj { proj_index: 0, _dbg_proj_label: "$ctrl".into() }, Typ::Ctrl).unwrap();
+        let arg = graph.new_node(vec![START_NID], Proj { proj_index: FIRST_ARG_PROJ_INDEX, _dbg_proj_label: "arg".into() }, Typ::IntBot).unwrap();
+        let zero = graph.new_node(vec![], Constant, Typ::Int { constant: 0 }).unwrap();
+        let one = graph.new_node(vec![], Constant, Typ::Int { constant: 1 }).unwrap();
+        let ten = graph.new_node(vec![], Constant, Typ::Int { constant: 10 }).unwrap();
+        // the back edges start out as the entry and are set once the body exists
+        let region = graph.new_node(vec![ctrl, ctrl], Region, Typ::Ctrl).unwrap();
+        let s = graph.new_node(vec![region, zero, zero], Phi, Typ::IntBot).unwrap();
+        let i = graph.new_node(vec![region, zero, zero], Phi, Typ::IntBot).unwrap();
+        let term = term(&mut graph, arg);
+        let next_s = graph.new_node(vec![s, term], Add, Typ::IntBot).unwrap();
+        let next_i = graph.new_node(vec![i, one], Add, Typ::IntBot).unwrap();
+        graph.set_input(s, 2, next_s).unwrap();
+        graph.set_input(i, 2, next_i).unwrap();
+        let cond = graph.new_node(vec![i, ten], Comp { kind: CompNodeKind::LT }, Typ::BoolBot).unwrap();
+        let iff = graph.new_node(vec![region, cond], If, Typ::Bot).unwrap();
+        let body = graph.new_node(vec![iff], Proj { proj_index: 0, _dbg_proj_label: "True".into() }, Typ::Ctrl).unwrap();
+        let exit = graph.new_node(vec![iff], Proj { proj_index: 1, _dbg_proj_label: "False".into() }, Typ::Ctrl).unwrap();
+        graph.set_input(region, 1, body).unwrap();
+        graph.new_node(vec![exit, s], Return, Typ::Bot).unwrap();
+        (graph, region, term)
+    }
+
+    #[test]
+    fn should_compare_loops_structurally() {
+        // Arrange
+        let build = || summing_loop(|graph, arg| {
+            let two = graph.new_node(vec![], Constant, Typ::Int { constant: 2 }).unwrap();
+            graph.new_node(vec![arg, two], Mul, Typ::IntBot).unwrap()
+        });
+        let ((graph, region, _), (other, other_region, _)) = (build(), build());
+        let ret = graph.graph_iter().find(|n| matches!(n.node_kind, Return)).unwrap().nid;
+        let other_ret = other.graph_iter().find(|n| matches!(n.node_kind, Return)).unwrap().nid;
+
+        // Act
+        let equal = graph.structurally_equal(ret, &other, other_ret);
+        let regions_equal = graph.structurally_equal(region, &other, other_region);
+        let differs = graph.structurally_equal(ret, &other, other_region);
+
+        // Assert
+        assert!(equal);
+        assert!(regions_equal);
+        assert!(!differs);
+    }
 }
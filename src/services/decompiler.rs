@@ -0,0 +1,359 @@
+use crate::nodes::node::{Node, NodeKind};
+use crate::services::formatter::{typ_keyword, INDENT};
+use crate::services::lowering::ZEROED_ARRAY_MAX_LEN;
+use crate::services::parser::Parser;
+use crate::typ::typ::Typ;
+use std::collections::{HashMap, HashSet};
+
+impl Parser {
+    /// Reconstructs source code from the (possibly optimized) graph: the struct and function definitions
+    /// followed by the statements each return depends on. Variables don't survive parsing, so their values
+    /// are inlined into the expressions. Only objects, calls and loads that would read or change memory at
+    /// the wrong place when inlined are bound to fresh variables.
+    pub fn to_source(&self) -> String {
+        let mut lines = vec![];
+        for typ in self.struct_definitions() {
+            let Typ::Struct { name, fields } = typ else { panic!("Typ {:?} is not a struct", typ) };
+            lines.push(format!("struct {} {{", name));
+            for (field, typ) in fields {
+                lines.push(format!("{}{} {};", INDENT, typ_keyword(typ), field));
+            }
+            lines.push("}".into());
+        }
+        let mut functions: Vec<_> = self.functions.iter().collect();
+        functions.sort_by_key(|(_, function)| function.entry);
+        for (name, function) in &functions {
+            let Some(ret) = function.ret else { continue };
+            let params = function.params.iter().map(|(param, typ)| format!("{} {}", typ_keyword(typ), param)).collect::<Vec<_>>().join(", ");
+            lines.push(format!("{} {}({}) {{", typ_keyword(&function.ret_typ), name, params));
+            lines.extend(self.statements(ret).into_iter().map(|stmt| format!("{}{}", INDENT, stmt)));
+            lines.push("}".into());
+        }
+        for ret in self.graph.graph_iter().filter(|n| matches!(n.node_kind, NodeKind::Return)) {
+            if !functions.iter().any(|(_, function)| function.ret == Some(ret.nid)) {
+                lines.extend(self.statements(ret.nid));
+            }
+        }
+        lines.join("\n")
+    }
+
+    /// the declared structs by name. Fields are ints or bools, so their order doesn't matter.
+    fn struct_definitions(&self) -> Vec<&Typ> {
+        let mut names: Vec<_> = self.structs.keys().collect();
+        names.sort();
+        names.into_iter().map(|name| &self.structs[name]).collect()
+    }
+
+    /// The statements of the body that ends with the return `ret`. Its side effects are emitted in the order
+    /// of the memory chain. A load is inlined where the memory it reads is current and a call into the
+    /// statement right after it, as long as nothing else uses its value.
+    fn statements(&self, ret: usize) -> Vec<String> {
+        let stmts: Vec<_> = self.effects(ret).into_iter().chain([ret]).collect();
+        // the statement at which the memory `mem` is current, i.e. the one after the effect that produced it
+        let current_at = |mem: usize| stmts.iter().position(|&stmt| stmt == mem).map_or(0, |i| i + 1);
+        let inlined_calls = self.inlined_calls(&stmts);
+
+        // loads that read stale memory where they are used are declared at the statement their memory is current at
+        let mut declared_loads = vec![vec![]; stmts.len()];
+        let mut bound = HashSet::new();
+        let mut worklist: Vec<_> = stmts.iter().enumerate().map(|(i, &stmt)| (i, self.operands(stmt))).collect();
+        while let Some((i, roots)) = worklist.pop() {
+            for nid in self.expression(&roots, |nid| bound.contains(&nid), &inlined_calls) {
+                let node = self.graph.get_node(nid).unwrap();
+                let at = current_at(node.inputs.first().copied().unwrap_or_default());
+                if matches!(node.node_kind, NodeKind::Load { .. }) && at != i && bound.insert(nid) {
+                    declared_loads[at].push(nid);
+                    worklist.push((at, vec![node.inputs[1]]));
+                }
+            }
+        }
+
+        let mut names = HashMap::new();
+        let mut lines = vec![];
+        // zeroing stores of the latest object that are still to be skipped
+        let mut zeroing = 0;
+        for (i, &stmt) in stmts.iter().enumerate() {
+            declared_loads[i].sort();
+            for &load in &declared_loads[i] {
+                self.declare_objects(&[load], &inlined_calls, &mut names, &mut lines);
+                lines.push(self.declaration(load, &mut names));
+            }
+            let node = self.graph.get_node(stmt).unwrap();
+            match node.node_kind {
+                NodeKind::Store { .. } if zeroing > 0 => zeroing -= 1,
+                NodeKind::Store { ref alias } => {
+                    let ptr = self.graph.get_node(node.inputs[1]).unwrap();
+                    let object = if matches!(ptr.node_kind, NodeKind::Offset) { ptr.inputs[0] } else { ptr.nid };
+                    // an object is created together with the stores that zero it
+                    let zeroed = self.zeroed_len(object);
+                    if zeroed > 0 && !names.contains_key(&object) {
+                        self.declare_objects(&[object], &inlined_calls, &mut names, &mut lines);
+                        zeroing = zeroed - 1;
+                        continue;
+                    }
+                    self.declare_objects(&node.inputs[1..], &inlined_calls, &mut names, &mut lines);
+                    let value = self.graph.get_node(node.inputs[2]).unwrap().bind(&self.graph).with_names(&names);
+                    if matches!(ptr.node_kind, NodeKind::Offset) {
+                        lines.push(format!("{} = {};", ptr.bind(&self.graph).with_names(&names), value));
+                    } else {
+                        lines.push(format!("{}.{} = {};", ptr.bind(&self.graph).with_names(&names), alias, value));
+                    }
+                }
+                NodeKind::Call { .. } if inlined_calls.contains(&stmt) => {}
+                NodeKind::Call { .. } => {
+                    self.declare_objects(&node.inputs[3..], &inlined_calls, &mut names, &mut lines);
+                    lines.push(self.declaration(stmt, &mut names));
+                }
+                _ => {
+                    self.declare_objects(&self.operands(stmt), &inlined_calls, &mut names, &mut lines);
+                    lines.push(format!("{}", node.bind(&self.graph).with_names(&names)));
+                }
+            }
+        }
+        lines
+    }
+
+    /// the prints, calls and stores `ret` depends on, from the oldest to the newest
+    fn effects(&self, ret: usize) -> Vec<usize> {
+        let mem = |node: &Node| match node.node_kind {
+            NodeKind::Store { .. } => Some(node.inputs[0]),
+            NodeKind::Print | NodeKind::Call { .. } => Some(node.inputs[1]),
+            _ => None,
+        };
+        let effects: HashSet<_> = self.graph.reverse_postorder(ret).into_iter()
+            .filter(|&nid| mem(self.graph.get_node(nid).unwrap()).is_some())
+            .collect();
+        let older: HashSet<_> = effects.iter().filter_map(|&nid| mem(self.graph.get_node(nid).unwrap())).collect();
+        let Some(mut effect) = effects.iter().copied().filter(|nid| !older.contains(nid)).max() else {
+            return vec![];
+        };
+        let mut chain = vec![effect];
+        while let Some(older) = mem(self.graph.get_node(effect).unwrap()).filter(|older| effects.contains(older)) {
+            chain.push(older);
+            effect = older;
+        }
+        chain.reverse();
+        chain
+    }
+
+    /// Calls whose value is only used by the statement right after them. Inlining such a call into it
+    /// doesn't move the call, unless a load of that statement or of the arguments is then read on the
+    /// other side of it.
+    fn inlined_calls(&self, stmts: &[usize]) -> HashSet<usize> {
+        let expressions: Vec<_> = stmts.iter().map(|&stmt| self.expression(&self.operands(stmt), |_| false, &HashSet::new())).collect();
+        let has_load = |expression: &[usize]| expression.iter().any(|&nid| matches!(self.graph.get_node(nid).unwrap().node_kind, NodeKind::Load { .. }));
+        let mut inlined = HashSet::new();
+        for (i, &stmt) in stmts.iter().enumerate().take(stmts.len() - 1) {
+            let Some(call_end) = self.graph.graph_iter().find(|n| matches!(n.node_kind, NodeKind::CallEnd) && n.inputs[0] == stmt) else {
+                continue;
+            };
+            let users: Vec<_> = expressions.iter().enumerate().filter(|(_, expression)| expression.contains(&call_end.nid)).map(|(j, _)| j).collect();
+            if users == [i + 1] && !has_load(&expressions[i]) && !has_load(&expressions[i + 1]) {
+                inlined.insert(stmt);
+            }
+        }
+        inlined
+    }
+
+    /// the values a statement uses
+    fn operands(&self, stmt: usize) -> Vec<usize> {
+        let node = self.graph.get_node(stmt).unwrap();
+        match node.node_kind {
+            NodeKind::Store { .. } => node.inputs[1..].to_vec(),
+            NodeKind::Print => vec![node.inputs[2]],
+            NodeKind::Call { .. } => node.inputs[3..].to_vec(),
+            NodeKind::Return => vec![node.inputs[1]],
+            _ => vec![],
+        }
+    }
+
+    /// The nodes the expressions of `roots` print, inputs before their users. Bound nodes are printed
+    /// by their name, so their inputs are left out, as are the arguments of calls that aren't inlined.
+    fn expression(&self, roots: &[usize], is_bound: impl Fn(usize) -> bool, inlined_calls: &HashSet<usize>) -> Vec<usize> {
+        let operands = |nid: usize| -> Vec<usize> {
+            let node = self.graph.get_node(nid).unwrap();
+            match node.node_kind {
+                _ if is_bound(nid) => vec![],
+                NodeKind::Constant | NodeKind::Proj { .. } | NodeKind::New => vec![],
+                NodeKind::CallEnd if inlined_calls.contains(&node.inputs[0]) => self.graph.get_node(node.inputs[0]).unwrap().inputs[3..].to_vec(),
+                NodeKind::CallEnd => vec![],
+                NodeKind::Load { .. } | NodeKind::NewArray => vec![node.inputs[1]],
+                NodeKind::Phi => {
+                    let iff = self.graph.region_if(node.inputs[0]).unwrap();
+                    [self.graph.get_node(iff).unwrap().inputs[1]].into_iter().chain(node.inputs[1..].iter().copied()).collect()
+                }
+                _ => node.inputs.clone(),
+            }
+        };
+        let mut visited = HashSet::new();
+        let mut postorder = vec![];
+        // (nid, its operands, index of the next operand to visit)
+        let mut stack: Vec<_> = roots.iter().rev().filter(|&&root| visited.insert(root)).map(|&root| (root, operands(root), 0)).collect();
+        while let Some((nid, inputs, i)) = stack.pop() {
+            if let Some(&input) = inputs.get(i) {
+                stack.push((nid, inputs, i + 1));
+                if visited.insert(input) {
+                    stack.push((input, operands(input), 0));
+                }
+            } else {
+                postorder.push(nid);
+            }
+        }
+        postorder
+    }
+
+    /// declares the objects the expressions of `roots` create and that don't have a name yet
+    fn declare_objects(&self, roots: &[usize], inlined_calls: &HashSet<usize>, names: &mut HashMap<usize, String>, lines: &mut Vec<String>) {
+        for nid in self.expression(roots, |nid| names.contains_key(&nid), inlined_calls) {
+            if matches!(self.graph.get_node(nid).unwrap().node_kind, NodeKind::New | NodeKind::NewArray) && !names.contains_key(&nid) {
+                lines.push(self.declaration(nid, names));
+            }
+        }
+    }
+
+    /// binds the value of `nid` to a fresh variable
+    fn declaration(&self, nid: usize, names: &mut HashMap<usize, String>) -> String {
+        let node = self.graph.get_node(nid).unwrap();
+        let keyword = match node.node_kind {
+            NodeKind::Call { ref name, .. } => typ_keyword(&self.functions[name].ret_typ),
+            _ if node.typ().is_int() => "int".into(),
+            _ if node.typ().is_bool() => "bool".into(),
+            _ => typ_keyword(&node.typ()),
+        };
+        let name = format!("v{}", nid);
+        let declaration = format!("{} {} = {};", keyword, name, node.bind(&self.graph).with_names(names));
+        names.insert(nid, name);
+        declaration
+    }
+
+    /// the number of stores that zero the object right after it is created
+    fn zeroed_len(&self, object: usize) -> usize {
+        let node = self.graph.get_node(object).unwrap();
+        match (&node.node_kind, node.typ()) {
+            (NodeKind::New, Typ::Struct { fields, .. }) => fields.len(),
+            (NodeKind::NewArray, Typ::Array { len: Some(len), .. }) if len <= ZEROED_ARRAY_MAX_LEN => len,
+            _ => 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::services::parser::{Parser, ParserOptions};
+
+    /// parses `src`, prints it via [Parser::to_source] and asserts that reparsing yields an equivalent graph
+    fn assert_round_trip(src: &str, do_optimize: bool) {
+        let options = || ParserOptions { do_optimize, ..Default::default() };
+        let mut parser = Parser::with_options(src, options()).unwrap();
+        let result = parser.parse().unwrap();
+        let source = parser.to_source();
+
+        let mut reparsed = Parser::with_options(&source, options()).unwrap();
+        let reparsed_result = reparsed.parse().unwrap();
+        assert!(parser.graph.structurally_equal(result, &reparsed.graph, reparsed_result), "{} printed as {}", src, source);
+    }
+
+    #[test]
+    fn should_round_trip_arithmetic_programs() {
+        for src in [
+            "return 1-2-3;",
+            "return 1-(2-3);",
+            "return 2*3+4;",
+            "return 2*(3+4);",
+            "return arg/2/3;",
+            "return arg/(2/arg);",
+            "return -arg*2;",
+            "return -(arg*2);",
+            "return 1+2*3+-5;",
+        ] {
+            assert_round_trip(src, false);
+            assert_round_trip(src, true);
+        }
+    }
+
+    #[test]
+    fn should_print_folded_program() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return 1+2*3+-5;").unwrap();
+        parser.parse().unwrap();
+
+        // Act
+        let source = parser.to_source();
+
+        // Assert
+        assert_eq!("return 2;", source);
+    }
+
+    #[test]
+    fn should_round_trip_declarations() {
+        // Arrange
+        let mut parser = Parser::new_noarg("int a=arg+1; return a*2;").unwrap();
+        let result = parser.parse().unwrap();
+
+        // Act
+        let source = parser.to_source();
+
+        // Assert
+        let mut reparsed = Parser::new_noarg(&source).unwrap();
+        let reparsed_result = reparsed.parse().unwrap();
+        assert_eq!("return (arg+1)*2;", source);
+        assert!(parser.graph.structurally_equal(result, &reparsed.graph, reparsed_result));
+    }
+
+    #[test]
+    fn should_print_prints_before_return() {
+        // Arrange
+        let mut parser = Parser::new_noarg("int a=arg+1; print(a); { print(a*2); } return a;").unwrap();
+        parser.parse().unwrap();
+
+        // Act
+        let source = parser.to_source();
+
+        // Assert
+        assert_eq!("print(arg+1);\nprint((arg+1)*2);\nreturn arg+1;", source);
+    }
+
+    #[test]
+    fn should_round_trip_functions_calls_and_stores() {
+        for src in [
+            "int f(int x){ return x*2; } print(f(arg)); return f(1);",
+            "int f(int x){ return x*2; } int a=f(arg); print(2); return a+f(a);",
+            "int f(int x){ print(x); return x; } return f(f(arg));",
+            "struct P{int x;} P p=new P; p.x=arg; return p.x;",
+            "struct P{int x; bool b;} P p=new P; int a=p.x; p.x=arg; print(a); return p.x+a;",
+            "int[] a=new int[3]; a[1]=arg; return a[1]+a[0];",
+            "int[] a=new int[arg]; a[0]=1; return a[0];",
+            "struct P{int x;} int f(P p){ p.x=p.x+1; return p.x; } P p=new P; int a=p.x; return f(p)+a;",
+        ] {
+            assert_round_trip(src, false);
+            assert_round_trip(src, true);
+        }
+    }
+
+    #[test]
+    fn should_print_function_definitions_and_calls() {
+        // Arrange
+        let mut parser = Parser::new_noarg("int f(int x){ return x*2; } print(f(arg)); return f(1);").unwrap();
+        parser.parse().unwrap();
+
+        // Act
+        let source = parser.to_source();
+
+        // Assert
+        assert_eq!("int f(int x) {\n    return x*2;\n}\nprint(f(arg));\nreturn f(1);", source);
+    }
+
+    #[test]
+    fn should_print_objects_and_stores() {
+        // Arrange
+        let options = ParserOptions { do_optimize: false, ..Default::default() };
+        let mut parser = Parser::with_options("struct P{int x;} P p=new P; p.x=arg; return p.x;", options).unwrap();
+        parser.parse().unwrap();
+
+        // Act
+        let source = parser.to_source();
+
+        // Assert
+        assert_eq!("struct P {\n    int x;\n}\nP v6 = new P;\nv6.x = arg;\nreturn v6.x;", source);
+    }
+}
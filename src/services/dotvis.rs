@@ -1,9 +1,20 @@
-use crate::nodes::node::{Node, NodeKind};
+use crate::nodes::node::{node_icon, NodeKind};
 use crate::services::parser::{Parser, SCOPE_NID};
 use crate::typ::typ::Typ;
 
+/// Options of [Parser::as_dotfile_with]. The default is what [Parser::as_dotfile] renders.
+#[derive(Clone, Debug, Default)]
+pub struct DotOptions {
+    /// appends `#uid` to the label of each node, e.g. `+#7`, to see how the canonicalization ordered operands
+    pub show_uids: bool,
+}
+
 impl Parser {
     pub fn as_dotfile(&self) -> String {
+        self.as_dotfile_with(&DotOptions::default())
+    }
+
+    pub fn as_dotfile_with(&self, options: &DotOptions) -> String {
         let mut sb = String::new();
         sb.push_str("digraph mygraph{\n");
         sb.push_str("/*\n");
@@ -25,22 +36,29 @@ impl Parser {
         // nodes much prettier to look at.
         sb.push_str("\tconcentrate=\"true\";\n");
 
+        // Nodes are named and ordered by their uid. Unlike the nid it doesn't depend on which slots the GC freed.
+        let mut nodes: Vec<_> = self.graph.graph_iter().filter(|n| !matches!(n.node_kind, NodeKind::KeepAlive | NodeKind::Scope {..})).collect();
+        nodes.sort_by_key(|n| n.uid);
+
         // Just the Nodes first, in a cluster no edges
         sb.push_str("\tsubgraph cluster_Nodes {\n"); // Magic "cluster_" in the subgraph name
         // define normal nodes
-        for n in self.graph.graph_iter().filter(|n| !matches!(n.node_kind, NodeKind::KeepAlive | NodeKind::Scope {..})) {
+        for n in nodes.iter() {
             sb.push_str("\t\t");
-            sb.push_str(&format!("Node_{}", n.nid));
+            sb.push_str(&format!("Node_{}", n.uid));
             sb.push_str(" [ ");
-            let lab = node_icon(n);
+            let mut lab = node_icon(n);
+            if options.show_uids {
+                lab.push_str(&format!("#{}", n.uid));
+            }
             // control nodes have box shape
             // other nodes are ellipses, i.e. default shape
             if n.bind(&self.graph).is_cfg() {
                 sb.push_str("shape=box fillcolor=yellow style=\"filled");
                 if matches!(n.node_kind, NodeKind::Proj {..}) {
-                    sb.push_str(",dashed\"");
+                    sb.push_str(",dashed\" ");
                 } else {
-                    sb.push_str("\"")
+                    sb.push_str("\" ")
                 }
             } else {
                 if matches!(n.node_kind, NodeKind::Proj {..}) {
@@ -56,7 +74,7 @@ impl Parser {
         sb.push_str("\t}\n");     // End Node cluster
 
         // define the scope node
-        if let NodeKind::Scope { scopes } = &self.graph.get_node(SCOPE_NID).unwrap().node_kind {
+        if let NodeKind::Scope { scopes, .. } = &self.graph.get_node(SCOPE_NID).unwrap().node_kind {
             sb.push_str("\tnode [shape=plaintext];\n");
             for (level, scope) in scopes.iter().enumerate() {
                 sb.push_str("\tsubgraph cluster_");
@@ -73,7 +91,7 @@ impl Parser {
                     sb.push_str("<TD PORT=\"");
                     sb.push_str(&format!("{}_{}", scope_name, name));
                     sb.push_str("\">");
-                    sb.push_str(&format!("{}", name));
+                    sb.push_str(&name.to_string());
                     sb.push_str("</TD>");
                 }
                 sb.push_str("</TR>\n\t\t\t</TABLE>>];\n");
@@ -83,19 +101,19 @@ impl Parser {
 
         // Walk the Node edges
         sb.push_str("\tedge [ fontname=Helvetica, fontsize=8 ];\n");
-        for n in self.graph.graph_iter().filter(|n| !matches!(n.node_kind, NodeKind::KeepAlive | NodeKind::Scope {..})) {
+        for n in nodes.iter() {
             // In this chapter we do display the Constant->Start edge;
             for (i, def_nid) in n.inputs.iter().enumerate() {
                 if let Some(Some(def)) = self.graph.get(*def_nid) {
                     // Most edges land here use->def
                     sb.push('\t');
-                    sb.push_str(&format!("Node_{}", n.nid));
+                    sb.push_str(&format!("Node_{}", n.uid));
                     sb.push_str(" -> ");
-                    sb.push_str(&format!("Node_{}", def_nid));
+                    sb.push_str(&format!("Node_{}", def.uid));
                     // Number edges, so we can see how they track
                     sb.push_str("[taillabel=");
                     sb.push_str(&format!("{}", i));
-                    if matches!(n.node_kind, NodeKind::Constant {..}) && matches!(def.node_kind, NodeKind::Start {..}) {
+                    if matches!(n.node_kind, NodeKind::Constant) && matches!(def.node_kind, NodeKind::Start) {
                         sb.push_str(" style=dotted");
                     } else if def.bind(&self.graph).is_cfg() && (!matches!(n.node_kind, NodeKind::Proj {..}) || matches!(n.typ(), Typ::Ctrl)) {   // control edges are colored red
                         sb.push_str(" color=red");
@@ -108,14 +126,18 @@ impl Parser {
         // Walk the variable definitions
         sb.push_str("\tedge [style=dashed color=cornflowerblue];\n");
         let scope_node = self.graph.get_node(SCOPE_NID).unwrap();
-        if let NodeKind::Scope { scopes } = &scope_node.node_kind {
+        if let NodeKind::Scope { scopes, .. } = &scope_node.node_kind {
             for (level, scope) in scopes.iter().enumerate() {
                 let scope_name = format!("Node_{}_{}", SCOPE_NID, level);
-                for (name, def_nid) in scope {
+                // sorted, since the order of a HashMap differs from run to run
+                let mut defs: Vec<_> = scope.iter().collect();
+                defs.sort();
+                for (name, def_nid) in defs {
+                    let Ok(def) = self.graph.get_node(*def_nid) else { continue };
                     sb.push('\t');
                     sb.push_str(&format!("{}:\"{}_{}\"", scope_name, scope_name, name));
                     sb.push_str(" -> ");
-                    sb.push_str(&format!("Node_{}", def_nid));
+                    sb.push_str(&format!("Node_{}", def.uid));
                     sb.push_str(";\n");
                 }
             }
@@ -125,35 +147,106 @@ impl Parser {
         sb
     }
 }
-fn node_icon(node: &Node) -> String {
-    match node.node_kind {
-        NodeKind::Constant => {
-            match node.typ() {
-                Typ::Int { constant } => format!("#{}", constant),
-                Typ::Bool { constant } => format!("#{}", constant),
-                _ => panic!("Type {:?} for NodeKind::Constant unsupported", node.typ()),
-            }
-        }
-        NodeKind::Return => "Return".into(),
-        NodeKind::Start => "Start".into(),
-        NodeKind::KeepAlive => "KeepAlive".into(),
-        NodeKind::Add => "+".into(),
-        NodeKind::Sub => "-".into(),
-        NodeKind::Mul => "*".into(),
-        NodeKind::Div => "/".into(),
-        NodeKind::Minus => "-".into(),
-        NodeKind::Scope { .. } => "Scope".into(),
-        NodeKind::Proj { ref _dbg_proj_label, .. } => _dbg_proj_label.into(),
-        NodeKind::Comp { .. } => "Bool".into(),
-        NodeKind::Not => "Not".into(),
-    }
-}
 
 #[cfg(test)]
 mod tests {
-    use crate::services::parser::Parser;
+    use crate::nodes::node::NodeKind;
+    use crate::nodes::node::node_icon;
+    use crate::services::dotvis::DotOptions;
+    use crate::services::parser::{Parser, ParserBuilder};
+    use crate::typ::typ::Typ;
+
+    #[test]
+    fn should_draw_scope_edge_to_comparison_defined_variable() {
+        // Arrange
+        let mut parser = ParserBuilder::new().do_optimize(false).build_noarg("bool b = arg<1; #showGraph; return b;").unwrap();
+
+        // Act
+        parser.parse().unwrap();
+
+        // Assert
+        let comp = parser.graph.graph_iter().find(|n| matches!(n.node_kind, NodeKind::Comp { .. })).unwrap();
+        assert!(parser._dbg_output.contains(&format!("\tNode_1_1:\"Node_1_1_b\" -> Node_{};\n", comp.uid)));
+        assert!(parser._dbg_output.contains(&format!("\t\tNode_{} [ label=\"<\" ];\n", comp.uid)));
+    }
+
+    #[test]
+    fn should_label_comparisons_bools_and_negation() {
+        // Arrange
+        let mut parser = ParserBuilder::new().do_optimize(false).build_noarg("bool b = arg<=-arg == true; return b && false;").unwrap();
+        parser.parse().unwrap();
+
+        // Act
+        let dotfile = parser.as_dotfile();
+
+        // Assert
+        for label in ["<=", "==", "&&", "neg", "#true", "#false"] {
+            assert!(dotfile.contains(&format!("label=\"{}\"", label)), "{}", label);
+        }
+    }
+
+    #[test]
+    fn should_output_same_dotfile_for_different_gc_histories() {
+        // Arrange
+        let src = "int a=1+2*3; int b=arg+a; int c=b*0+a; b = if (arg) b+1 else c+2; #showGraph; return b+c;";
+        let mut eager = Parser::new_noarg(src).unwrap();
+        let mut lazy = Parser::new_noarg(src).unwrap();
+        // the same garbage in both, which eager frees before parsing and lazy only afterwards
+        let garbage = |parser: &mut Parser| (100..110).map(|constant| parser.graph.new_node(vec![], NodeKind::Constant, Typ::Int { constant }).unwrap()).collect::<Vec<_>>();
+        garbage(&mut eager);
+        eager.drop_unused_nodes();
+        let lazy_garbage = garbage(&mut lazy);
+        for &nid in lazy_garbage.iter() {
+            lazy.keep_node(nid).unwrap();
+        }
+
+        // Act
+        eager.parse().unwrap();
+        let ret = lazy.parse().unwrap();
+        for &nid in lazy_garbage.iter() {
+            lazy.unkeep_node(nid).unwrap();
+        }
+        lazy.drop_all_unused_nodes(ret).unwrap();
+
+        // Assert
+        let nids = |parser: &Parser| parser.graph.graph_iter().map(|n| (n.uid, n.nid)).collect::<Vec<_>>();
+        assert_ne!(nids(&eager), nids(&lazy));
+        assert_eq!(eager.as_dotfile(), lazy.as_dotfile());
+    }
+
+    #[test]
+    fn should_append_uid_to_labels() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return arg+1;").unwrap();
+        parser.parse().unwrap();
+
+        // Act
+        let dotfile = parser.as_dotfile_with(&DotOptions { show_uids: true });
+
+        // Assert
+        for n in parser.graph.graph_iter().filter(|n| !matches!(n.node_kind, NodeKind::KeepAlive | NodeKind::Scope { .. })) {
+            assert!(dotfile.contains(&format!("Node_{} [ ", n.uid)));
+            assert!(dotfile.contains(&format!("label=\"{}#{}\"", node_icon(n), n.uid)), "{}", n.nid);
+        }
+        assert!(!parser.as_dotfile().contains("label=\"Start#"));
+    }
+
+    #[test]
+    fn should_label_parameter_with_its_name() {
+        // Arrange
+        let mut parser = ParserBuilder::new().params(vec![("n".into(), Typ::IntBot)]).build_noarg("return n*2;").unwrap();
+        let ret = parser.parse().unwrap();
+
+        // Act
+        let dotfile = parser.as_dotfile();
+
+        // Assert
+        let n = parser.graph.graph_iter().find(|n| matches!(n.node_kind, NodeKind::Proj { proj_index: 2, .. })).unwrap();
+        assert!(dotfile.contains(&format!("\t\tNode_{} [ style=dashed label=\"n\" ];\n", n.uid)));
+        assert_eq!("return n*2;", format!("{:}", parser.graph.get_node(ret).unwrap().bind(&parser.graph)));
+    }
 
-    // #[test]
+    #[test]
     fn should_output_minimal_dotfile() {
         // Arrange
         let parser = Parser::new_noarg("").unwrap();
@@ -164,14 +257,13 @@ mod tests {
         dbg!(&dotfile);
 
         // Assert
-        assert_eq!(dotfile, "digraph mygraph{\n/*\n\n*/\n\trankdir=BT;\n\tordering=\"in\";\n\tconcentrate=\"true\";\n\tsubgraph cluster_Nodes {\n\t\tNode_1 [ shape=box style=filled fillcolor=yellow label=\"Start\" ];\n\t}\n\tedge [ fontname=Helvetica, fontsize=8 ];\n}\n");
+        assert_eq!(dotfile, "digraph mygraph{\n/*\n\n*/\n\trankdir=BT;\n\tordering=\"in\";\n\tconcentrate=\"true\";\n\tsubgraph cluster_Nodes {\n\t\tNode_2 [ shape=box fillcolor=yellow style=\"filled\" label=\"Start\" ];\n\t}\n\tnode [shape=plaintext];\n\tedge [ fontname=Helvetica, fontsize=8 ];\n\tedge [style=dashed color=cornflowerblue];\n}\n");
     }
 
-    // #[test]
+    #[test]
     fn should_output_return_1_dotfile() {
         // Arrange
-        let mut parser = Parser::new_noarg("return 1;").unwrap();
-        parser.do_optimize = false;
+        let mut parser = ParserBuilder::new().do_optimize(false).build_noarg("return 1;").unwrap();
         parser.parse().unwrap();
 
         // Act
@@ -180,14 +272,13 @@ mod tests {
         dbg!(&dotfile);
 
         // Assert
-        assert_eq!(dotfile, "digraph mygraph{\n/*\nreturn 1;\n*/\n\trankdir=BT;\n\tordering=\"in\";\n\tconcentrate=\"true\";\n\tsubgraph cluster_Nodes {\n\t\tNode_1 [ shape=box style=filled fillcolor=yellow label=\"Start\" ];\n\t\tNode_2 [ label=\"#1\" ];\n\t\tNode_3 [ shape=box style=filled fillcolor=yellow label=\"Return\" ];\n\t}\n\tedge [ fontname=Helvetica, fontsize=8 ];\n\tNode_3 -> Node_1[taillabel=0 color=red];\n\tNode_3 -> Node_2[taillabel=1];\n}\n");
+        assert_eq!(dotfile, "digraph mygraph{\n/*\nreturn 1;\n*/\n\trankdir=BT;\n\tordering=\"in\";\n\tconcentrate=\"true\";\n\tsubgraph cluster_Nodes {\n\t\tNode_2 [ shape=box fillcolor=yellow style=\"filled\" label=\"Start\" ];\n\t\tNode_3 [ shape=box fillcolor=yellow style=\"filled,dashed\" label=\"$ctrl\" ];\n\t\tNode_6 [ label=\"#1\" ];\n\t\tNode_7 [ shape=box fillcolor=yellow style=\"filled\" label=\"Return\" ];\n\t}\n\tnode [shape=plaintext];\n\tedge [ fontname=Helvetica, fontsize=8 ];\n\tNode_3 -> Node_2[taillabel=0 color=red];\n\tNode_7 -> Node_3[taillabel=0 color=red];\n\tNode_7 -> Node_6[taillabel=1];\n\tedge [style=dashed color=cornflowerblue];\n}\n");
     }
 
-    // #[test]
+    #[test]
     fn should_complex_dotfile() {
         // Arrange
-        let mut parser = Parser::new_noarg("return 1+2*3+-5;").unwrap();
-        parser.do_optimize = false;
+        let mut parser = ParserBuilder::new().do_optimize(false).build_noarg("return 1+2*3+-5;").unwrap();
         parser.parse().unwrap();
 
         // Act
@@ -196,6 +287,6 @@ mod tests {
         dbg!(&dotfile);
 
         // Assert
-        assert_eq!(dotfile, "digraph mygraph{\n/*\nreturn 1+2*3+-5;\n*/\n\trankdir=BT;\n\tordering=\"in\";\n\tconcentrate=\"true\";\n\tsubgraph cluster_Nodes {\n\t\tNode_1 [ shape=box style=filled fillcolor=yellow label=\"Start\" ];\n\t\tNode_2 [ label=\"#1\" ];\n\t\tNode_3 [ label=\"#2\" ];\n\t\tNode_4 [ label=\"#3\" ];\n\t\tNode_5 [ label=\"*\" ];\n\t\tNode_6 [ label=\"#5\" ];\n\t\tNode_7 [ label=\"-\" ];\n\t\tNode_8 [ label=\"+\" ];\n\t\tNode_9 [ label=\"+\" ];\n\t\tNode_10 [ shape=box style=filled fillcolor=yellow label=\"Return\" ];\n\t}\n\tedge [ fontname=Helvetica, fontsize=8 ];\n\tNode_5 -> Node_3[taillabel=0];\n\tNode_5 -> Node_4[taillabel=1];\n\tNode_7 -> Node_6[taillabel=0];\n\tNode_8 -> Node_5[taillabel=0];\n\tNode_8 -> Node_7[taillabel=1];\n\tNode_9 -> Node_2[taillabel=0];\n\tNode_9 -> Node_8[taillabel=1];\n\tNode_10 -> Node_1[taillabel=0 color=red];\n\tNode_10 -> Node_9[taillabel=1];\n}\n");
+        assert_eq!(dotfile, "digraph mygraph{\n/*\nreturn 1+2*3+-5;\n*/\n\trankdir=BT;\n\tordering=\"in\";\n\tconcentrate=\"true\";\n\tsubgraph cluster_Nodes {\n\t\tNode_2 [ shape=box fillcolor=yellow style=\"filled\" label=\"Start\" ];\n\t\tNode_3 [ shape=box fillcolor=yellow style=\"filled,dashed\" label=\"$ctrl\" ];\n\t\tNode_6 [ label=\"#1\" ];\n\t\tNode_7 [ label=\"#2\" ];\n\t\tNode_8 [ label=\"#3\" ];\n\t\tNode_9 [ label=\"*\" ];\n\t\tNode_10 [ label=\"+\" ];\n\t\tNode_11 [ label=\"#5\" ];\n\t\tNode_12 [ label=\"neg\" ];\n\t\tNode_13 [ label=\"+\" ];\n\t\tNode_14 [ shape=box fillcolor=yellow style=\"filled\" label=\"Return\" ];\n\t}\n\tnode [shape=plaintext];\n\tedge [ fontname=Helvetica, fontsize=8 ];\n\tNode_3 -> Node_2[taillabel=0 color=red];\n\tNode_9 -> Node_7[taillabel=0];\n\tNode_9 -> Node_8[taillabel=1];\n\tNode_10 -> Node_6[taillabel=0];\n\tNode_10 -> Node_9[taillabel=1];\n\tNode_12 -> Node_11[taillabel=0];\n\tNode_13 -> Node_10[taillabel=0];\n\tNode_13 -> Node_12[taillabel=1];\n\tNode_14 -> Node_3[taillabel=0 color=red];\n\tNode_14 -> Node_13[taillabel=1];\n\tedge [style=dashed color=cornflowerblue];\n}\n");
     }
 }
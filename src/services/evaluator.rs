@@ -0,0 +1,596 @@
+use crate::errors::son_error::SoNError;
+use crate::nodes::node::{CompNodeKind, NodeKind};
+use crate::services::parser::{Parser, FIRST_ARG_PROJ_INDEX, START_NID};
+use crate::typ::typ::Typ;
+use std::collections::HashMap;
+
+/// The steps [Parser::evaluate_args] takes before it gives up with StepBudgetExceeded. Bounds programs that loop forever.
+pub const DEFAULT_STEP_BUDGET: usize = 1_000_000;
+
+/// The most calls that run inside each other before the evaluation gives up with ExecutionLimitExceeded.
+/// Each call is evaluated by recursion, so this bounds the stack a recursive program takes. It leaves room to spare
+/// on the 2 MiB stack of a thread in an unoptimized build.
+pub const MAX_CALL_DEPTH: usize = 64;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Value {
+    Int(i64),
+    Bool(bool),
+    Float(f64),
+}
+
+impl Value {
+    fn typ(self) -> Typ {
+        match self {
+            Value::Int(constant) => Typ::Int { constant },
+            Value::Bool(constant) => Typ::Bool { constant },
+            Value::Float(constant) => Typ::Float { constant },
+        }
+    }
+}
+
+/// The state of one run of the interpreter. A call runs in a fresh one that shares the steps and the trace.
+struct Evaluation {
+    args: Vec<Value>,
+    values: HashMap<usize, Value>,
+    /// The value of each Phi of the reached Regions. It is kept when a Region is entered again through
+    /// a back edge, while the other values are computed again from the new Phi values.
+    phis: HashMap<usize, Value>,
+    /// the input each reached Region was entered from
+    entered_from: HashMap<usize, usize>,
+    /// each node that is evaluated and each control edge that is followed takes one step
+    steps_left: usize,
+    /// the number of calls this one runs in
+    call_depth: usize,
+    /// the nodes in the order their values were computed, if requested
+    trace: Option<Vec<(usize, Typ)>>,
+}
+
+impl Evaluation {
+    fn new(args: Vec<Value>, steps_left: usize) -> Evaluation {
+        Evaluation { args, values: HashMap::new(), phis: HashMap::new(), entered_from: HashMap::new(), steps_left, call_depth: 0, trace: None }
+    }
+}
+
+impl Parser {
+    /// Interprets the parsed program by following its control from Start to the Return. Each If takes the projection
+    /// its predicate selects and each Phi the value of the branch its Region was entered from. Entering a Region again
+    /// through a back edge moves its Phis to the values of that edge. Calls run the callee with the evaluated arguments.
+    /// `args` are bound to the parameter projections of the Start node in order. Side effects like `print` are not
+    /// performed and memory is not supported. The result is a constant typ, e.g. `Bool { constant }` for a boolean program.
+    /// The evaluation gives up after [DEFAULT_STEP_BUDGET] steps or [MAX_CALL_DEPTH] nested calls.
+    pub fn evaluate_args(&self, args: &[i64]) -> Result<Typ, SoNError> {
+        self.evaluate_with_budget(args, DEFAULT_STEP_BUDGET)
+    }
+
+    /// Like [Parser::evaluate_args] for programs returning an int. Bools are returned as 0 and 1.
+    pub fn evaluate_i64(&self, args: &[i64]) -> Result<i64, SoNError> {
+        match self.evaluate_args(args)? {
+            Typ::Int { constant } => Ok(constant),
+            Typ::Bool { constant } => Ok(constant as i64),
+            typ => Err(SoNError::TypeMismatch { variable: None, expected: "int or bool".into(), but_got: format!("{:?}", typ) }),
+        }
+    }
+
+    /// Like [Parser::evaluate_args], but gives up with a StepBudgetExceeded once more than `max_steps` steps are taken.
+    /// This guards against programs that would run forever.
+    pub fn evaluate_with_budget(&self, args: &[i64], max_steps: usize) -> Result<Typ, SoNError> {
+        self.evaluate(&mut Evaluation::new(args.iter().map(|&arg| Value::Int(arg)).collect(), max_steps)).map(Value::typ)
+    }
+
+    /// Like [Parser::evaluate_args] with the single argument `arg`, but also returns the value of each evaluated node
+    /// in the order they were computed.
+    pub fn evaluate_traced(&self, arg: i64) -> (Result<Typ, SoNError>, Vec<(usize, Typ)>) {
+        let mut evaluation = Evaluation::new(vec![Value::Int(arg)], DEFAULT_STEP_BUDGET);
+        evaluation.trace = Some(vec![]);
+        let result = self.evaluate(&mut evaluation).map(Value::typ);
+        (result, evaluation.trace.unwrap_or_default())
+    }
+
+    fn evaluate(&self, evaluation: &mut Evaluation) -> Result<Value, SoNError> {
+        let Typ::Tuple { typs } = self.graph.get_node(START_NID)?.typ() else {
+            panic!("Start node has to be a tuple.")
+        };
+        let expected = typs.len() - FIRST_ARG_PROJ_INDEX;
+        if expected != evaluation.args.len() {
+            return Err(SoNError::ArgumentCountMismatch { expected, but_got: evaluation.args.len() });
+        }
+        self.run(START_NID, evaluation)
+    }
+
+    /// follows the control from the Start or Fun `entry` to a Return and evaluates its value
+    fn run(&self, entry: usize, evaluation: &mut Evaluation) -> Result<Value, SoNError> {
+        let mut ctrl = self.ctrl_proj(entry, 0)?;
+        let ret = loop {
+            evaluation.steps_left = evaluation.steps_left.checked_sub(1).ok_or(SoNError::StepBudgetExceeded)?;
+            let next = self.graph.outputs_of(ctrl)?.iter()
+                .filter_map(|&nid| self.graph.get_node(nid).ok())
+                .find(|user| user.inputs.first() == Some(&ctrl) && matches!(user.node_kind, NodeKind::If | NodeKind::Return | NodeKind::Print | NodeKind::Call { .. })
+                    || matches!(user.node_kind, NodeKind::Region))
+                .ok_or(SoNError::ControlDisconnected { nid: ctrl })?;
+            ctrl = match next.node_kind {
+                NodeKind::Return => break next.nid,
+                NodeKind::If => {
+                    let taken = condition(self.evaluate_node(next.inputs[1], evaluation)?)?;
+                    self.ctrl_proj(next.nid, if taken { 0 } else { 1 })?
+                }
+                NodeKind::Region => {
+                    let input = next.inputs.iter().position(|&input| input == ctrl).ok_or(SoNError::ControlDisconnected { nid: ctrl })?;
+                    self.enter_region(next.nid, input, evaluation)?;
+                    next.nid
+                }
+                // neither prints nor calls are performed here, a call runs once its value is needed
+                _ => next.nid,
+            };
+        };
+        self.evaluate_node(ret, evaluation)
+    }
+
+    /// Moves the Phis of a Region that was already reached to the values of `input`, as when taking a back edge.
+    /// The values of all other nodes may depend on the Phis and are computed again.
+    fn enter_region(&self, region: usize, input: usize, evaluation: &mut Evaluation) -> Result<(), SoNError> {
+        if evaluation.entered_from.contains_key(&region) {
+            let mut phis = vec![];
+            for &user in self.graph.outputs_of(region)? {
+                let phi = self.graph.get_node(user)?;
+                if matches!(phi.node_kind, NodeKind::Phi) {
+                    phis.push((user, self.evaluate_node(phi.inputs[input + 1], evaluation)?));
+                }
+            }
+            evaluation.values.clear();
+            for (phi, value) in phis {
+                evaluation.phis.insert(phi, value);
+                if let Some(trace) = &mut evaluation.trace {
+                    trace.push((phi, value.typ()));
+                }
+            }
+        }
+        evaluation.entered_from.insert(region, input);
+        Ok(())
+    }
+
+    /// The projection of the control with `proj_index` out of a Start, Fun or If
+    fn ctrl_proj(&self, nid: usize, proj_index: usize) -> Result<usize, SoNError> {
+        let proj = self.graph.outputs_of(nid)?.iter()
+            .filter_map(|&nid| self.graph.get_node(nid).ok())
+            .find(|user| matches!(user.node_kind, NodeKind::Proj { proj_index: index, .. } if index == proj_index))
+            .ok_or(SoNError::ControlDisconnected { nid })?;
+        Ok(proj.nid)
+    }
+
+    /// Runs the callee of the Call with the evaluated arguments. The callee shares the steps and the trace of the caller.
+    fn evaluate_call(&self, call: usize, evaluation: &mut Evaluation) -> Result<Value, SoNError> {
+        if evaluation.call_depth >= MAX_CALL_DEPTH {
+            return Err(SoNError::ExecutionLimitExceeded);
+        }
+        let call = self.graph.get_node(call)?;
+        let mut args = vec![];
+        for &arg in &call.inputs[3..] {
+            args.push(self.evaluate_node(arg, evaluation)?);
+        }
+        let mut callee = Evaluation::new(args, evaluation.steps_left);
+        callee.call_depth = evaluation.call_depth + 1;
+        callee.trace = evaluation.trace.take();
+        let result = self.run(call.inputs[2], &mut callee);
+        evaluation.steps_left = callee.steps_left;
+        evaluation.trace = callee.trace;
+        result
+    }
+
+    fn evaluate_node(&self, nid: usize, evaluation: &mut Evaluation) -> Result<Value, SoNError> {
+        if let Some(value) = evaluation.values.get(&nid).or_else(|| evaluation.phis.get(&nid)) {
+            return Ok(*value);
+        }
+        evaluation.steps_left = evaluation.steps_left.checked_sub(1).ok_or(SoNError::StepBudgetExceeded)?;
+        let node = self.graph.get_node(nid)?;
+        let value = match &node.node_kind {
+            NodeKind::Constant => match node.typ() {
+                Typ::Int { constant } => Value::Int(constant),
+                Typ::Bool { constant } => Value::Bool(constant),
+                Typ::Float { constant } => Value::Float(constant),
+                _ => return Err(SoNError::NotEvaluable { nid }),
+            },
+            NodeKind::Return => self.evaluate_node(node.inputs[1], evaluation)?,
+            NodeKind::Proj { proj_index, .. } if *proj_index >= FIRST_ARG_PROJ_INDEX => {
+                *evaluation.args.get(*proj_index - FIRST_ARG_PROJ_INDEX).ok_or(SoNError::NotEvaluable { nid })?
+            }
+            NodeKind::Add | NodeKind::Sub | NodeKind::Mul | NodeKind::Div | NodeKind::AddSat | NodeKind::SubSat | NodeKind::MulSat | NodeKind::Pow | NodeKind::Min | NodeKind::Max | NodeKind::Comp { .. }
+            | NodeKind::Abs | NodeKind::Minus | NodeKind::Not | NodeKind::Cast { .. } => {
+                let mut inputs = Vec::with_capacity(node.inputs.len());
+                for &input in &node.inputs {
+                    inputs.push(self.evaluate_node(input, evaluation)?);
+                }
+                // kept out of this function, which recursive programs enter once per node of each call
+                evaluate_operation(&node.node_kind, &inputs)?
+            }
+            NodeKind::Phi => {
+                let entered_from = *evaluation.entered_from.get(&node.inputs[0]).ok_or(SoNError::ControlDisconnected { nid: node.inputs[0] })?;
+                let value = self.evaluate_node(node.inputs[entered_from + 1], evaluation)?;
+                evaluation.phis.insert(nid, value);
+                if let Some(trace) = &mut evaluation.trace {
+                    trace.push((nid, value.typ()));
+                }
+                return Ok(value);
+            }
+            NodeKind::CallEnd => self.evaluate_call(node.inputs[0], evaluation)?,
+            // control and memory have no value the interpreter can compute
+            NodeKind::Proj { .. } | NodeKind::Start | NodeKind::KeepAlive | NodeKind::Scope { .. } | NodeKind::Print | NodeKind::New | NodeKind::NewArray | NodeKind::Offset | NodeKind::Fun { .. } | NodeKind::Call { .. } | NodeKind::Load { .. } | NodeKind::Store { .. } | NodeKind::If | NodeKind::Region => {
+                return Err(SoNError::NotEvaluable { nid });
+            }
+        };
+        evaluation.values.insert(nid, value);
+        if let Some(trace) = &mut evaluation.trace {
+            trace.push((nid, value.typ()));
+        }
+        Ok(value)
+    }
+}
+
+/// the value of a unary or binary operation, a comparison or a cast of the evaluated `inputs`
+fn evaluate_operation(node_kind: &NodeKind, inputs: &[Value]) -> Result<Value, SoNError> {
+    Ok(match (node_kind, inputs) {
+        (NodeKind::Abs, [input]) => match *input {
+            Value::Int(value) => Value::Int(value.checked_abs().ok_or(SoNError::IntegerOverflow)?),
+            Value::Float(value) => Value::Float(value.abs()),
+            value => return Err(type_mismatch("int or float", value)),
+        },
+        (NodeKind::Minus, [input]) => match *input {
+            Value::Int(value) => Value::Int(value.wrapping_neg()),
+            Value::Float(value) => Value::Float(-value),
+            value => return Err(type_mismatch("int or float", value)),
+        },
+        (NodeKind::Not, [input]) => match *input {
+            Value::Int(value) => Value::Int(!value),
+            Value::Bool(value) => Value::Bool(!value),
+            value => return Err(type_mismatch("int or bool", value)),
+        },
+        (NodeKind::Cast { to }, [input]) => match (to, *input) {
+            (Typ::IntBot, Value::Bool(value)) => Value::Int(value as i64),
+            (Typ::BoolBot, Value::Int(value)) => Value::Bool(value != 0),
+            (_, value @ (Value::Int(_) | Value::Bool(_))) => value,
+            (_, value) => return Err(type_mismatch("int or bool", value)),
+        },
+        (_, [lhs, rhs]) => evaluate_binary(node_kind, *lhs, *rhs)?,
+        _ => panic!("{:?} with {} inputs", node_kind, inputs.len()),
+    })
+}
+
+fn condition(value: Value) -> Result<bool, SoNError> {
+    match value {
+        Value::Bool(value) => Ok(value),
+        Value::Int(value) => Ok(value != 0),
+        value => Err(type_mismatch("int or bool", value)),
+    }
+}
+
+fn type_mismatch(expected: &str, value: Value) -> SoNError {
+    SoNError::TypeMismatch { variable: None, expected: expected.into(), but_got: format!("{:?}", value) }
+}
+
+fn evaluate_binary(node_kind: &NodeKind, lhs: Value, rhs: Value) -> Result<Value, SoNError> {
+    use Value::{Bool, Float, Int};
+    Ok(match (node_kind, lhs, rhs) {
+        (NodeKind::Add, Int(l), Int(r)) => Int(l.wrapping_add(r)),
+        (NodeKind::Sub, Int(l), Int(r)) => Int(l.wrapping_sub(r)),
+        (NodeKind::Mul, Int(l), Int(r)) => Int(l.wrapping_mul(r)),
+        (NodeKind::Div, Int(_), Int(0)) => return Err(SoNError::DivisionByZero),
+        (NodeKind::Div, Int(l), Int(r)) => Int(l.wrapping_div(r)),
+        (NodeKind::AddSat, Int(l), Int(r)) => Int(l.saturating_add(r)),
+        (NodeKind::SubSat, Int(l), Int(r)) => Int(l.saturating_sub(r)),
+        (NodeKind::MulSat, Int(l), Int(r)) => Int(l.saturating_mul(r)),
+        (NodeKind::Pow, Int(l), Int(r)) => Int(u32::try_from(r).ok().and_then(|r| l.checked_pow(r)).ok_or(SoNError::PowOutOfRange)?),
+        (NodeKind::Add | NodeKind::AddSat, Float(l), Float(r)) => Float(l + r),
+        (NodeKind::Sub | NodeKind::SubSat, Float(l), Float(r)) => Float(l - r),
+        (NodeKind::Mul | NodeKind::MulSat, Float(l), Float(r)) => Float(l * r),
+        (NodeKind::Div, Float(l), Float(r)) => Float(l / r),
+        (NodeKind::Pow, Float(l), Float(r)) => Float(l.powf(r)),
+        (NodeKind::Min, Int(l), Int(r)) => Int(l.min(r)),
+        (NodeKind::Max, Int(l), Int(r)) => Int(l.max(r)),
+        (NodeKind::Min, Float(l), Float(r)) => Float(l.min(r)),
+        (NodeKind::Max, Float(l), Float(r)) => Float(l.max(r)),
+        (NodeKind::Comp { kind }, Int(l), Int(r)) => match kind {
+            CompNodeKind::LT => Bool(l < r),
+            CompNodeKind::LEQ => Bool(l <= r),
+            CompNodeKind::EQ => Bool(l == r),
+            CompNodeKind::LogAnd => Int(l & r),
+            CompNodeKind::LogOr => Int(l | r),
+            CompNodeKind::LogXor => Int(l ^ r),
+        },
+        (NodeKind::Comp { kind }, Float(l), Float(r)) => match kind {
+            CompNodeKind::LT => Bool(l < r),
+            CompNodeKind::LEQ => Bool(l <= r),
+            CompNodeKind::EQ => Bool(l == r),
+            _ => return Err(type_mismatch("int or bool", Float(l))),
+        },
+        (NodeKind::Comp { kind }, Bool(l), Bool(r)) => match kind {
+            CompNodeKind::EQ => Bool(l == r),
+            CompNodeKind::LogAnd => Bool(l && r),
+            CompNodeKind::LogOr => Bool(l || r),
+            CompNodeKind::LogXor => Bool(l ^ r),
+            _ => return Err(type_mismatch("int or float", Bool(l))),
+        },
+        (_, lhs, rhs) => return Err(SoNError::TypeMismatch { variable: None, expected: format!("{:?}", lhs), but_got: format!("{:?}", rhs) }),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::errors::son_error::SoNError;
+    use crate::nodes::node::{CompNodeKind, NodeKind};
+    use crate::services::evaluator::MAX_CALL_DEPTH;
+    use crate::services::parser::{Parser, ParserBuilder, START_NID};
+    use crate::typ::typ::Typ;
+
+    #[test]
+    fn should_evaluate_with_arg() {
+        // Arrange
+        let mut parser = Parser::new_noarg("int a=arg*arg; return a+1 < 50 & arg != 3;").unwrap();
+        parser.parse().unwrap();
+
+        // Act & Assert
+        assert!(matches!(parser.evaluate_i64(&[6]), Ok(1)));
+        assert!(matches!(parser.evaluate_i64(&[7]), Ok(0)));
+        assert!(matches!(parser.evaluate_i64(&[3]), Ok(0)));
+    }
+
+    #[test]
+    fn should_evaluate_bool_program_to_bool() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return 1<2;").unwrap();
+        parser.parse().unwrap();
+
+        // Act
+        let result = parser.evaluate_args(&[0]);
+
+        // Assert
+        assert!(matches!(result, Ok(Typ::Bool { constant: true })));
+    }
+
+    #[test]
+    fn should_evaluate_unoptimized_graph() {
+        // Arrange
+        let mut parser = ParserBuilder::new().do_optimize(false).build_noarg("return (arg+2)*3/-arg;").unwrap();
+        parser.parse().unwrap();
+
+        // Act
+        let result = parser.evaluate_i64(&[4]);
+
+        // Assert
+        assert!(matches!(result, Ok(-4)));
+    }
+
+    #[test]
+    fn should_evaluate_taken_branch_of_if_expression() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return if(arg < 3) arg * 2 else -arg;").unwrap();
+        parser.parse().unwrap();
+
+        // Act & Assert
+        assert!(matches!(parser.evaluate_i64(&[2]), Ok(4)));
+        assert!(matches!(parser.evaluate_i64(&[5]), Ok(-5)));
+    }
+
+    #[test]
+    fn should_follow_control_through_nested_ifs() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return if(arg < 3) (if(arg < 1) 10 else 20) else 30;").unwrap();
+        parser.parse().unwrap();
+
+        // Act & Assert
+        assert!(matches!(parser.evaluate_i64(&[-1]), Ok(10)));
+        assert!(matches!(parser.evaluate_i64(&[2]), Ok(20)));
+        assert!(matches!(parser.evaluate_i64(&[5]), Ok(30)));
+    }
+
+    #[test]
+    fn should_follow_control_through_consecutive_ifs() {
+        // Arrange
+        let mut parser = Parser::new_noarg("int a = if(arg < 0) -arg else arg; return if(a < 3) a else 3;").unwrap();
+        parser.parse().unwrap();
+
+        // Act & Assert
+        assert!(matches!(parser.evaluate_i64(&[-2]), Ok(2)));
+        assert!(matches!(parser.evaluate_i64(&[-7]), Ok(3)));
+        assert!(matches!(parser.evaluate_i64(&[1]), Ok(1)));
+    }
+
+    #[test]
+    fn should_step_over_print() {
+        // Arrange
+        let mut parser = Parser::new_noarg("print(arg); int a = arg + 1; print(a); return a;").unwrap();
+        parser.parse().unwrap();
+
+        // Act
+        let result = parser.evaluate_i64(&[2]);
+
+        // Assert
+        assert!(matches!(result, Ok(3)));
+    }
+
+    #[test]
+    fn should_run_called_function() {
+        // Arrange
+        let mut parser = Parser::new_noarg("int f(int n) { return if(n < 1) 0 else f(n - 1) + 2; } return f(arg) + 1;").unwrap();
+        parser.parse().unwrap();
+
+        // Act
+        let result = parser.evaluate_i64(&[3]);
+
+        // Assert
+        assert!(matches!(result, Ok(7)));
+    }
+
+    /// the graph of `int i=0; while(i<limit) i=i+1; return i;`. The language has no loops yet, so it is built by hand.
+    fn counting_loop(limit: i64) -> Parser {
+        let mut parser = Parser::new_noarg("").unwrap();
+        let graph = &mut parser.graph;
+        let ctrl = graph.new_node(vec![START_NID], NodeKind::Proj { proj_index: 0, _dbg_proj_label: "$ctrl".into() }, Typ::Ctrl).unwrap();
+        let zero = graph.new_node(vec![], NodeKind::Constant, Typ::Int { constant: 0 }).unwrap();
+        let one = graph.new_node(vec![], NodeKind::Constant, Typ::Int { constant: 1 }).unwrap();
+        // the back edges start out as the entry and are set once the body exists
+        let region = graph.new_node(vec![ctrl, ctrl], NodeKind::Region, Typ::Ctrl).unwrap();
+        let i = graph.new_node(vec![region, zero, zero], NodeKind::Phi, Typ::IntBot).unwrap();
+        let next = graph.new_node(vec![i, one], NodeKind::Add, Typ::IntBot).unwrap();
+        graph.set_input(i, 2, next).unwrap();
+        let limit = graph.new_node(vec![], NodeKind::Constant, Typ::Int { constant: limit }).unwrap();
+        let cond = graph.new_node(vec![i, limit], NodeKind::Comp { kind: CompNodeKind::LT }, Typ::BoolBot).unwrap();
+        let iff = graph.new_node(vec![region, cond], NodeKind::If, Typ::Bot).unwrap();
+        let body = graph.new_node(vec![iff], NodeKind::Proj { proj_index: 0, _dbg_proj_label: "True".into() }, Typ::Ctrl).unwrap();
+        let exit = graph.new_node(vec![iff], NodeKind::Proj { proj_index: 1, _dbg_proj_label: "False".into() }, Typ::Ctrl).unwrap();
+        graph.set_input(region, 1, body).unwrap();
+        graph.new_node(vec![exit, i], NodeKind::Return, Typ::Bot).unwrap();
+        parser
+    }
+
+    #[test]
+    fn should_loop_on_back_edge_until_return() {
+        // Arrange
+        let parser = counting_loop(5);
+
+        // Act
+        let result = parser.evaluate_i64(&[0]);
+
+        // Assert
+        assert!(matches!(result, Ok(5)));
+    }
+
+    #[test]
+    fn should_stop_endless_loop_at_default_step_budget() {
+        // Arrange
+        let parser = counting_loop(i64::MAX);
+
+        // Act
+        let result = parser.evaluate_i64(&[0]);
+
+        // Assert
+        assert!(matches!(result, Err(SoNError::StepBudgetExceeded)));
+    }
+
+    #[test]
+    fn should_stop_endless_recursion_at_call_depth() {
+        // Arrange
+        let mut parser = Parser::new_noarg("int f(int x){ return f(x)+1; } return f(1);").unwrap();
+        parser.parse().unwrap();
+
+        // Act
+        let result = parser.evaluate_args(&[0]);
+
+        // Assert
+        assert!(matches!(result, Err(SoNError::ExecutionLimitExceeded)));
+    }
+
+    #[test]
+    fn should_run_calls_up_to_call_depth() {
+        // Arrange
+        let src = format!("int f(int n) {{ return if(n < 1) 0 else f(n - 1) + 1; }} return f({});", MAX_CALL_DEPTH - 1);
+        let mut parser = Parser::new_noarg(&src).unwrap();
+        parser.parse().unwrap();
+
+        // Act
+        let result = parser.evaluate_i64(&[0]);
+
+        // Assert
+        assert!(matches!(result, Ok(value) if value == MAX_CALL_DEPTH as i64 - 1));
+    }
+
+    #[test]
+    fn should_reject_memory_instead_of_panicking() {
+        // Arrange
+        let mut parser = Parser::new_noarg("int[] a = new int[arg]; a[arg - 1] = 2; return a[0];").unwrap();
+        parser.parse().unwrap();
+
+        // Act
+        let result = parser.evaluate_i64(&[1]);
+
+        // Assert
+        assert!(matches!(result, Err(SoNError::NotEvaluable { .. })));
+    }
+
+    #[test]
+    fn should_evaluate_with_multiple_args() {
+        // Arrange
+        let params = vec![("arg0".into(), Typ::IntBot), ("arg1".into(), Typ::IntBot)];
+        let mut parser = ParserBuilder::new().params(params).build_noarg("return arg0*arg1;").unwrap();
+        parser.parse().unwrap();
+
+        // Act
+        let result = parser.evaluate_i64(&[6, 7]);
+
+        // Assert
+        assert!(matches!(result, Ok(42)));
+    }
+
+    #[test]
+    fn should_reject_wrong_argument_count() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return arg;").unwrap();
+        parser.parse().unwrap();
+
+        // Act
+        let result = parser.evaluate_i64(&[1, 2]);
+
+        // Assert
+        assert!(matches!(result, Err(SoNError::ArgumentCountMismatch { expected: 1, but_got: 2 })));
+    }
+
+    #[test]
+    fn should_reject_division_by_zero() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return 1/arg;").unwrap();
+        parser.parse().unwrap();
+
+        // Act
+        let result = parser.evaluate_i64(&[0]);
+
+        // Assert
+        assert!(matches!(result, Err(SoNError::DivisionByZero)));
+    }
+
+    #[test]
+    fn should_finish_within_step_budget() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return arg*arg+1;").unwrap();
+        parser.parse().unwrap();
+
+        // Act
+        let result = parser.evaluate_with_budget(&[3], 16);
+
+        // Assert
+        assert!(matches!(result, Ok(Typ::Int { constant: 10 })));
+    }
+
+    #[test]
+    fn should_stop_when_step_budget_is_exceeded() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return arg*arg+1;").unwrap();
+        parser.parse().unwrap();
+
+        // Act
+        let result = parser.evaluate_with_budget(&[3], 2);
+
+        // Assert
+        assert!(matches!(result, Err(SoNError::StepBudgetExceeded)));
+    }
+
+    #[test]
+    fn should_trace_evaluated_nodes_in_order() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return arg+1;").unwrap();
+        parser.parse().unwrap();
+
+        // Act
+        let (result, trace) = parser.evaluate_traced(1);
+
+        // Assert
+        assert!(matches!(result, Ok(Typ::Int { constant: 2 })));
+        let kinds = trace.iter()
+            .map(|(nid, typ)| (parser.graph.get_node(*nid).unwrap().node_kind.clone(), typ.clone()))
+            .collect::<Vec<_>>();
+        assert!(matches!(&kinds[..], [
+            (NodeKind::Proj { .. }, Typ::Int { constant: 1 }),
+            (NodeKind::Constant, Typ::Int { constant: 1 }),
+            (NodeKind::Add, Typ::Int { constant: 2 }),
+            (NodeKind::Return, Typ::Int { constant: 2 }),
+        ]));
+    }
+}
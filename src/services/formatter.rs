@@ -0,0 +1,189 @@
+use crate::ast::expr::Expr;
+use crate::ast::stmt::Stmt;
+use crate::errors::son_error::SoNError;
+use crate::services::operators::{Associativity, PREFIX_PRECEDENCE};
+use crate::services::parser::Parser;
+use crate::typ::typ::Typ;
+
+pub(crate) const INDENT: &str = "    ";
+
+/// Formats the program canonically: one statement per line, spaces around binary operators
+/// and parens only where precedence requires them. Formatting is idempotent.
+pub fn format(src: &str) -> Result<String, SoNError> {
+    let program = Parser::new_noarg(src)?.parse_ast()?;
+    let mut lines = vec![];
+    for stmt in &program {
+        format_stmt(stmt, 0, &mut lines);
+    }
+    Ok(lines.join("\n"))
+}
+
+fn format_stmt(stmt: &Stmt, depth: usize, lines: &mut Vec<String>) {
+    let indent = INDENT.repeat(depth);
+    match stmt {
+        Stmt::Decl { declared_typ, name, value, is_const, .. } => {
+            let qualifier = if *is_const { "const " } else { "" };
+            lines.push(format!("{}{}{} {} = {};", indent, qualifier, typ_keyword(declared_typ), name, format_expr(value)))
+        }
+        Stmt::Assign { name, value, .. } => lines.push(format!("{}{} = {};", indent, name, format_expr(value))),
+        Stmt::IndexAssign { array, index, value, .. } => lines.push(format!("{}{}[{}] = {};", indent, format_operand(array, u8::MAX), format_expr(index), format_expr(value))),
+        Stmt::FieldAssign { object, field, value, .. } => lines.push(format!("{}{}.{} = {};", indent, format_operand(object, u8::MAX), field, format_expr(value))),
+        Stmt::Struct { typ: Typ::Struct { name, fields }, .. } => {
+            lines.push(format!("{}struct {} {{", indent, name));
+            for (field, typ) in fields {
+                lines.push(format!("{}{}{} {};", indent, INDENT, typ_keyword(typ), field));
+            }
+            lines.push(format!("{}}}", indent));
+        }
+        Stmt::Struct { typ, .. } => panic!("Typ {:?} is not a struct", typ),
+        Stmt::Function { ret_typ, name, params, body, .. } => {
+            let params = params.iter().map(|(param, typ)| format!("{} {}", typ_keyword(typ), param)).collect::<Vec<_>>().join(", ");
+            lines.push(format!("{}{} {}({}) {{", indent, typ_keyword(ret_typ), name, params));
+            for stmt in body {
+                format_stmt(stmt, depth + 1, lines);
+            }
+            lines.push(format!("{}}}", indent));
+        }
+        Stmt::Return { value, .. } => lines.push(format!("{}return {};", indent, format_expr(value))),
+        Stmt::Print { value, .. } => lines.push(format!("{}print({});", indent, format_expr(value))),
+        Stmt::Block { stmts, .. } => {
+            lines.push(format!("{}{{", indent));
+            for stmt in stmts {
+                format_stmt(stmt, depth + 1, lines);
+            }
+            lines.push(format!("{}}}", indent));
+        }
+        Stmt::ShowGraph { .. } => lines.push(format!("{}#showGraph;", indent)),
+        Stmt::Empty { .. } => {}
+    }
+}
+
+pub(crate) fn typ_keyword(typ: &Typ) -> String {
+    match typ {
+        Typ::IntBot => "int".into(),
+        Typ::BoolBot => "bool".into(),
+        Typ::Struct { name, .. } => name.clone(),
+        Typ::Array { elem, .. } => format!("{}[]", typ_keyword(elem)),
+        _ => panic!("Typ {:?} has no keyword", typ),
+    }
+}
+
+fn precedence(expr: &Expr) -> u8 {
+    match expr {
+        Expr::Binary { operator, .. } => operator.precedence,
+        Expr::Minus { .. } | Expr::Not { .. } => PREFIX_PRECEDENCE,
+        // the else branch extends as far as possible
+        Expr::If { .. } => 0,
+        Expr::Number { .. } | Expr::Float { .. } | Expr::Bool { .. } | Expr::Identifier { .. } | Expr::Cast { .. } | Expr::New { .. } | Expr::Field { .. } | Expr::Call { .. } | Expr::NewArray { .. } | Expr::Index { .. } => u8::MAX,
+    }
+}
+
+/// formats the operand and only wraps it in parens if it binds weaker than `min_precedence`
+fn format_operand(expr: &Expr, min_precedence: u8) -> String {
+    if precedence(expr) < min_precedence {
+        format!("({})", format_expr(expr))
+    } else {
+        format_expr(expr)
+    }
+}
+
+fn format_expr(expr: &Expr) -> String {
+    match expr {
+        Expr::Number { value, .. } => value.to_string(),
+        // debug formatting keeps the fraction of whole floats, so they don't turn into ints
+        Expr::Float { value, .. } => format!("{:?}", value),
+        Expr::Bool { value, .. } => value.to_string(),
+        Expr::If { cond, then, otherwise, .. } => format!("if ({}) {} else {}", format_expr(cond), format_expr(then), format_expr(otherwise)),
+        Expr::Identifier { name, .. } => name.clone(),
+        Expr::Minus { operand, .. } => format!("-{}", format_operand(operand, PREFIX_PRECEDENCE)),
+        Expr::Not { operand, .. } => format!("!{}", format_operand(operand, PREFIX_PRECEDENCE)),
+        Expr::Cast { to, operand, .. } => format!("{}({})", typ_keyword(to), format_expr(operand)),
+        Expr::New { typ, .. } => format!("new {}", typ_keyword(typ)),
+        Expr::NewArray { elem, len, .. } => format!("new {}[{}]", typ_keyword(elem), format_expr(len)),
+        Expr::Index { array, index, .. } => format!("{}[{}]", format_operand(array, u8::MAX), format_expr(index)),
+        Expr::Call { name, args, .. } => format!("{}({})", name, args.iter().map(format_expr).collect::<Vec<_>>().join(", ")),
+        Expr::Field { object, field, .. } => format!("{}.{}", format_operand(object, u8::MAX), field),
+        Expr::Binary { operator, lhs, rhs, .. } => {
+            let (lhs_precedence, rhs_precedence) = match operator.associativity {
+                Associativity::Left => (operator.precedence, operator.precedence + 1),
+                Associativity::Right => (operator.precedence + 1, operator.precedence),
+            };
+            format!("{} {} {}", format_operand(lhs, lhs_precedence), operator.lexeme, format_operand(rhs, rhs_precedence))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_format_spacing_and_parens() {
+        // Act
+        let formatted = format("return 1+2 *3 ;").unwrap();
+
+        // Assert
+        assert_eq!("return 1 + 2 * 3;", formatted);
+        assert_eq!(formatted, format(&formatted).unwrap());
+    }
+
+    #[test]
+    fn should_format_one_statement_per_line() {
+        // Act
+        let formatted = format("int a=((arg-1))-(2-3); {bool b=!(a<2); a=-a;} return int(a!=1);").unwrap();
+
+        // Assert
+        assert_eq!("int a = arg - 1 - (2 - 3);\n{\n    bool b = !(a < 2);\n    a = -a;\n}\nreturn int(a != 1);", formatted);
+        assert_eq!(formatted, format(&formatted).unwrap());
+    }
+
+    #[test]
+    fn should_format_functions() {
+        // Act
+        let formatted = format("int add(int a,bool b){return a+int(b);} return add(1,2<3);").unwrap();
+
+        // Assert
+        assert_eq!("int add(int a, bool b) {\n    return a + int(b);\n}\nreturn add(1, 2 < 3);", formatted);
+        assert_eq!(formatted, format(&formatted).unwrap());
+    }
+
+    #[test]
+    fn should_format_arrays() {
+        // Act
+        let formatted = format("bool[] a=new bool[arg+1]; a[ 0 ]=(new bool[2])[1]; return a[0];").unwrap();
+
+        // Assert
+        assert_eq!("bool[] a = new bool[arg + 1];\na[0] = new bool[2][1];\nreturn a[0];", formatted);
+        assert_eq!(formatted, format(&formatted).unwrap());
+    }
+
+    #[test]
+    fn should_format_if_expressions() {
+        // Act
+        let formatted = format("return 1+(if(arg)if(arg<2)1 else 2 else(bool)arg+(int)true);").unwrap();
+
+        // Assert
+        assert_eq!("return 1 + (if (arg) if (arg < 2) 1 else 2 else bool(arg) + int(true));", formatted);
+        assert_eq!(formatted, format(&formatted).unwrap());
+    }
+
+    #[test]
+    fn should_format_const_declarations() {
+        // Act
+        let formatted = format("const   bool b=arg<1; return b;").unwrap();
+
+        // Assert
+        assert_eq!("const bool b = arg < 1;\nreturn b;", formatted);
+        assert_eq!(formatted, format(&formatted).unwrap());
+    }
+
+    #[test]
+    fn should_format_structs() {
+        // Act
+        let formatted = format("struct P{int x;bool b;} P p=new P; p.x=(new P).x+1; return p.x;").unwrap();
+
+        // Assert
+        assert_eq!("struct P {\n    int x;\n    bool b;\n}\nP p = new P;\np.x = new P.x + 1;\nreturn p.x;", formatted);
+        assert_eq!(formatted, format(&formatted).unwrap());
+    }
+}
@@ -0,0 +1,209 @@
+use crate::errors::son_error::SoNError;
+use crate::nodes::node::NodeKind;
+use crate::services::parser::{Parser, FIRST_ARG_PROJ_INDEX};
+use crate::typ::typ::Typ;
+use std::collections::HashMap;
+
+impl Parser {
+    /// Replaces calls of side effect free functions with at most [max_inline_nodes](crate::services::parser::ParserOptions::max_inline_nodes) nodes by a copy
+    /// of the callee's returned value. The copy is peepholed with the arguments in place of the
+    /// parameters, which folds across the call. A callee that prints or calls, e.g. a recursive one,
+    /// returns behind that control and is never inlined.
+    /// Returns the number of inlined calls.
+    pub fn inline_calls(&mut self) -> Result<usize, SoNError> {
+        let max_callee_nodes = self.max_inline_nodes;
+        // nothing keeps the returns of a parsed program alive
+        let returns: Vec<usize> = self.nodes().filter(|n| matches!(n.node_kind, NodeKind::Return)).map(|n| n.nid).collect();
+        self.with_monotonicity_check(|parser| parser.with_kept_nodes(&returns, |parser| {
+            let call_ends: Vec<usize> = parser.nodes().filter(|n| matches!(n.node_kind, NodeKind::CallEnd)).map(|n| n.nid).collect();
+            let mut inlined = 0;
+            for call_end in call_ends {
+                if !parser.graph.node_exists(call_end) {
+                    continue;
+                }
+                let call = parser.graph.inputs_of(call_end)?[0];
+                let Some(value) = parser.inlinable_value(call, max_callee_nodes)? else {
+                    continue;
+                };
+                let cloned = parser.clone_into_call(call, value)?;
+                parser.replace_call(call, call_end, cloned)?;
+                inlined += 1;
+            }
+            while parser.drop_unused_nodes() > 0 {}
+            Ok(inlined)
+        }))
+    }
+
+    /// the value returned by the callee of `call`, if the callee can be inlined
+    fn inlinable_value(&self, call: usize, max_callee_nodes: usize) -> Result<Option<usize>, SoNError> {
+        let NodeKind::Call { name, .. } = &self.graph.get_node(call)?.node_kind else {
+            panic!("CallEnd without Call")
+        };
+        let function = &self.functions[name];
+        let Some(ret) = function.ret.map(|ret| self.graph.get_node(ret)).transpose()? else {
+            return Ok(None);
+        };
+        if !matches!(ret.node_kind, NodeKind::Return) {
+            return Ok(None);
+        }
+        // without a print or call in between, the return is still controlled by the entry.
+        // This also rules out recursion, since the body can't contain a call.
+        let ctrl = self.graph.get_node(ret.inputs[0])?;
+        if !matches!(ctrl.node_kind, NodeKind::Proj { proj_index: 0, .. }) || ctrl.inputs[0] != function.entry {
+            return Ok(None);
+        }
+        let body = self.graph.reverse_postorder(ret.inputs[1]);
+        if body.len() > max_callee_nodes {
+            return Ok(None);
+        }
+        Ok(Some(ret.inputs[1]))
+    }
+
+    /// copies the nodes `value` depends on, with the projections of the callee's entry replaced by the inputs of `call`
+    fn clone_into_call(&mut self, call: usize, value: usize) -> Result<usize, SoNError> {
+        let call_inputs = self.graph.inputs_of(call)?.to_vec();
+        let entry = call_inputs[2];
+        let mut clones: HashMap<usize, usize> = HashMap::new();
+        // inputs before their users
+        for nid in self.graph.reverse_postorder(value).into_iter().rev() {
+            let node = self.graph.get_node(nid)?.clone();
+            let clone = match node.node_kind {
+                // control, memory and then the arguments, which follow the entry in the inputs of the call
+                NodeKind::Proj { proj_index, .. } if node.inputs[0] == entry => call_inputs[if proj_index < FIRST_ARG_PROJ_INDEX { proj_index } else { proj_index + 1 }],
+                _ => {
+                    let inputs = node.inputs.iter().map(|i| clones[i]).collect();
+                    let typ = if node.typ() == Typ::Ctrl { Typ::Bot } else { node.typ() };
+                    self.add_node_spanned(inputs, node.node_kind.clone(), typ, node.span)?
+                }
+            };
+            // nothing uses the clones until the copy is complete
+            self.keep_node(clone)?;
+            clones.insert(nid, clone);
+        }
+        for &clone in clones.values() {
+            self.unkeep_node(clone)?;
+        }
+        Ok(clones[&value])
+    }
+
+    /// makes the users of the call use its control and memory inputs and the users of the CallEnd use `value`
+    fn replace_call(&mut self, call: usize, call_end: usize, value: usize) -> Result<(), SoNError> {
+        self.keep_node(value)?;
+        for user in self.graph.users_of(call_end)?.to_vec() {
+            self.replace_inputs(user, call_end, |_| value)?;
+        }
+        let (ctrl, mem) = (self.graph.inputs_of(call)?[0], self.graph.inputs_of(call)?[1]);
+        for user in self.graph.users_of(call)?.to_vec() {
+            let kind = self.graph.get_node(user)?.node_kind.clone();
+            self.replace_inputs(user, call, |index| match (&kind, index) {
+                (NodeKind::Load { .. } | NodeKind::Store { .. }, 0) | (NodeKind::Print | NodeKind::Call { .. }, 1) => mem,
+                _ => ctrl,
+            })?;
+        }
+        self.unkeep_node(value)
+    }
+
+    fn replace_inputs(&mut self, user: usize, old: usize, new: impl Fn(usize) -> usize) -> Result<(), SoNError> {
+        if matches!(self.graph.get_node(user)?.node_kind, NodeKind::CallEnd) {
+            return Ok(());
+        }
+        for index in 0..self.graph.inputs_of(user)?.len() {
+            if self.graph.inputs_of(user)?[index] == old {
+                self.graph.set_input(user, index, new(index))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::nodes::bound_node::BoundNode;
+    use crate::nodes::node::NodeKind;
+    use crate::services::parser::{Parser, ParserBuilder};
+
+    #[test]
+    fn should_fold_inlined_call() {
+        // Arrange
+        let mut parser = Parser::new_noarg("int sq(int x){ return x*x; } return sq(5);").unwrap();
+        let result = parser.parse().unwrap();
+
+        // Act
+        let inlined = parser.inline_calls().unwrap();
+
+        // Assert
+        assert_eq!(1, inlined);
+        assert_eq!("return 25;", format!("{:}", BoundNode::new(parser.node(result).unwrap(), &parser.graph)));
+    }
+
+    #[test]
+    fn should_span_inlined_nodes_in_the_callee() {
+        // Arrange
+        let src = "int add(int a, int b){ return a-b; } return add(arg, 2);";
+        let mut parser = Parser::new_noarg(src).unwrap();
+        let result = parser.parse().unwrap();
+
+        // Act
+        parser.inline_calls().unwrap();
+
+        // Assert
+        let sub = parser.node(parser.graph.inputs_of(result).unwrap()[1]).unwrap();
+        assert!(matches!(sub.node_kind, NodeKind::Sub));
+        assert_eq!("a-b", &src[sub.span.0..sub.span.1]);
+    }
+
+    #[test]
+    fn should_fold_call_of_increment() {
+        // Arrange
+        let mut parser = Parser::new_noarg("int f(int x){return x+1;} return f(41);").unwrap();
+        let result = parser.parse().unwrap();
+
+        // Act
+        parser.inline_calls().unwrap();
+
+        // Assert
+        assert_eq!("return 42;", format!("{:}", BoundNode::new(parser.node(result).unwrap(), &parser.graph)));
+    }
+
+    #[test]
+    fn should_inline_with_non_constant_arguments() {
+        // Arrange
+        let mut parser = Parser::new_noarg("int add(int a, int b){ return a-b; } return add(arg, 2);").unwrap();
+        let result = parser.parse().unwrap();
+
+        // Act
+        let inlined = parser.inline_calls().unwrap();
+
+        // Assert
+        assert_eq!(1, inlined);
+        assert_eq!("return arg-2;", format!("{:}", BoundNode::new(parser.node(result).unwrap(), &parser.graph)));
+    }
+
+    #[test]
+    fn should_not_inline_large_functions() {
+        // Arrange
+        let mut parser = ParserBuilder::new().max_inline_nodes(1).build_noarg("int sq(int x){ return x*x; } return sq(2);").unwrap();
+        let result = parser.parse().unwrap();
+
+        // Act
+        let inlined = parser.inline_calls().unwrap();
+
+        // Assert
+        assert_eq!(0, inlined);
+        assert_eq!("return sq(2);", format!("{:}", BoundNode::new(parser.node(result).unwrap(), &parser.graph)));
+    }
+
+    #[test]
+    fn should_not_inline_recursive_functions() {
+        // Arrange
+        let mut parser = ParserBuilder::new().max_inline_nodes(1000).build_noarg("int f(int x){ return f(x)+1; } return f(1);").unwrap();
+        let result = parser.parse().unwrap();
+
+        // Act
+        let inlined = parser.inline_calls().unwrap();
+
+        // Assert
+        assert_eq!(0, inlined);
+        assert_eq!("return f(1);", format!("{:}", BoundNode::new(parser.node(result).unwrap(), &parser.graph)));
+    }
+}
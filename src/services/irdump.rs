@@ -0,0 +1,43 @@
+use crate::nodes::node::node_icon;
+use crate::services::parser::Parser;
+
+impl Parser {
+    /// Dumps every live node on its own line as `%nid = Kind(inputs) : Typ`, ordered by nid.
+    /// This is easier to grep and more stable for snapshot tests than the dotfile.
+    pub fn dump_ir(&self) -> String {
+        let mut sb = String::new();
+        for n in self.graph.graph_iter() {
+            let inputs = n.inputs.iter().map(|i| format!("%{}", i)).collect::<Vec<_>>().join(", ");
+            sb.push_str(&format!("%{} = {}({}) : {:?}\n", n.nid, node_icon(n), inputs, n.typ()));
+        }
+        sb
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::nodes::node::NodeKind;
+    use crate::services::parser::{ParserBuilder, START_NID};
+
+    #[test]
+    fn should_dump_one_plus_one() {
+        // Arrange
+        let mut parser = ParserBuilder::new().do_optimize(false).build_noarg("return 1+1;").unwrap();
+        let ret = parser.parse().unwrap();
+        let ret_node = parser.graph.get_node(ret).unwrap();
+        let (ctrl, add) = (ret_node.inputs[0], ret_node.inputs[1]);
+        let add_node = parser.graph.get_node(add).unwrap();
+        let (lhs, rhs) = (add_node.inputs[0], add_node.inputs[1]);
+
+        // Act
+        let dump = parser.dump_ir();
+
+        // Assert
+        assert!(dump.contains(&format!("%{} = #1() : Int {{ constant: 1 }}\n", lhs)));
+        assert!(dump.contains(&format!("%{} = #1() : Int {{ constant: 1 }}\n", rhs)));
+        assert!(dump.contains(&format!("%{} = +(%{}, %{}) : Int {{ constant: 2 }}\n", add, lhs, rhs)));
+        assert!(dump.contains(&format!("%{} = Return(%{}, %{}) : Bot\n", ret, ctrl, add)));
+        assert!(dump.contains(&format!("%{} = $ctrl(%{}) : Ctrl\n", ctrl, START_NID)));
+        assert_eq!(parser.graph.graph_iter().filter(|n| matches!(n.node_kind, NodeKind::Constant)).count(), 1);
+    }
+}
@@ -1,9 +1,28 @@
 use crate::errors::son_error::SoNError;
 use std::fmt::{Display, Formatter};
+use std::io::Read;
+
+#[derive(Debug, PartialEq)]
+pub enum NumberLiteral {
+    Int(i64),
+    Float(f64),
+}
+
+/// A token as seen by lookahead. Operators are split into their single characters.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Token {
+    Identifier(String),
+    Number(String),
+    Symbol(char),
+}
 
 pub struct Lexer {
     pub input: String,
     position: usize,
+    /// tokens peeked from `lookahead_position`. The buffer ends at `lookahead_end`.
+    lookahead: Vec<Token>,
+    lookahead_position: usize,
+    lookahead_end: usize,
 }
 
 impl Display for Lexer {
@@ -16,8 +35,19 @@ impl Display for Lexer {
 }
 
 impl Lexer {
-    pub fn from_string(input: String) -> Lexer {
-        Lexer { input, position: 0 }
+    /// A leading UTF-8 byte order mark is dropped, so positions and columns start at the first character after it.
+    pub fn from_string(mut input: String) -> Lexer {
+        if input.starts_with('\u{FEFF}') {
+            input.drain(..'\u{FEFF}'.len_utf8());
+        }
+        Lexer { input, position: 0, lookahead: vec![], lookahead_position: 0, lookahead_end: 0 }
+    }
+
+    /// reads all of `reader`, which has to be UTF-8
+    pub fn from_reader(mut reader: impl Read) -> Result<Lexer, std::io::Error> {
+        let mut input = String::new();
+        reader.read_to_string(&mut input)?;
+        Ok(Lexer::from_string(input))
     }
 
     pub fn from_str(input: &str) -> Lexer {
@@ -28,12 +58,20 @@ impl Lexer {
         self.position
     }
 
+    pub fn set_position(&mut self, position: usize) {
+        self.position = position;
+    }
+
     pub fn dbg_position(&self) -> Option<(usize, usize)> {
         self.line_col_for(self.position())
     }
 
     pub fn dbg_position_string(&self) -> String {
-        if let Some((line, column)) = self.dbg_position() {
+        self.dbg_position_string_at(self.position())
+    }
+
+    pub fn dbg_position_string_at(&self, position: usize) -> String {
+        if let Some((line, column)) = self.line_col_for(position) {
             format!("{}:{}", line, column)
         } else {
             "out of bounds".into()
@@ -41,38 +79,101 @@ impl Lexer {
     }
 
     pub fn line_col_for(&self, position: usize) -> Option<(usize, usize)> {
-        let mut line_start = 0;
-        for (line_number, line) in self.input.lines().enumerate() {
-            let line_len = line.len();
-            if position <= line_start + line_len {
+        for (line_number, (line_start, line)) in self.source_lines().enumerate() {
+            if position <= line_start + line.len() {
                 let column = position - line_start;
                 return Some((line_number + 1, column + 1)); // 1-based
             }
-            // '\n'
-            line_start += line_len + 1;
         }
         None
     }
 
+    /// The lines of the input with the position they start at. Lines end at `\r\n`, `\n` or a lone `\r`,
+    /// which is not part of the line. Like [str::lines] there is no empty line after a final line ending.
+    pub fn source_lines(&self) -> impl Iterator<Item=(usize, &str)> {
+        let mut rest = self.input.as_str();
+        let mut line_start = 0;
+        std::iter::from_fn(move || {
+            if rest.is_empty() {
+                return None;
+            }
+            let (line_len, ending_len) = match rest.find(['\r', '\n']) {
+                Some(i) if rest[i..].starts_with("\r\n") => (i, 2),
+                Some(i) => (i, 1),
+                None => (rest.len(), 0),
+            };
+            let line = (line_start, &rest[..line_len]);
+            rest = &rest[line_len + ending_len..];
+            line_start += line_len + ending_len;
+            Some(line)
+        })
+    }
+
 
     pub fn is_eof(&self) -> bool { self.position >= self.input.len() }
 
-    pub fn peek(&self) -> Option<char> { self.input.chars().nth(self.position) }
+    /// The char starting at the current byte position. Positions are byte offsets and always stay on a char boundary.
+    pub fn peek(&self) -> Option<char> { self.input.get(self.position..)?.chars().next() }
 
     pub fn next_char(&mut self) -> Option<char> {
-        self.peek().inspect(|_| self.position += 1)
+        self.peek().inspect(|c| self.position += c.len_utf8())
+    }
+
+    /// The current position before any whitespace a failed match skipped, i.e. the end of the last consumed token
+    /// unless a comment follows it.
+    pub fn position_before_whitespace(&self) -> usize {
+        self.input[..self.position].trim_end().len()
     }
 
     pub fn is_whitespace(&self) -> bool {
         self.peek().map(|c| c.is_whitespace()).unwrap_or(false)
     }
 
+    /// skips whitespace and block comments. An unterminated comment is not skipped.
     pub fn skip_whitespace(&mut self) {
-        while self.is_whitespace() {
-            self.next_char();
+        loop {
+            while self.is_whitespace() {
+                self.next_char();
+            }
+            match self.block_comment_len() {
+                Some(len) => self.position += len,
+                None => return,
+            }
         }
     }
 
+    /// Byte length of the block comment starting at the current position, `/* a /* b */ c */` included.
+    /// None, if there is no comment or it is unterminated.
+    fn block_comment_len(&self) -> Option<usize> {
+        let rest = self.input.get(self.position..).unwrap_or("").as_bytes();
+        if !rest.starts_with(b"/*") {
+            return None;
+        }
+        let mut depth = 0;
+        let mut i = 0;
+        while i + 1 < rest.len() {
+            match &rest[i..i + 2] {
+                b"/*" => {
+                    depth += 1;
+                    i += 2;
+                }
+                b"*/" => {
+                    depth -= 1;
+                    i += 2;
+                    if depth == 0 {
+                        return Some(i);
+                    }
+                }
+                _ => i += 1,
+            }
+        }
+        None
+    }
+
+    pub fn is_at_unterminated_comment(&self) -> bool {
+        self.input.get(self.position..).is_some_and(|rest| rest.starts_with("/*")) && self.block_comment_len().is_none()
+    }
+
     /// Does NOT change self.
     pub fn peek_matsch(&mut self, syntax: &str) -> bool {
         let prev_position = self.position;
@@ -100,7 +201,7 @@ impl Lexer {
     /// Return false otherwise, and do not advance the cursor.
     pub fn matsch(&mut self, syntax: &str) -> bool {
         self.skip_whitespace();
-        if self.input[self.position..].starts_with(syntax) {
+        if self.input.is_char_boundary(self.position) && self.input[self.position..].starts_with(syntax) {
             self.position += syntax.len();
             true
         } else {
@@ -120,6 +221,36 @@ impl Lexer {
     }
 
 
+    /// Peeks the token `n` tokens ahead, where 0 is the next token, without moving the cursor.
+    /// Returns None at the end of the input.
+    pub fn peek_nth_token(&mut self, n: usize) -> Option<Token> {
+        if self.lookahead_position != self.position || self.lookahead.is_empty() {
+            self.lookahead.clear();
+            self.lookahead_position = self.position;
+            self.lookahead_end = self.position;
+        }
+        let position = self.position;
+        while self.lookahead.len() <= n {
+            self.position = self.lookahead_end;
+            self.skip_whitespace();
+            let Some(ch) = self.peek() else {
+                break;
+            };
+            let token = if Lexer::is_id_start(&ch) {
+                Token::Identifier(self.parse_id())
+            } else if Lexer::is_number(&ch) {
+                Token::Number(self.parse_number_string())
+            } else {
+                self.position += ch.len_utf8();
+                Token::Symbol(ch)
+            };
+            self.lookahead.push(token);
+            self.lookahead_end = self.position;
+        }
+        self.position = position;
+        self.lookahead.get(n).cloned()
+    }
+
     // Used for errors
     pub fn dbg_get_any_next_token(&mut self) -> String {
         if self.is_eof() {
@@ -140,20 +271,70 @@ impl Lexer {
         ch.to_string()
     }
 
+    /// The byte range of the token at `position`, without skipping whitespace first. Does NOT change self.
+    pub fn token_span_at(&mut self, position: usize) -> (usize, usize) {
+        let prev_position = self.position;
+        self.position = position;
+        let token = self.dbg_get_any_next_token();
+        self.position = prev_position;
+        (position, position + token.len())
+    }
+
+    /// Does NOT change self. Errors capture the position of the offending token, so it must not be consumed.
+    pub fn dbg_peek_any_next_token(&mut self) -> String {
+        let prev_position = self.position;
+        let token = self.dbg_get_any_next_token();
+        self.position = prev_position;
+        token
+    }
+
+    /// On error the number is not consumed, so the error points at its first digit.
     pub fn parse_number(&mut self) -> Result<i64, SoNError> {
+        let start = self.position;
         let snum = self.parse_number_string();
         if snum.len() > 1 && snum.chars().nth(0).is_some_and(|c| c.eq(&'0')) {
+            self.position = start;
             return Err(SoNError::NumberCannotStartWith0);
         }
         Ok(snum.parse::<i64>().expect("numbers must start with a digit"))
     }
 
+    /// Parses an integer or a float literal like `1.5` or `2e3`.
+    pub fn parse_number_literal(&mut self) -> Result<NumberLiteral, SoNError> {
+        let start = self.position;
+        let int_part = self.parse_number_string();
+        let mut is_float = false;
+        if self.peek() == Some('.') && self.input[self.position + 1..].starts_with(|c: char| c.is_ascii_digit()) {
+            self.position += 1;
+            self.parse_number_string();
+            is_float = true;
+        }
+        if self.peek().is_some_and(|c| c == 'e' || c == 'E') {
+            let exponent = self.input[self.position + 1..].trim_start_matches(['+', '-']);
+            let sign_len = self.input.len() - self.position - 1 - exponent.len();
+            if sign_len <= 1 && exponent.starts_with(|c: char| c.is_ascii_digit()) {
+                self.position += 1 + sign_len;
+                self.parse_number_string();
+                is_float = true;
+            }
+        }
+        if !is_float {
+            self.position = start;
+            return self.parse_number().map(NumberLiteral::Int);
+        }
+        if int_part.len() > 1 && int_part.starts_with('0') {
+            self.position = start;
+            return Err(SoNError::NumberCannotStartWith0);
+        }
+        Ok(NumberLiteral::Float(self.input[start..self.position].parse::<f64>().expect("float literals are valid f64")))
+    }
+
     fn parse_number_string(&mut self) -> String {
         let start = self.position;
         while let Some(c) = self.next_char() {
             if !Lexer::is_number(&c) {
-                // Step back one position so we don't consume this non‑ID char
-                self.position -= 1;
+                // Step back one char so we don't consume this non‑ID char
+                self.position -= c.len_utf8();
                 break;
             }
         }
@@ -165,8 +346,8 @@ impl Lexer {
 
         while let Some(c) = self.next_char() {
             if !Lexer::is_id_letter(&c) {
-                // Step back one position so we don't consume this non‑ID char
-                self.position -= 1;
+                // Step back one char so we don't consume this non‑ID char
+                self.position -= c.len_utf8();
                 break;
             }
         }
@@ -180,7 +361,7 @@ impl Lexer {
     }
 
     fn is_number(ch: &char) -> bool {
-        ch.is_digit(10)
+        ch.is_ascii_digit()
     }
 
     pub fn peek_is_number(&self) -> bool {
@@ -198,6 +379,38 @@ impl Lexer {
 mod tests {
     use super::*;
 
+    #[test]
+    fn should_peek_tokens_without_moving() {
+        // Arrange
+        let mut lexer = Lexer::from_str("  Point p = 12;");
+
+        // Act
+        let second = lexer.peek_nth_token(1);
+        let first = lexer.peek_nth_token(0);
+        let last = lexer.peek_nth_token(4);
+
+        // Assert
+        assert_eq!(Some(Token::Identifier("p".into())), second);
+        assert_eq!(Some(Token::Identifier("Point".into())), first);
+        assert_eq!(Some(Token::Symbol(';')), last);
+        assert_eq!(None, lexer.peek_nth_token(5));
+        assert_eq!(0, lexer.position);
+    }
+
+    #[test]
+    fn should_peek_from_the_new_position_after_moving() {
+        // Arrange
+        let mut lexer = Lexer::from_str("int x");
+        lexer.peek_nth_token(1);
+
+        // Act
+        assert!(lexer.matschx("int"));
+        let token = lexer.peek_nth_token(0);
+
+        // Assert
+        assert_eq!(Some(Token::Identifier("x".into())), token);
+    }
+
     #[test]
     fn should_lex_dbg() {
         // Arrange
@@ -211,6 +424,19 @@ mod tests {
         assert_eq!(4, lexer.position);
     }
 
+    #[test]
+    fn should_peek_dbg_without_moving() {
+        // Arrange
+        let mut lexer = Lexer::from_str("1230");
+
+        // Act
+        let token = lexer.dbg_peek_any_next_token();
+
+        // Assert
+        assert_eq!("1230", token);
+        assert_eq!(0, lexer.position);
+    }
+
     #[test]
     fn should_lex_number() {
         // Arrange
@@ -253,7 +479,7 @@ mod tests {
         let mut lexer = Lexer::from_str("waitaminute");
 
         // Act
-        let m = lexer.matsch(&"wait".to_string());
+        let m = lexer.matsch("wait");
 
         // Assert
         assert!(m);
@@ -299,6 +525,45 @@ mod tests {
         assert_eq!("2:2", result);
     }
 
+    #[test]
+    fn should_get_line_col_after_crlf() {
+        // Arrange
+        let mut lexer = Lexer::from_str("int a=1;\r\nreturn a;\r\n");
+        lexer.position = 17;
+
+        // Act
+        let result = lexer.dbg_position_string();
+
+        // Assert
+        assert_eq!("2:8", result);
+    }
+
+    #[test]
+    fn should_get_line_col_after_lone_cr() {
+        // Arrange
+        let mut lexer = Lexer::from_str("01\r34\r\n6");
+        lexer.position = 7;
+
+        // Act
+        let result = lexer.dbg_position_string();
+
+        // Assert
+        assert_eq!("3:1", result);
+    }
+
+    #[test]
+    fn should_skip_byte_order_mark() {
+        // Arrange
+        let mut lexer = Lexer::from_str("\u{FEFF}return 1;");
+
+        // Act
+        let m = lexer.matsch("return");
+
+        // Assert
+        assert!(m);
+        assert_eq!("1:7", lexer.dbg_position_string());
+    }
+
     #[test]
     fn should_get_line_col_oob() {
         // Arrange
@@ -312,6 +577,92 @@ mod tests {
         assert_eq!("out of bounds", result);
     }
 
+    #[test]
+    fn should_skip_nested_block_comment() {
+        // Arrange
+        let mut lexer = Lexer::from_str("/* a /* b */ c */ 1");
+
+        // Act
+        lexer.skip_whitespace();
+
+        // Assert
+        assert_eq!(18, lexer.position);
+        assert!(lexer.peek_is_number());
+    }
+
+    #[test]
+    fn should_not_skip_unterminated_nested_block_comment() {
+        // Arrange
+        let mut lexer = Lexer::from_str(" /* a /* b */ c 1");
+
+        // Act
+        lexer.skip_whitespace();
+
+        // Assert
+        assert_eq!(1, lexer.position);
+        assert!(lexer.is_at_unterminated_comment());
+    }
+
+    #[test]
+    fn should_match_keyword_after_multibyte_identifier() {
+        // Arrange
+        let mut lexer = Lexer::from_str("größe€ return 1;");
+
+        // Act
+        let id = lexer.parse_id();
+        let euro = lexer.next_char();
+        let matched = lexer.matschx("return");
+
+        // Assert
+        assert_eq!("größe", id);
+        assert_eq!(Some('€'), euro);
+        assert!(matched);
+        assert_eq!(17, lexer.position);
+    }
+
+    #[test]
+    #[allow(clippy::approx_constant)]
+    fn should_lex_float() {
+        // Arrange
+        let mut lexer = Lexer::from_str("3.14;");
+
+        // Act
+        let result = lexer.parse_number_literal().unwrap();
+
+        // Assert
+        assert_eq!(NumberLiteral::Float(3.14), result);
+        assert_eq!(4, lexer.position);
+    }
+
+    #[test]
+    fn should_lex_float_with_exponent() {
+        // Arrange
+        let mut lexer = Lexer::from_str("2e3+1.5E-1");
+
+        // Act
+        let first = lexer.parse_number_literal().unwrap();
+        lexer.matsch("+");
+        let second = lexer.parse_number_literal().unwrap();
+
+        // Assert
+        assert_eq!(NumberLiteral::Float(2e3), first);
+        assert_eq!(NumberLiteral::Float(0.15), second);
+        assert!(lexer.is_eof());
+    }
+
+    #[test]
+    fn should_lex_int_literal_followed_by_dot_as_int() {
+        // Arrange
+        let mut lexer = Lexer::from_str("12.x");
+
+        // Act
+        let result = lexer.parse_number_literal().unwrap();
+
+        // Assert
+        assert_eq!(NumberLiteral::Int(12), result);
+        assert_eq!(2, lexer.position);
+    }
+
     #[test]
     fn should_parse_zero_number() {
         // Arrange
@@ -0,0 +1,124 @@
+use crate::nodes::node::NodeKind;
+use crate::services::parser::Parser;
+use std::fmt::{Display, Formatter};
+
+/// A warning about a program that parses fine. `line` and `col` are 1-based and point at the source it is about.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Lint {
+    /// a variable that is declared but never read. Parameters like `arg` are not reported.
+    UnusedVariable { name: String, line: usize, col: usize },
+    /// a declaration in a nested block that hides a variable of an enclosing block
+    ShadowedVariable { name: String, line: usize, col: usize },
+    /// an int division whose divisor isn't known to be non-zero. A divisor that is always zero is an error instead.
+    PossibleDivisionByZero { line: usize, col: usize },
+}
+
+impl Display for Lint {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Lint::UnusedVariable { name, line, col } => write!(f, "Warning@{}:{} unused variable '{}'", line, col, name),
+            Lint::ShadowedVariable { name, line, col } => write!(f, "Warning@{}:{} '{}' shadows a variable of an enclosing block", line, col, name),
+            Lint::PossibleDivisionByZero { line, col } => write!(f, "Warning@{}:{} the divisor may be zero", line, col),
+        }
+    }
+}
+
+impl Parser {
+    /// Warnings about the parsed program, ordered by their position in the source.
+    pub fn lints(&self) -> Vec<Lint> {
+        let mut lints = vec![];
+        for (name, pos) in self.unused_declarations() {
+            let (line, col) = self.lexer.line_col_for(pos).unwrap_or((0, 0));
+            lints.push((pos, Lint::UnusedVariable { name, line, col }));
+        }
+        for (name, pos) in self.shadowing_declarations.iter().cloned() {
+            let (line, col) = self.lexer.line_col_for(pos).unwrap_or((0, 0));
+            lints.push((pos, Lint::ShadowedVariable { name, line, col }));
+        }
+        for node in self.nodes().filter(|n| matches!(n.node_kind, NodeKind::Div) && n.typ().is_int()) {
+            let Ok(divisor) = self.graph.get_node(node.inputs[1]) else {
+                continue;
+            };
+            let non_zero = matches!(self.graph.int_bounds(divisor), Ok(Some((min, max))) if min > 0 || max < 0);
+            if !non_zero {
+                let (line, col) = self.lexer.line_col_for(node.span.0).unwrap_or((0, 0));
+                lints.push((node.span.0, Lint::PossibleDivisionByZero { line, col }));
+            }
+        }
+        lints.sort_by_key(|(pos, _)| *pos);
+        lints.into_iter().map(|(_, lint)| lint).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::errors::son_error::{ErrorWithContext, SoNError};
+    use crate::services::lints::Lint;
+    use crate::services::parser::Parser;
+
+    #[test]
+    fn should_report_unused_variable() {
+        // Arrange
+        let mut parser = Parser::new_noarg("int a=1;\nint b=2; return a;").unwrap();
+        parser.parse().unwrap();
+
+        // Act
+        let lints = parser.lints();
+
+        // Assert
+        assert_eq!(vec![Lint::UnusedVariable { name: "b".into(), line: 2, col: 1 }], lints);
+        assert_eq!("Warning@2:1 unused variable 'b'", lints[0].to_string());
+    }
+
+    #[test]
+    fn should_report_shadowed_variable() {
+        // Arrange
+        let mut parser = Parser::new_noarg("int x=1;\n{ int x=2; arg=x; }\nreturn x;").unwrap();
+        parser.parse().unwrap();
+
+        // Act
+        let lints = parser.lints();
+
+        // Assert
+        assert_eq!(vec![Lint::ShadowedVariable { name: "x".into(), line: 2, col: 3 }], lints);
+    }
+
+    #[test]
+    fn should_keep_redefinition_in_same_scope_an_error() {
+        // Arrange
+        let mut parser = Parser::new_noarg("int x=1; int x=2; return x;").unwrap();
+
+        // Act
+        let result = parser.parse();
+
+        // Assert
+        assert!(matches!(result, Err(ErrorWithContext { error: SoNError::VariableRedefinition { .. }, .. })));
+        assert!(parser.lints().is_empty());
+    }
+
+    #[test]
+    fn should_report_divisor_that_may_be_zero() {
+        // Arrange
+        let mut parser = Parser::new_noarg("int a=arg/arg;\nint b=arg/2;\nint c=arg/((arg&3)+1);\nreturn a+b+c;").unwrap();
+        parser.parse().unwrap();
+
+        // Act
+        let lints = parser.lints();
+
+        // Assert
+        assert_eq!(vec![Lint::PossibleDivisionByZero { line: 1, col: 7 }], lints);
+    }
+
+    #[test]
+    fn should_not_report_unused_parameter() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return 1;").unwrap();
+        parser.parse().unwrap();
+
+        // Act
+        let lints = parser.lints();
+
+        // Assert
+        assert!(lints.is_empty());
+    }
+}
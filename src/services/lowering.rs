@@ -0,0 +1,454 @@
+use crate::ast::expr::Expr;
+use crate::ast::stmt::Stmt;
+use crate::errors::son_error::SoNError;
+use crate::errors::son_error::SoNError::{DebugPropagateControlFlowUpward, VariableRedefinition};
+use crate::nodes::node::NodeKind;
+use crate::services::parser::{Function, Parser, FIRST_ARG_PROJ_INDEX, START_NID};
+use crate::typ::typ::Typ;
+use crate::typ::typ::Typ::Ctrl;
+
+/// the alias of array elements in Load and Store nodes
+const ELEMENT_ALIAS: &str = "[]";
+/// arrays up to this constant length are zeroed element by element, so loads of unwritten elements fold
+pub(crate) const ZEROED_ARRAY_MAX_LEN: usize = 16;
+
+impl Parser {
+    /// Builds the graph for the statements of a program and returns the nid of the last statement.
+    /// The source position of whatever is lowered is tracked in lowering_pos, so errors and nodes point at it.
+    pub fn lower(&mut self, program: &[Stmt]) -> Result<usize, SoNError> {
+        self.begin_lowering()?;
+        let nid = self.lower_block(program)?;
+        self.end_lowering()?;
+        Ok(nid)
+    }
+
+    /// opens the scope of the program parameters. The statements of the program are lowered in a nested block.
+    pub(crate) fn begin_lowering(&mut self) -> Result<(), SoNError> {
+        self.push_scope()?;
+        self.define_projections(START_NID, &self.params.clone())
+    }
+
+    /// closes the scope of [Parser::begin_lowering]
+    pub(crate) fn end_lowering(&mut self) -> Result<(), SoNError> {
+        self.pop_scope()
+    }
+
+    /// projects control, memory and then the parameters from the entry of a program or function
+    /// and defines them as variables of the current scope
+    fn define_projections(&mut self, entry: usize, params: &[(String, Typ)]) -> Result<(), SoNError> {
+        let ctrl_nid = self.add_node_unrefined(vec![entry], NodeKind::Proj { proj_index: 0, _dbg_proj_label: "$ctrl".into() })?;
+        self.define_var("$ctrl", ctrl_nid, Ctrl)?;
+        let mem_nid = self.add_node_unrefined(vec![entry], NodeKind::Proj { proj_index: 1, _dbg_proj_label: "$mem".into() })?;
+        self.define_var("$mem", mem_nid, Typ::Mem)?;
+        for (i, (name, typ)) in params.iter().enumerate() {
+            if self.get_var(name).is_some() {
+                return Err(VariableRedefinition { variable: name.clone() });
+            }
+            let nid = self.add_node_unrefined(vec![entry], NodeKind::Proj { proj_index: FIRST_ARG_PROJ_INDEX + i, _dbg_proj_label: name.clone() })?;
+            let declared_typ = if typ.is_int() {
+                Typ::IntBot
+            } else if typ.is_bool() {
+                Typ::BoolBot
+            } else if typ.is_float() {
+                Typ::FloatBot
+            } else {
+                typ.clone()
+            };
+            self.define_var(name, nid, declared_typ)?;
+        }
+        Ok(())
+    }
+
+    /// Lowers the body of a function with only its parameters in scope. The entry and the last
+    /// statement of the body are kept alive, since nothing in the program has to use them.
+    /// The last statement has to return a value of the declared return typ.
+    fn lower_function(&mut self, ret_typ: &Typ, name: &str, params: &[(String, Typ)], body: &[Stmt], pos: usize) -> Result<(), SoNError> {
+        if self.functions.contains_key(name) {
+            return Err(SoNError::FunctionRedefinition { name: name.into() });
+        }
+        let typs = [Ctrl, Typ::Mem].into_iter().chain(params.iter().map(|(_, typ)| typ.clone())).collect();
+        let entry = self.add_node(vec![], NodeKind::Fun { name: name.into() }, Typ::Tuple { typs })?;
+        self.keep_node(entry)?;
+        // registered before the body is lowered, so the function can call itself
+        self.functions.insert(name.into(), Function { entry, params: params.to_vec(), ret_typ: ret_typ.clone(), ret: None });
+        self.with_fresh_scopes(|parser| {
+            parser.push_scope()?;
+            parser.define_projections(entry, params)?;
+            let ret = parser.lower_block(body)?;
+            let ret_node = parser.graph.get_node(ret)?;
+            if !matches!(ret_node.node_kind, NodeKind::Return) {
+                parser.lowering_pos = pos;
+                return Err(SoNError::MissingReturn { function: name.into() });
+            }
+            // lowering_pos still points at the return, which is the last statement
+            let typ = parser.graph.get_node(ret_node.inputs[1])?.typ();
+            if typ.meet(ret_typ) != *ret_typ {
+                return Err(SoNError::TypeMismatch { variable: None, expected: format!("{:?}", ret_typ), but_got: format!("{:?}", typ) });
+            }
+            parser.keep_node(ret)?;
+            parser.functions.get_mut(name).expect("function is registered").ret = Some(ret);
+            parser.pop_scope()
+        })
+    }
+
+    /// returns the value of the last statement. A block without any value evaluates to the current control.
+    fn lower_block(&mut self, stmts: &[Stmt]) -> Result<usize, SoNError> {
+        self.push_scope()?;
+        let mut node = self.ctrl();
+        for stmt in stmts {
+            node = self.lower_block_statement(stmt, node)?;
+        }
+        self.pop_scope()?;
+        Ok(node)
+    }
+
+    /// returns the value of `stmt`, or `node` if the statement has no value
+    pub(crate) fn lower_block_statement(&mut self, stmt: &Stmt, node: usize) -> Result<usize, SoNError> {
+        match self.lower_statement(stmt) {
+            Err(DebugPropagateControlFlowUpward) => Ok(node),
+            new_node => new_node,
+        }
+    }
+
+    fn lower_statement(&mut self, stmt: &Stmt) -> Result<usize, SoNError> {
+        match stmt {
+            Stmt::Decl { declared_typ, name, value, is_const, pos } => {
+                let expression = self.lower_expression(value)?;
+                self.lowering_pos = *pos;
+                // a variable of an outer scope is shadowed until the end of this one
+                if self.is_defined_in_innermost_scope(name) {
+                    return Err(VariableRedefinition { variable: name.clone() });
+                }
+                self.require_assignable(name, declared_typ, expression)?;
+                if self.get_var(name).is_some() {
+                    self.shadowing_declarations.push((name.clone(), *pos));
+                }
+                self.define_var(name, expression, declared_typ.clone())?;
+                if *is_const {
+                    self.make_const(name)?;
+                }
+                self.track_declaration(name, *pos);
+                Ok(expression)
+            }
+            Stmt::Assign { name, value, pos } => {
+                let expression = self.lower_expression(value)?;
+                self.lowering_pos = *pos;
+                if self.get_var(name).is_none() {
+                    return Err(self.variable_undefined(name));
+                }
+                if self.is_const(name) {
+                    return Err(SoNError::AssignToConst { variable: name.clone() });
+                }
+                let declared_typ = self.get_declared_typ(name).expect("Variable without declared typ.");
+                self.require_assignable(name, &declared_typ, expression)?;
+                self.update_var(name, expression)?;
+                Ok(expression)
+            }
+            Stmt::FieldAssign { object, field, value, pos } => {
+                let expression = self.lower_expression(value)?;
+                let ptr = self.with_kept_node(expression, |parser| parser.lower_expression(object))?;
+                self.lowering_pos = *pos;
+                let field_typ = self.field_typ(ptr, field)?;
+                self.require_assignable(field, &field_typ, expression)?;
+                let store = self.add_node(vec![self.mem(), ptr, expression], NodeKind::Store { alias: field.clone() }, Typ::Mem)?;
+                self.update_var("$mem", store)?;
+                Ok(expression)
+            }
+            Stmt::IndexAssign { array, index, value, pos } => {
+                let expression = self.lower_expression(value)?;
+                let (offset, elem) = self.with_kept_node(expression, |parser| parser.lower_offset(array, index, *pos))?;
+                self.lowering_pos = *pos;
+                self.require_assignable(ELEMENT_ALIAS, &elem, expression)?;
+                let store = self.add_node(vec![self.mem(), offset, expression], NodeKind::Store { alias: ELEMENT_ALIAS.into() }, Typ::Mem)?;
+                self.update_var("$mem", store)?;
+                Ok(expression)
+            }
+            Stmt::Struct { .. } => Err(DebugPropagateControlFlowUpward),
+            Stmt::Function { ret_typ, name, params, body, pos } => {
+                self.lowering_pos = *pos;
+                self.lower_function(ret_typ, name, params, body, *pos)?;
+                Err(DebugPropagateControlFlowUpward)
+            }
+            Stmt::Return { value, pos } => {
+                let expression = self.lower_expression(value)?;
+                self.lowering_pos = *pos;
+                self.add_node_unrefined(vec![self.ctrl(), expression], NodeKind::Return)
+            }
+            Stmt::Print { value, pos } => {
+                let expression = self.lower_expression(value)?;
+                self.lowering_pos = *pos;
+                let print = self.add_node_unrefined(vec![self.ctrl(), self.mem(), expression], NodeKind::Print)?;
+                // the print is both the new control and the new memory, which orders it before later side effects
+                self.update_var("$ctrl", print)?;
+                self.update_var("$mem", print)?;
+                Err(DebugPropagateControlFlowUpward)
+            }
+            Stmt::Block { stmts, .. } => self.lower_block(stmts),
+            Stmt::ShowGraph { pos } => {
+                self.lowering_pos = *pos;
+                let out = format!("#showGraph@{}\n{}", self.lexer.dbg_position_string_at(*pos), self.as_dotfile());
+                self._dbg_output.push_str(out.as_str());
+                Err(DebugPropagateControlFlowUpward)
+            }
+            Stmt::Empty { .. } => Err(DebugPropagateControlFlowUpward),
+        }
+    }
+
+    /// the typ of the value has to be a subtype of the declared typ of the variable
+    fn require_assignable(&self, name: &str, declared_typ: &Typ, nid: usize) -> Result<(), SoNError> {
+        let typ = self.graph.get_node(nid)?.typ();
+        if typ.meet(declared_typ) != *declared_typ {
+            return Err(SoNError::TypeMismatch { variable: Some(name.into()), expected: format!("{:?}", declared_typ), but_got: format!("{:?}", typ) });
+        }
+        Ok(())
+    }
+
+    /// the declared typ of `field` in the struct `ptr` points to
+    fn field_typ(&self, ptr: usize, field: &str) -> Result<Typ, SoNError> {
+        match self.graph.get_node(ptr)?.typ() {
+            Typ::Struct { name, fields } => fields.iter()
+                .find(|(name, _)| name == field)
+                .map(|(_, typ)| typ.clone())
+                .ok_or(SoNError::FieldUndefined { struct_name: name, field: field.into() }),
+            typ => Err(SoNError::TypeMismatch { variable: None, expected: "struct".into(), but_got: format!("{:?}", typ) }),
+        }
+    }
+
+    /// Lowers the address of `array[index]` and returns it with the typ of the element.
+    /// A constant index into an array of known length is checked against its bounds.
+    fn lower_offset(&mut self, array: &Expr, index: &Expr, pos: usize) -> Result<(usize, Typ), SoNError> {
+        let array = self.lower_expression(array)?;
+        let index = self.with_kept_node(array, |parser| parser.lower_expression(index))?;
+        self.lowering_pos = pos;
+        let array_typ = self.graph.get_node(array)?.typ();
+        let Typ::Array { elem, len } = &array_typ else {
+            return Err(SoNError::TypeMismatch { variable: None, expected: "array".into(), but_got: format!("{:?}", array_typ) });
+        };
+        match self.graph.get_node(index)?.typ() {
+            Typ::Int { constant } if constant < 0 || len.is_some_and(|len| constant as u64 >= len as u64) => {
+                return Err(SoNError::IndexOutOfBounds { index: constant, len: len.unwrap_or(0) });
+            }
+            typ if !typ.is_int() => {
+                return Err(SoNError::TypeMismatch { variable: None, expected: "int".into(), but_got: format!("{:?}", typ) });
+            }
+            _ => {}
+        }
+        let offset = self.add_node(vec![array, index], NodeKind::Offset, array_typ.clone())?;
+        Ok((offset, *elem.clone()))
+    }
+
+    /// Lowers the arguments of an intrinsic like `min(a, b)` and the node computing it.
+    fn lower_intrinsic(&mut self, kind: NodeKind, args: &[Expr], pos: usize) -> Result<usize, SoNError> {
+        if kind.arity() != args.len() {
+            return Err(SoNError::ArgumentCountMismatch { expected: kind.arity(), but_got: args.len() });
+        }
+        let mut inputs = vec![];
+        for arg in args {
+            let nid = self.with_kept_nodes(&inputs, |parser| parser.lower_expression(arg))?;
+            inputs.push(nid);
+        }
+        self.lowering_pos = pos;
+        self.add_node_unrefined(inputs, kind)
+    }
+
+    /// Lowers each branch with the control of its side of an If and merges the values with a Phi.
+    /// The memory is merged as well if a branch changed it.
+    fn lower_if(&mut self, cond: &Expr, then: &Expr, otherwise: &Expr, pos: usize) -> Result<usize, SoNError> {
+        let pred = self.lower_expression(cond)?;
+        self.lowering_pos = cond.pos();
+        let pred_typ = self.graph.get_node(pred)?.typ();
+        if !pred_typ.is_int() && !pred_typ.is_bool() {
+            return Err(SoNError::TypeMismatch { variable: None, expected: "int or bool".into(), but_got: format!("{:?}", pred_typ) });
+        }
+        self.lowering_pos = pos;
+        let iff = self.with_kept_node(pred, |parser| parser.add_node_unrefined(vec![parser.ctrl(), pred], NodeKind::If))?;
+        self.with_kept_node(iff, |parser| {
+            let true_ctrl = parser.add_node_unrefined(vec![iff], NodeKind::Proj { proj_index: 0, _dbg_proj_label: "True".into() })?;
+            let false_ctrl = parser.with_kept_node(true_ctrl, |parser| {
+                parser.add_node_unrefined(vec![iff], NodeKind::Proj { proj_index: 1, _dbg_proj_label: "False".into() })
+            })?;
+            let mem = parser.mem();
+            parser.with_kept_nodes(&[false_ctrl, mem], |parser| {
+                parser.update_var("$ctrl", true_ctrl)?;
+                let then_value = parser.lower_expression(then)?;
+                let (then_ctrl, then_mem) = (parser.ctrl(), parser.mem());
+                parser.with_kept_nodes(&[then_value, then_ctrl, then_mem], |parser| {
+                    parser.update_var("$ctrl", false_ctrl)?;
+                    parser.update_var("$mem", mem)?;
+                    let otherwise_value = parser.lower_expression(otherwise)?;
+                    let (otherwise_ctrl, otherwise_mem) = (parser.ctrl(), parser.mem());
+                    parser.lowering_pos = pos;
+                    let (then_typ, otherwise_typ) = (parser.graph.get_node(then_value)?.typ(), parser.graph.get_node(otherwise_value)?.typ());
+                    if then_typ.meet(&otherwise_typ) == Typ::Bot {
+                        return Err(SoNError::TypeMismatch { variable: None, expected: format!("{:?}", then_typ), but_got: format!("{:?}", otherwise_typ) });
+                    }
+                    parser.with_kept_nodes(&[otherwise_value, otherwise_mem], |parser| {
+                        let region = parser.add_node_unrefined(vec![then_ctrl, otherwise_ctrl], NodeKind::Region)?;
+                        parser.update_var("$ctrl", region)?;
+                        if !matches!(parser.graph.get_node(region)?.node_kind, NodeKind::Region) {
+                            // the If folded, so only the taken branch is left to merge
+                            let taken = pred_typ.constant_condition().expect("only an If with a constant condition folds");
+                            parser.update_var("$mem", if taken { then_mem } else { otherwise_mem })?;
+                            return Ok(if taken { then_value } else { otherwise_value });
+                        }
+                        if then_mem != otherwise_mem {
+                            let mem_phi = parser.add_node_unrefined(vec![region, then_mem, otherwise_mem], NodeKind::Phi)?;
+                            parser.update_var("$mem", mem_phi)?;
+                        }
+                        parser.add_node_unrefined(vec![region, then_value, otherwise_value], NodeKind::Phi)
+                    })
+                })
+            })
+        })
+    }
+
+    fn lower_expression(&mut self, expr: &Expr) -> Result<usize, SoNError> {
+        match expr {
+            Expr::Number { value, pos } => {
+                self.lowering_pos = *pos;
+                self.add_node(vec![], NodeKind::Constant, Typ::Int { constant: *value })
+            }
+            Expr::Float { value, pos } => {
+                self.lowering_pos = *pos;
+                self.add_node(vec![], NodeKind::Constant, Typ::Float { constant: *value })
+            }
+            Expr::Bool { value, pos } => {
+                self.lowering_pos = *pos;
+                self.add_node(vec![], NodeKind::Constant, Typ::Bool { constant: *value })
+            }
+            Expr::Identifier { name, pos } => {
+                self.lowering_pos = *pos;
+                self.track_read(name);
+                self.get_var(name).ok_or_else(|| self.variable_undefined(name))
+            }
+            Expr::Minus { operand, pos } => {
+                let operand = self.lower_expression(operand)?;
+                self.lowering_pos = *pos;
+                self.add_node_unrefined(vec![operand], NodeKind::Minus)
+            }
+            Expr::Not { operand, pos } => {
+                let operand = self.lower_expression(operand)?;
+                self.lowering_pos = *pos;
+                self.add_node_unrefined(vec![operand], NodeKind::Not)
+            }
+            Expr::Cast { to, operand, pos } => {
+                let operand = self.lower_expression(operand)?;
+                self.lowering_pos = *pos;
+                self.add_node_unrefined(vec![operand], NodeKind::Cast { to: to.clone() })
+            }
+            Expr::New { typ, pos } => {
+                self.lowering_pos = *pos;
+                let ptr = self.add_node(vec![self.ctrl()], NodeKind::New, typ.clone())?;
+                let Typ::Struct { fields, .. } = typ else { panic!("new of non-struct typ {:?}", typ) };
+                self.with_kept_node(ptr, |parser| {
+                    // fields start out zeroed
+                    for (field, field_typ) in fields {
+                        let zero = if field_typ.is_bool() { Typ::Bool { constant: false } } else { Typ::Int { constant: 0 } };
+                        let zero = parser.add_node(vec![], NodeKind::Constant, zero)?;
+                        let store = parser.add_node(vec![parser.mem(), ptr, zero], NodeKind::Store { alias: field.clone() }, Typ::Mem)?;
+                        parser.update_var("$mem", store)?;
+                    }
+                    Ok(ptr)
+                })
+            }
+            Expr::NewArray { elem, len, pos } => {
+                let len = self.lower_expression(len)?;
+                self.lowering_pos = *pos;
+                let known_len = match self.graph.get_node(len)?.typ() {
+                    Typ::Int { constant } if constant < 0 => return Err(SoNError::IndexOutOfBounds { index: constant, len: 0 }),
+                    Typ::Int { constant } => Some(constant as usize),
+                    typ if typ.is_int() => None,
+                    typ => return Err(SoNError::TypeMismatch { variable: None, expected: "int".into(), but_got: format!("{:?}", typ) }),
+                };
+                let typ = Typ::Array { elem: Box::new(elem.clone()), len: known_len };
+                let array = self.add_node(vec![self.ctrl(), len], NodeKind::NewArray, typ.clone())?;
+                let Some(known_len) = known_len.filter(|&len| len <= ZEROED_ARRAY_MAX_LEN) else {
+                    return Ok(array);
+                };
+                self.with_kept_node(array, |parser| {
+                    for i in 0..known_len {
+                        let index = parser.add_node(vec![], NodeKind::Constant, Typ::Int { constant: i as i64 })?;
+                        let offset = parser.add_node(vec![array, index], NodeKind::Offset, typ.clone())?;
+                        let zero = if elem.is_bool() { Typ::Bool { constant: false } } else { Typ::Int { constant: 0 } };
+                        let zero = parser.with_kept_node(offset, |parser| parser.add_node(vec![], NodeKind::Constant, zero))?;
+                        let store = parser.add_node(vec![parser.mem(), offset, zero], NodeKind::Store { alias: ELEMENT_ALIAS.into() }, Typ::Mem)?;
+                        parser.update_var("$mem", store)?;
+                    }
+                    Ok(array)
+                })
+            }
+            Expr::Index { array, index, pos } => {
+                let (offset, elem) = self.lower_offset(array, index, *pos)?;
+                self.add_node(vec![self.mem(), offset], NodeKind::Load { alias: ELEMENT_ALIAS.into() }, elem)
+            }
+            Expr::Field { object, field, pos } => {
+                let ptr = self.lower_expression(object)?;
+                self.lowering_pos = *pos;
+                let field_typ = self.field_typ(ptr, field)?;
+                self.add_node(vec![self.mem(), ptr], NodeKind::Load { alias: field.clone() }, field_typ)
+            }
+            Expr::Call { name, args, pos } => {
+                self.lowering_pos = *pos;
+                if let Some(kind) = intrinsic(name) && !self.functions.contains_key(name) {
+                    return self.lower_intrinsic(kind, args, *pos);
+                }
+                let Some(function) = self.functions.get(name).cloned() else {
+                    return Err(SoNError::UnknownFunction { name: name.clone() });
+                };
+                if function.params.len() != args.len() {
+                    return Err(SoNError::ArgumentCountMismatch { expected: function.params.len(), but_got: args.len() });
+                }
+                let mut inputs = vec![];
+                for (arg, (param, typ)) in args.iter().zip(&function.params) {
+                    let nid = self.with_kept_nodes(&inputs, |parser| parser.lower_expression(arg))?;
+                    self.lowering_pos = arg.pos();
+                    self.require_assignable(param, typ, nid)?;
+                    inputs.push(nid);
+                }
+                self.lowering_pos = *pos;
+                let inputs = [self.ctrl(), self.mem(), function.entry].into_iter().chain(inputs).collect();
+                let call = self.add_node_unrefined(inputs, NodeKind::Call { name: name.clone(), argc: args.len() })?;
+                // like a print, the call is both the new control and the new memory
+                self.update_var("$ctrl", call)?;
+                self.update_var("$mem", call)?;
+                self.add_node(vec![call], NodeKind::CallEnd, function.ret_typ)
+            }
+            Expr::If { cond, then, otherwise, pos } => self.lower_if(cond, then, otherwise, *pos),
+            Expr::Binary { .. } => self.lower_binary(expr),
+        }
+    }
+
+    /// Lowers the left-nested operands of a chain like `a+b+c` in a loop, so long chains don't grow the stack.
+    /// The nodes span the whole expression, while their errors point at the operator.
+    fn lower_binary(&mut self, expr: &Expr) -> Result<usize, SoNError> {
+        let mut chain = vec![];
+        let mut leftmost = expr;
+        while let Expr::Binary { operator, lhs, rhs, pos, op_pos, end } = leftmost {
+            chain.push((operator, rhs, (*pos, *end), *op_pos));
+            leftmost = lhs;
+        }
+        let mut lhs = self.lower_expression(leftmost)?;
+        for (operator, rhs, span, op_pos) in chain.into_iter().rev() {
+            lhs = self.with_kept_node(lhs, |parser| {
+                let rhs = parser.lower_expression(rhs)?;
+                parser.lowering_pos = op_pos;
+                let nid = parser.add_node_spanned(vec![lhs, rhs], operator.node_kind.clone(), Typ::Bot, span)?;
+                if operator.negated {
+                    return parser.add_node_spanned(vec![nid], NodeKind::Not, Typ::Bot, span);
+                }
+                Ok(nid)
+            })?;
+        }
+        Ok(lhs)
+    }
+}
+
+/// the node kind of a built-in function. A function of the program with the same name takes precedence.
+fn intrinsic(name: &str) -> Option<NodeKind> {
+    match name {
+        "abs" => Some(NodeKind::Abs),
+        "min" => Some(NodeKind::Min),
+        "max" => Some(NodeKind::Max),
+        _ => None,
+    }
+}
@@ -0,0 +1,62 @@
+use crate::nodes::node::{node_icon, NodeKind};
+use crate::services::parser::Parser;
+use crate::typ::typ::Typ;
+
+impl Parser {
+    /// Renders the graph as a Mermaid flowchart. In contrast to the dotfile this can be
+    /// rendered inline by Markdown viewers (e.g. GitHub) without Graphviz.
+    pub fn as_mermaid(&self) -> String {
+        let mut sb = String::new();
+        sb.push_str("graph BT\n");
+
+        // define the nodes. control nodes are boxes, the other nodes have rounded edges.
+        for n in self.graph.graph_iter().filter(|n| !matches!(n.node_kind, NodeKind::KeepAlive | NodeKind::Scope {..})) {
+            sb.push_str(&format!("\tNode_{}", n.nid));
+            let lab = node_icon(n).replace('"', "#quot;");
+            if n.bind(&self.graph).is_cfg() {
+                sb.push_str(&format!("[\"{}\"]", lab));
+            } else {
+                sb.push_str(&format!("(\"{}\")", lab));
+            }
+            sb.push('\n');
+        }
+
+        // walk the node edges use->def. control edges are drawn thick.
+        for n in self.graph.graph_iter().filter(|n| !matches!(n.node_kind, NodeKind::KeepAlive | NodeKind::Scope {..})) {
+            for (i, def_nid) in n.inputs.iter().enumerate() {
+                if let Some(Some(def)) = self.graph.get(*def_nid) {
+                    let arrow = if def.bind(&self.graph).is_cfg() && (!matches!(n.node_kind, NodeKind::Proj {..}) || matches!(n.typ(), Typ::Ctrl)) {
+                        "==>"
+                    } else {
+                        "-->"
+                    };
+                    sb.push_str(&format!("\tNode_{} {}|{}| Node_{}\n", n.nid, arrow, i, def_nid));
+                }
+            }
+        }
+        sb
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::nodes::node::NodeKind;
+    use crate::services::parser::ParserBuilder;
+
+    #[test]
+    fn should_output_return_1_mermaid() {
+        // Arrange
+        let mut parser = ParserBuilder::new().do_optimize(false).build_noarg("return 1;").unwrap();
+        let ret = parser.parse().unwrap();
+        let constant = parser.graph.graph_iter().find(|n| matches!(n.node_kind, NodeKind::Constant)).unwrap().nid;
+
+        // Act
+        let mermaid = parser.as_mermaid();
+
+        // Assert
+        assert!(mermaid.starts_with("graph BT\n"));
+        assert!(mermaid.contains(&format!("\tNode_{}[\"Return\"]\n", ret)));
+        assert!(mermaid.contains(&format!("\tNode_{}(\"#1\")\n", constant)));
+        assert!(mermaid.contains(&format!("\tNode_{} -->|1| Node_{}\n", ret, constant)));
+    }
+}
@@ -0,0 +1,73 @@
+use crate::nodes::node::NodeKind;
+use crate::services::parser::Parser;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GraphMetrics {
+    pub live_nodes: usize,
+    pub constant_nodes: usize,
+    /// the longest chain of data edges starting at a Return
+    pub max_data_depth: usize,
+    pub cfg_nodes: usize,
+}
+
+impl Parser {
+    pub fn metrics(&self) -> GraphMetrics {
+        let mut max_data_depth = 0;
+        for ret in self.nodes().filter(|n| matches!(n.node_kind, NodeKind::Return)) {
+            let mut depths: HashMap<usize, usize> = HashMap::new();
+            // inputs before their users, so every input depth is known
+            for nid in self.graph.reverse_postorder(ret.nid).into_iter().rev() {
+                let node = self.graph.get_node(nid).unwrap();
+                let depth = node.inputs.iter()
+                    .filter(|&&i| !self.graph.get_node(i).unwrap().bind(&self.graph).is_cfg())
+                    .map(|i| depths[i] + 1)
+                    .max()
+                    .unwrap_or(0);
+                depths.insert(nid, depth);
+            }
+            max_data_depth = max_data_depth.max(depths[&ret.nid]);
+        }
+
+        GraphMetrics {
+            live_nodes: self.graph.live_count(),
+            constant_nodes: self.nodes().filter(|n| matches!(n.node_kind, NodeKind::Constant)).count(),
+            max_data_depth,
+            cfg_nodes: self.nodes().filter(|n| n.bind(&self.graph).is_cfg()).count(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::services::metrics::GraphMetrics;
+    use crate::services::parser::ParserBuilder;
+
+    #[test]
+    fn should_measure_unoptimized_expression() {
+        // Arrange
+        let mut parser = ParserBuilder::new().do_optimize(false).build_noarg("return 1+2*3;").unwrap();
+        parser.parse().unwrap();
+
+        // Act
+        let metrics = parser.metrics();
+
+        // Assert
+        // KeepAlive, Scope, Start, $ctrl, 3 constants, Mul, Add and Return
+        assert_eq!(GraphMetrics { live_nodes: 10, constant_nodes: 3, max_data_depth: 3, cfg_nodes: 3 }, metrics);
+    }
+
+    #[test]
+    fn should_count_shared_subgraph_once() {
+        // Arrange
+        let mut parser = ParserBuilder::new().do_optimize(false).build_noarg("int a=arg*arg; return a+a;").unwrap();
+        parser.parse().unwrap();
+
+        // Act
+        let metrics = parser.metrics();
+
+        // Assert
+        assert_eq!(3, metrics.max_data_depth);
+        assert_eq!(0, metrics.constant_nodes);
+    }
+}
@@ -1,5 +1,16 @@
 pub mod parser;
+pub mod lowering;
 mod lexer;
+pub mod operators;
 pub mod dotvis;
+pub mod mermaidvis;
+pub mod decompiler;
+pub mod evaluator;
+pub mod inliner;
+pub mod formatter;
+pub mod irdump;
+pub mod metrics;
+pub mod lints;
+pub mod stepwise;
 pub mod typ_refiner;
 pub mod node_idealizer;
\ No newline at end of file
@@ -1,7 +1,7 @@
 use crate::errors::son_error::SoNError;
 use crate::nodes::node::CompNodeKind::{LogAnd, LogOr, LogXor};
 use crate::nodes::node::NodeKind::{Comp, Constant, Mul};
-use crate::nodes::node::{CompNodeKind, NodeKind};
+use crate::nodes::node::{CompNodeKind, Node, NodeKind};
 use crate::services::parser::Parser;
 use crate::typ::typ::Typ;
 use crate::typ::typ::Typ::{Bool, Int};
@@ -10,17 +10,113 @@ use NodeKind::{Add, Div, KeepAlive, Minus, Proj, Return, Scope, Start, Sub};
 use Typ::{BoolBot, BoolTop, IntBot, IntTop};
 
 impl Parser {
+    /// two distinct allocations never point to the same memory and neither do different constant offsets into them
+    fn never_alias(&self, lhs: usize, rhs: usize) -> Result<bool, SoNError> {
+        let (lhs_node, rhs_node) = (self.graph.get_node(lhs)?, self.graph.get_node(rhs)?);
+        match (&lhs_node.node_kind, &rhs_node.node_kind) {
+            (NodeKind::New | NodeKind::NewArray, NodeKind::New | NodeKind::NewArray) => Ok(lhs != rhs),
+            (NodeKind::Offset, NodeKind::Offset) => {
+                let (lhs_index, rhs_index) = (self.graph.get_node(lhs_node.inputs[1])?.typ(), self.graph.get_node(rhs_node.inputs[1])?.typ());
+                Ok(self.never_alias(lhs_node.inputs[0], rhs_node.inputs[0])?
+                    || lhs_node.inputs[0] == rhs_node.inputs[0] && lhs_index.is_constant() && rhs_index.is_constant() && lhs_index != rhs_index)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// the same pointer or offsets of the same array with equal indices
+    fn same_address(&self, lhs: usize, rhs: usize) -> Result<bool, SoNError> {
+        let (lhs_node, rhs_node) = (self.graph.get_node(lhs)?, self.graph.get_node(rhs)?);
+        if lhs == rhs {
+            return Ok(true);
+        }
+        if !matches!((&lhs_node.node_kind, &rhs_node.node_kind), (NodeKind::Offset, NodeKind::Offset)) || lhs_node.inputs[0] != rhs_node.inputs[0] {
+            return Ok(false);
+        }
+        let lhs_index = self.graph.get_node(lhs_node.inputs[1])?.typ();
+        Ok(lhs_node.inputs[1] == rhs_node.inputs[1] || lhs_index.is_constant() && lhs_index == self.graph.get_node(rhs_node.inputs[1])?.typ())
+    }
+
+    /// `!x`, or the operand of `x` if it already is a negation
+    fn negate(&mut self, nid: usize) -> Result<usize, SoNError> {
+        let node = self.graph.get_node(nid)?;
+        if matches!(node.node_kind, NodeKind::Not) {
+            return Ok(node.inputs[0]);
+        }
+        self.add_node_unrefined(vec![nid], NodeKind::Not)
+    }
+
+    /// `x` and `c` of `x*c` with a constant `c`, otherwise the node itself scaled by 1
+    fn scaled_term(&self, nid: usize) -> Result<(usize, i64), SoNError> {
+        let node = self.graph.get_node(nid)?;
+        if matches!(node.node_kind, Mul) && let Int { constant } = self.graph.get_node(node.inputs[1])?.typ() {
+            return Ok((node.inputs[0], constant));
+        }
+        Ok((nid, 1))
+    }
+
+    /// `lhs + (-constant)`, the Add form of `lhs - constant`
+    fn sub_as_add(&mut self, lhs_nid: usize, constant: i64) -> Result<usize, SoNError> {
+        let negated = self.add_node(vec![], Constant, Int { constant: constant.wrapping_neg() })?;
+        self.add_node_unrefined(vec![lhs_nid, negated], Add)
+    }
+
     pub(crate) fn idealize_node(&mut self, nid: usize) -> Result<usize, SoNError> {
         let node = self.graph.get_node(nid)?.clone();
+        if node.typ().is_float() {
+            return Ok(nid); // float arithmetic is neither associative nor exact, so the rewrites don't apply
+        }
         match node.node_kind {
             Constant => Ok(nid),
             Return => Ok(nid),
             Start => Ok(nid),
             KeepAlive => Ok(nid),
+            NodeKind::Print | NodeKind::Store { .. } | NodeKind::New | NodeKind::NewArray | NodeKind::Offset => Ok(nid),
+            NodeKind::Fun { .. } | NodeKind::Call { .. } | NodeKind::CallEnd => Ok(nid),
+            NodeKind::If => Ok(nid),
+            NodeKind::Region => {
+                let iff = self.graph.get_node(self.graph.region_if(nid)?)?.clone();
+                let Some(taken) = self.graph.get_node(iff.inputs[1])?.typ().constant_condition() else {
+                    return Ok(nid);
+                };
+                let proj_index = if taken { 0 } else { 1 };
+                let proj = *iff.outputs.iter()
+                    .find(|&&output| matches!(self.graph.get_node(output).unwrap().node_kind, Proj { proj_index: index, .. } if index == proj_index))
+                    .expect("If without projection of the taken branch");
+                // The taken branch continues the control before the If. The other one is dead.
+                // Control nodes inside the branch still use the projection until Graph::bypass_constant_ifs rewires them.
+                let taken_ctrl = node.inputs[proj_index];
+                Ok(if taken_ctrl == proj { iff.inputs[0] } else { taken_ctrl }) // T_IF_CONST
+            }
+            NodeKind::Phi => {
+                let (then, otherwise) = (node.inputs[1], node.inputs[2]);
+                if then == otherwise {
+                    return Ok(then); // T_PHI_SAME
+                }
+                let iff = self.graph.get_node(self.graph.region_if(node.inputs[0])?)?;
+                match self.graph.get_node(iff.inputs[1])?.typ().constant_condition() {
+                    Some(taken) => Ok(if taken { then } else { otherwise }), // T_PHI_CONST
+                    None => Ok(nid),
+                }
+            }
+            NodeKind::Load { ref alias } => {
+                let (mem_nid, ptr_nid) = (node.inputs[0], node.inputs[1]);
+                let mem = self.graph.get_node(mem_nid)?.clone();
+                match &mem.node_kind {
+                    NodeKind::Store { alias: store_alias } if store_alias == alias && self.same_address(mem.inputs[1], ptr_nid)? => {
+                        Ok(mem.inputs[2]) // load after store
+                    }
+                    NodeKind::Store { alias: store_alias } if store_alias != alias || self.never_alias(mem.inputs[1], ptr_nid)? => {
+                        self.add_node(vec![mem.inputs[0], ptr_nid], node.node_kind.clone(), node.typ()) // skip the unrelated store
+                    }
+                    NodeKind::Print => self.add_node(vec![mem.inputs[1], ptr_nid], node.node_kind.clone(), node.typ()),
+                    _ => Ok(nid),
+                }
+            }
             Add => {
-                let lhs_nid = node.inputs.get(0).unwrap().clone();
+                let lhs_nid = *node.inputs.first().unwrap();
                 let lhs = self.graph.get_node(lhs_nid)?;
-                let rhs_nid = node.inputs.get(1).unwrap().clone();
+                let rhs_nid = *node.inputs.get(1).unwrap();
                 let rhs = self.graph.get_node(rhs_nid)?;
                 assert!(!lhs.typ().is_constant() || !rhs.typ().is_constant(), "Already handled by peephole constant folding");
 
@@ -28,30 +124,43 @@ impl Parser {
                     return Ok(lhs_nid); // T_ARITH_IDENT
                 }
 
-                if lhs_nid == rhs_nid {
-                    let two = self.add_node(vec![], Constant, Int { constant: 2 })?;
-                    return Ok(self.add_node_unrefined(vec![lhs_nid, two], Mul)?); // T_ADD_SAME
+                // x + x, x*c + x and x*c1 + x*c2 gather into a single Mul. Otherwise every further x of a chain like
+                // x+x+x+... would be sorted down the whole left spine, recursing once per term.
+                let ((lhs_term, lhs_factor), (rhs_term, rhs_factor)) = (self.scaled_term(lhs_nid)?, self.scaled_term(rhs_nid)?);
+                if lhs_term == rhs_term {
+                    let factor = self.add_node(vec![], Constant, Int { constant: lhs_factor.wrapping_add(rhs_factor) })?;
+                    return self.add_node_unrefined(vec![lhs_term, factor], Mul); // T_ADD_SAME
+                }
+
+                // (a - c1) + c2 gathers the constants once the Sub is an Add
+                if self.canonicalize_sub && rhs.typ().is_constant() && matches!(lhs.node_kind, Sub)
+                    && let Int { constant } = self.graph.get_node(lhs.inputs[1])?.typ() {
+                    let lhs_lhs_nid = lhs.inputs[0];
+                    let add = self.with_kept_node(rhs_nid, |parser| parser.sub_as_add(lhs_lhs_nid, constant))?;
+                    return self.add_node_unrefined(vec![add, rhs_nid], Add); // T_SUB_AS_ADD
                 }
 
+                // terms are sorted by increasing uid, with the constants last so T_RIGHT_CONST can gather them
+                let sorts_after = |x: &Node, y: &Node| (x.typ().is_constant(), x.uid) > (y.typ().is_constant(), y.uid);
                 let is_lhs_add = matches!(&lhs.node_kind, Add);
                 let is_rhs_add = matches!(&rhs.node_kind, Add);
                 if !is_lhs_add && is_rhs_add {
                     let mut_node = self.graph.get_node_mut(nid)?;
                     mut_node.inputs[0] = rhs_nid;
                     mut_node.inputs[1] = lhs_nid;
-                    return Ok(self.peephole(nid)?); // T_LEFT_SPINE
+                    return self.peephole(nid); // T_LEFT_SPINE
                 }
 
                 if is_rhs_add {
-                    let rhs_lhs_nid = rhs.inputs.get(0).unwrap().clone();
-                    let rhs_rhs_nid = rhs.inputs.get(1).unwrap().clone();
+                    let rhs_lhs_nid = *rhs.inputs.first().unwrap();
+                    let rhs_rhs_nid = *rhs.inputs.get(1).unwrap();
                     let inner = self.add_node_unrefined(vec![lhs_nid, rhs_lhs_nid], Add)?;
                     let outer = self.add_node_unrefined(vec![inner, rhs_rhs_nid], Add)?;
                     return Ok(outer); // T_ASSOCIATIVITY
                 }
 
                 if !is_lhs_add && !is_rhs_add {
-                    return if lhs.uid > rhs.uid {
+                    return if sorts_after(lhs, rhs) {
                         let mut_node = self.graph.get_node_mut(nid)?;
                         mut_node.inputs[0] = rhs_nid;
                         mut_node.inputs[1] = lhs_nid;
@@ -62,8 +171,8 @@ impl Parser {
                 }
 
                 if is_lhs_add {
-                    let lhs_lhs_nid = lhs.inputs.get(0).unwrap().clone();
-                    let lhs_rhs_nid = lhs.inputs.get(1).unwrap().clone();
+                    let lhs_lhs_nid = *lhs.inputs.first().unwrap();
+                    let lhs_rhs_nid = *lhs.inputs.get(1).unwrap();
                     let lhs_rhs = self.graph.get_node(lhs_rhs_nid)?;
                     if lhs_rhs.typ().is_constant() && rhs.typ().is_constant() {
                         let inner = self.add_node_unrefined(vec![lhs_rhs_nid, rhs_nid], Add)?;
@@ -71,7 +180,7 @@ impl Parser {
                         return Ok(outer); // T_RIGHT_CONST
                     }
 
-                    if lhs_rhs.uid > rhs.uid {
+                    if sorts_after(lhs_rhs, rhs) {
                         let inner = self.add_node_unrefined(vec![lhs_lhs_nid, rhs_nid], Add)?;
                         let outer = self.add_node_unrefined(vec![inner, lhs_rhs_nid], Add)?;
                         return Ok(outer);  // T_CANONIC_INC_NID
@@ -79,11 +188,20 @@ impl Parser {
                 }
                 Ok(nid)
             }
-            Sub => Ok(nid),
+            Sub => {
+                let lhs = self.graph.get_node(node.inputs[0])?;
+                // (a + c1) - c2 gathers the constants as an Add
+                if self.canonicalize_sub && matches!(lhs.node_kind, Add) && self.graph.get_node(lhs.inputs[1])?.typ().is_constant()
+                    && let Int { constant } = self.graph.get_node(node.inputs[1])?.typ() {
+                    return self.sub_as_add(node.inputs[0], constant); // T_SUB_AS_ADD
+                }
+                Ok(nid)
+            }
+            NodeKind::Pow => Ok(nid),
             Mul => {
-                let lhs_nid = node.inputs.get(0).unwrap().clone();
+                let lhs_nid = *node.inputs.first().unwrap();
                 let lhs = self.graph.get_node(lhs_nid)?;
-                let rhs_nid = node.inputs.get(1).unwrap().clone();
+                let rhs_nid = *node.inputs.get(1).unwrap();
                 let rhs = self.graph.get_node(rhs_nid)?;
 
                 if let Int { constant } = rhs.typ() && constant == 1 {
@@ -94,28 +212,47 @@ impl Parser {
                     let mut_node = self.graph.get_node_mut(nid)?;
                     mut_node.inputs[0] = rhs_nid;
                     mut_node.inputs[1] = lhs_nid;
-                    return Ok(self.peephole(nid)?)  // T_RIGHT_CONST
+                    return self.peephole(nid)  // T_RIGHT_CONST
                 }
 
                 Ok(nid)
             }
             Div => {
-                let lhs_nid = node.inputs.get(0).unwrap().clone();
-                let rhs_nid = node.inputs.get(1).unwrap().clone();
+                let lhs_nid = *node.inputs.first().unwrap();
+                let rhs_nid = *node.inputs.get(1).unwrap();
                 let rhs = self.graph.get_node(rhs_nid)?;
 
                 if let Int { constant } = rhs.typ() && constant == 1 {
                     return Ok(lhs_nid); // T_ARITH_IDENT
                 }
+                // only a divisor that is provably nonzero, otherwise the division by zero would be folded away.
+                // A constant divisor is folded before, so this needs bounds that exclude zero.
+                if lhs_nid == rhs_nid && let Some((min, max)) = self.graph.int_bounds(rhs)? && (min > 0 || max < 0) {
+                    return self.add_node(vec![], Constant, Int { constant: 1 }); // T_DIV_SAME
+                }
+                Ok(nid)
+            }
+            // saturation isn't associative, so only the identities apply
+            NodeKind::AddSat | NodeKind::SubSat => {
+                if let Int { constant: 0 } = self.graph.get_node(node.inputs[1])?.typ() {
+                    return Ok(node.inputs[0]); // T_ARITH_IDENT
+                }
+                Ok(nid)
+            }
+            NodeKind::MulSat => {
+                if let Int { constant: 1 } = self.graph.get_node(node.inputs[1])?.typ() {
+                    return Ok(node.inputs[0]); // T_ARITH_IDENT
+                }
                 Ok(nid)
             }
             Minus => Ok(nid),
+            NodeKind::Abs | NodeKind::Min | NodeKind::Max => Ok(nid),
             Scope { .. } => Ok(nid),
             Proj { .. } => Ok(nid),
             Comp { kind: ref comp_node_kind } => {
-                let lhs_nid = node.inputs.get(0).unwrap().clone();
+                let lhs_nid = *node.inputs.first().unwrap();
                 let lhs = self.graph.get_node(lhs_nid)?;
-                let rhs_nid = node.inputs.get(1).unwrap().clone();
+                let rhs_nid = *node.inputs.get(1).unwrap();
                 let rhs = self.graph.get_node(rhs_nid)?;
 
                 if matches!(rhs.typ(), Bool { constant: _a @ true }) && matches!(comp_node_kind, LogAnd)
@@ -128,10 +265,38 @@ impl Parser {
 
                 if lhs_nid == rhs_nid && matches!(comp_node_kind, LogXor) {
                     if matches!(node.typ(), Int { .. } | IntBot | IntTop ) {
-                        return Ok(self.add_node(vec![], Constant, Int { constant: 0 })?); // T_ADD_SAME
+                        return self.add_node(vec![], Constant, Int { constant: 0 }); // T_ADD_SAME
                     }
                     if matches!(node.typ(),  Bool { .. } | BoolTop | BoolBot ) {
-                        return Ok(self.add_node(vec![], Constant, Bool { constant: false })?); // T_ADD_SAME
+                        return self.add_node(vec![], Constant, Bool { constant: false }); // T_ADD_SAME
+                    }
+                }
+
+                if lhs.typ().is_int() && rhs.typ().is_int() && matches!(comp_node_kind, LogAnd | LogOr | LogXor) {
+                    let (value_nid, constant_nid, constant) = match (lhs.typ(), rhs.typ()) {
+                        (_, Int { constant }) => (lhs_nid, rhs_nid, Some(constant)),
+                        (Int { constant }, _) => (rhs_nid, lhs_nid, Some(constant)),
+                        _ => (lhs_nid, rhs_nid, None),
+                    };
+                    match (comp_node_kind, constant) {
+                        (LogAnd, Some(0)) | (LogOr, Some(-1)) => return Ok(constant_nid), // T_ARITH_ZERO
+                        (LogAnd, Some(-1)) | (LogOr | LogXor, Some(0)) => return Ok(value_nid), // T_ARITH_IDENT
+                        _ => {}
+                    }
+                }
+
+                if lhs.typ().is_bool() && rhs.typ().is_bool() && matches!(comp_node_kind, LogAnd | LogOr) {
+                    let negates = |x: &Node, y: usize| matches!(x.node_kind, NodeKind::Not) && x.inputs[0] == y;
+                    if negates(lhs, rhs_nid) || negates(rhs, lhs_nid) {
+                        return self.add_node(vec![], Constant, Bool { constant: matches!(comp_node_kind, LogOr) }); // T_COMPLEMENT
+                    }
+                    let dual = if matches!(comp_node_kind, LogAnd) { LogOr } else { LogAnd };
+                    let absorbs = |x: &Node, y: usize| matches!(&x.node_kind, Comp { kind } if *kind == dual) && x.inputs.contains(&y);
+                    if absorbs(rhs, lhs_nid) {
+                        return Ok(lhs_nid); // T_ABSORPTION
+                    }
+                    if absorbs(lhs, rhs_nid) {
+                        return Ok(rhs_nid); // T_ABSORPTION
                     }
                 }
 
@@ -142,13 +307,13 @@ impl Parser {
                     let mut_node = self.graph.get_node_mut(nid)?;
                     mut_node.inputs[0] = rhs_nid;
                     mut_node.inputs[1] = lhs_nid;
-                    return Ok(self.peephole(nid)?); // T_LEFT_SPINE
+                    return self.peephole(nid); // T_LEFT_SPINE
                 }
 
                 if matches!(&rhs.node_kind, Comp { kind: rhs_comp_node_kind } if rhs_comp_node_kind == comp_node_kind)
                     && matches!(comp_node_kind, LogXor | LogAnd | LogOr) {
-                    let rhs_lhs_nid = rhs.inputs.get(0).unwrap().clone();
-                    let rhs_rhs_nid = rhs.inputs.get(1).unwrap().clone();
+                    let rhs_lhs_nid = *rhs.inputs.first().unwrap();
+                    let rhs_rhs_nid = *rhs.inputs.get(1).unwrap();
                     let inner = self.add_node_unrefined(vec![lhs_nid, rhs_lhs_nid], Comp { kind: comp_node_kind.clone() })?;
                     let outer = self.add_node_unrefined(vec![inner, rhs_rhs_nid], Comp { kind: comp_node_kind.clone() })?;
                     return Ok(outer); // T_ASSOCIATIVITY
@@ -169,31 +334,54 @@ impl Parser {
 
                 if matches!(&lhs.node_kind, Comp { kind: lhs_comp_node_kind } if lhs_comp_node_kind == comp_node_kind)
                     && matches!(comp_node_kind, LogXor | LogAnd | LogOr) {
-                    let lhs_lhs_nid = lhs.inputs.get(0).unwrap().clone();
-                    let lhs_rhs_nid = lhs.inputs.get(1).unwrap().clone();
+                    let lhs_lhs_nid = *lhs.inputs.first().unwrap();
+                    let lhs_rhs_nid = *lhs.inputs.get(1).unwrap();
                     let lhs_rhs = self.graph.get_node(lhs_rhs_nid)?;
                     if lhs_rhs.typ().is_constant() && rhs.typ().is_constant() {
                         let inner = self.add_node_unrefined(vec![lhs_rhs_nid, rhs_nid], Comp { kind: comp_node_kind.clone() })?;
                         let outer = self.add_node_unrefined(vec![lhs_lhs_nid, inner], Comp { kind: comp_node_kind.clone() })?;
-                        return Ok(self.peephole(outer)?); // T_RIGHT_CONST
+                        return self.peephole(outer); // T_RIGHT_CONST
                     }
                 }
 
                 if matches!(&lhs.node_kind, Comp { kind: lhs_comp_node_kind } if lhs_comp_node_kind == comp_node_kind)
                     && matches!(comp_node_kind, EQ | LogXor | LogAnd | LogOr) {
-                    let lhs_lhs_nid = lhs.inputs.get(0).unwrap().clone();
-                    let lhs_rhs_nid = lhs.inputs.get(1).unwrap().clone();
+                    let lhs_lhs_nid = *lhs.inputs.first().unwrap();
+                    let lhs_rhs_nid = *lhs.inputs.get(1).unwrap();
                     let lhs_rhs = self.graph.get_node(lhs_rhs_nid)?;
                     if lhs_rhs.uid > rhs.uid {
                         let inner = self.add_node_unrefined(vec![lhs_lhs_nid, rhs_nid], Comp { kind: comp_node_kind.clone() })?;
                         let outer = self.add_node_unrefined(vec![inner, lhs_rhs_nid], Comp { kind: comp_node_kind.clone() })?;
-                        return Ok(self.peephole(outer)?);  // T_CANONIC_INC_NID
+                        return self.peephole(outer);  // T_CANONIC_INC_NID
                     }
                 }
 
                 Ok(nid)
             }
-            NodeKind::Not => Ok(nid)
+            NodeKind::Not => {
+                let operand = self.graph.get_node(node.inputs[0])?.clone();
+                let Comp { kind: ref comp_node_kind @ (LogAnd | LogOr) } = operand.node_kind else {
+                    return Ok(nid);
+                };
+                let (lhs, rhs) = (self.graph.get_node(operand.inputs[0])?, self.graph.get_node(operand.inputs[1])?);
+                let negated_inputs = [lhs, rhs].iter().filter(|input| matches!(input.node_kind, NodeKind::Not)).count();
+                // only when it removes a negation, otherwise the rewrite would grow the graph
+                if !lhs.typ().is_bool() || !rhs.typ().is_bool() || negated_inputs == 0 {
+                    return Ok(nid);
+                }
+                let dual = if matches!(comp_node_kind, LogAnd) { LogOr } else { LogAnd };
+                let lhs = self.negate(operand.inputs[0])?;
+                let rhs = self.with_kept_node(lhs, |parser| parser.negate(operand.inputs[1]))?;
+                self.add_node_unrefined(vec![lhs, rhs], Comp { kind: dual }) // T_DE_MORGAN
+            }
+            NodeKind::Cast { ref to } => {
+                let lhs_nid = *node.inputs.first().unwrap();
+                let lhs = self.graph.get_node(lhs_nid)?;
+                if matches!((to, lhs.typ()), (IntBot, Int { .. } | IntBot | IntTop) | (BoolBot, Bool { .. } | BoolBot | BoolTop)) {
+                    return Ok(lhs_nid); // T_ARITH_IDENT
+                }
+                Ok(nid)
+            }
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,67 @@
+use crate::nodes::node::{CompNodeKind, NodeKind};
+
+/// precedence of the prefix operators `-` and `!`. Binds stronger than every binary operator but `**`,
+/// so `-2 ** 2` is `-(2 ** 2)`.
+pub const PREFIX_PRECEDENCE: u8 = 10;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Associativity {
+    Left,
+    Right,
+}
+
+/// A binary infix operator of the expression grammar.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BinaryOperator {
+    pub lexeme: String,
+    /// operators with a higher precedence bind stronger
+    pub precedence: u8,
+    pub associativity: Associativity,
+    /// the node that is built from lhs and rhs
+    pub node_kind: NodeKind,
+    /// wraps the built node in a Not node, e.g. `a != b` is `!(a == b)`
+    pub negated: bool,
+}
+
+impl BinaryOperator {
+    pub fn new(lexeme: &str, precedence: u8, associativity: Associativity, node_kind: NodeKind) -> BinaryOperator {
+        BinaryOperator { lexeme: lexeme.into(), precedence, associativity, node_kind, negated: false }
+    }
+
+    pub fn negated(mut self) -> BinaryOperator {
+        self.negated = !self.negated;
+        self
+    }
+
+    /// the default operator with the given lexeme
+    pub fn default_for(lexeme: &str) -> Option<BinaryOperator> {
+        Self::defaults().into_iter().find(|operator| operator.lexeme == lexeme)
+    }
+
+    /// the operators of the language, from weakest to strongest binding
+    pub fn defaults() -> Vec<BinaryOperator> {
+        use Associativity::{Left, Right};
+        let comp = |kind| NodeKind::Comp { kind };
+        vec![
+            BinaryOperator::new("||", 1, Left, comp(CompNodeKind::LogOr)),
+            BinaryOperator::new("&&", 2, Left, comp(CompNodeKind::LogAnd)),
+            BinaryOperator::new("|", 3, Left, comp(CompNodeKind::LogOr)),
+            BinaryOperator::new("^", 4, Left, comp(CompNodeKind::LogXor)),
+            BinaryOperator::new("&", 5, Left, comp(CompNodeKind::LogAnd)),
+            BinaryOperator::new("==", 6, Left, comp(CompNodeKind::EQ)),
+            BinaryOperator::new("!=", 6, Left, comp(CompNodeKind::EQ)).negated(),
+            BinaryOperator::new("<", 7, Left, comp(CompNodeKind::LT)),
+            BinaryOperator::new("<=", 7, Left, comp(CompNodeKind::LEQ)),
+            BinaryOperator::new(">", 7, Left, comp(CompNodeKind::LEQ)).negated(),
+            BinaryOperator::new(">=", 7, Left, comp(CompNodeKind::LT)).negated(),
+            BinaryOperator::new("+", 8, Left, NodeKind::Add),
+            BinaryOperator::new("-", 8, Left, NodeKind::Sub),
+            BinaryOperator::new("+|", 8, Left, NodeKind::AddSat),
+            BinaryOperator::new("-|", 8, Left, NodeKind::SubSat),
+            BinaryOperator::new("*", 9, Left, NodeKind::Mul),
+            BinaryOperator::new("*|", 9, Left, NodeKind::MulSat),
+            BinaryOperator::new("/", 9, Left, NodeKind::Div),
+            BinaryOperator::new("**", PREFIX_PRECEDENCE + 1, Right, NodeKind::Pow),
+        ]
+    }
+}
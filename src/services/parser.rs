@@ -1,58 +1,293 @@
-use crate::errors::son_error::SoNError::{DebugPropagateControlFlowUpward, VariableUndefined};
-use crate::errors::son_error::SoNError::{SyntaxExpected, VariableRedefinition};
+use crate::ast::expr::Expr;
+use crate::ast::stmt::Stmt;
+use crate::errors::son_error::SoNError::SyntaxExpected;
 use crate::errors::son_error::{ErrorWithContext, SoNError};
-use crate::nodes::node::{CompNodeKind, Graph, NodeKind};
-use crate::services::lexer::Lexer;
+use crate::nodes::node::{Graph, Node, NodeKind};
+use crate::services::lexer::{Lexer, NumberLiteral, Token};
+use crate::services::operators::{Associativity, BinaryOperator, PREFIX_PRECEDENCE};
 use crate::typ::typ::Typ;
 use crate::typ::typ::Typ::{Bot, Ctrl};
 use once_cell::sync::Lazy;
 use std::collections::hash_map::Values;
 use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::Read;
+use std::path::Path;
 
 pub static KEYWORDS: Lazy<HashSet<String>> = Lazy::new(|| {
-    HashSet::from(["int".into(), "return".into()])
+    HashSet::from(["int".into(), "bool".into(), "return".into(), "print".into(), "struct".into(), "new".into(), "true".into(), "false".into(), "if".into(), "else".into(), "const".into()])
 });
 
 pub struct Parser {
     pub lexer: Lexer,
     pub graph: Graph,
     /// peephole optimization
-    pub do_optimize: bool,
+    do_optimize: bool,
     pub _dbg_output: String,
+    operators: Vec<BinaryOperator>,
+    /// callees with more nodes are not inlined by [Parser::inline_calls]
+    pub(crate) max_inline_nodes: usize,
+    /// see [ParserOptions::implicit_block]
+    implicit_block: bool,
+    /// see [ParserOptions::check_monotonicity]
+    check_monotonicity: bool,
+    /// see [ParserOptions::gc_cap]
+    gc_cap: usize,
+    /// see [ParserOptions::disabled_folds]
+    disabled_folds: Vec<NodeKind>,
+    /// see [ParserOptions::canonicalize_sub]
+    pub(crate) canonicalize_sub: bool,
+    /// see [ParserOptions::max_nodes]
+    max_nodes: usize,
+    /// see [ParserOptions::max_nesting_depth]
+    max_nesting_depth: usize,
+    /// expressions and statements the parser is currently inside of
+    depth: usize,
+    /// source position of the statement or expression being lowered. Errors of the lowering and the nodes it adds point at it.
+    pub(crate) lowering_pos: usize,
+    /// declared variables of each open scope, parallel to the scopes of the Scope node
+    declarations: Vec<Vec<Declaration>>,
+    unused_variables: Vec<Declaration>,
+    /// names and positions of the declarations that shadow a variable of an enclosing scope
+    pub(crate) shadowing_declarations: Vec<(String, usize)>,
+    /// names and typs of the parameters, projected from Start in this order
+    pub(crate) params: Vec<(String, Typ)>,
+    /// struct typs declared so far, by name
+    pub(crate) structs: HashMap<String, Typ>,
+    /// functions lowered so far, by name
+    pub(crate) functions: HashMap<String, Function>,
+}
+
+/// A function of the program. Calls reference its entry node.
+#[derive(Clone, Debug)]
+pub(crate) struct Function {
+    pub(crate) entry: usize,
+    pub(crate) params: Vec<(String, Typ)>,
+    pub(crate) ret_typ: Typ,
+    /// the last statement of the body, once it is lowered
+    pub(crate) ret: Option<usize>,
+}
+
+#[derive(Clone, Debug)]
+struct Declaration {
+    name: String,
+    pos: usize,
+    is_read: bool,
+}
+
+/// Construction options of a [Parser]. Override single options with `..Default::default()`.
+#[derive(Clone, Debug)]
+pub struct ParserOptions {
+    /// peephole optimization
+    pub do_optimize: bool,
+    /// names and typs of the parameters. Each is projected from the Start node and defined as a variable.
+    pub params: Vec<(String, Typ)>,
+    /// binary operators of the expression grammar. Extend [BinaryOperator::defaults] to add custom operators.
+    pub operators: Vec<BinaryOperator>,
+    /// callees with more nodes are not inlined by [Parser::inline_calls]
+    pub max_inline_nodes: usize,
+    /// the program is a sequence of statements like the body of a block. Otherwise it has to be a single block.
+    pub implicit_block: bool,
+    /// Debugging aid: fails a pass with TypTransitionNotAllowed if it moved the typ of a node or of the input of a use
+    /// downwards. Each peephole of [Parser::parse] is checked against the node it replaces, and [Parser::inline_calls] as a whole.
+    pub check_monotonicity: bool,
+    /// The most nodes the incremental garbage collection drops at once, e.g. on every added node.
+    /// A larger cap collects more per node at the cost of latency.
+    pub gc_cap: usize,
+    /// Debugging aid: nodes of these kinds are not constant folded, e.g. `vec![NodeKind::Mul]` keeps `2*3`
+    /// while `2+3` still folds. Comparisons are disabled per operator, e.g. `NodeKind::Comp { kind: CompNodeKind::LT }`.
+    pub disabled_folds: Vec<NodeKind>,
+    /// rewrites `a - c` into `a + (-c)` for a constant `c` when the Add can gather it with another constant,
+    /// e.g. `arg - 3 + 5` becomes `arg + 2`
+    pub canonicalize_sub: bool,
+    /// The most live nodes the graph may hold. Adding another one fails with GraphSizeLimitExceeded,
+    /// which guards tools parsing untrusted programs. Nodes dropped by the garbage collection don't count.
+    pub max_nodes: usize,
+    /// The deepest the parser recurses into nested expressions and blocks, e.g. parentheses or unary operators.
+    /// Deeper programs fail with NestingTooDeep instead of overflowing the stack. The default of 64 leaves room
+    /// for lowering on the 2 MiB stack of a spawned thread, even in debug builds.
+    pub max_nesting_depth: usize,
+}
+
+impl Default for ParserOptions {
+    fn default() -> Self {
+        ParserOptions { do_optimize: true, params: vec![("arg".into(), Typ::IntBot)], operators: BinaryOperator::defaults(), max_inline_nodes: 16, implicit_block: true, check_monotonicity: false, gc_cap: 100, disabled_folds: vec![], canonicalize_sub: true, max_nodes: usize::MAX, max_nesting_depth: 64 }
+    }
+}
+
+/// Chained construction of a [Parser], e.g. `ParserBuilder::new().do_optimize(false).build(program, 3)`.
+#[derive(Clone, Debug, Default)]
+pub struct ParserBuilder {
+    options: ParserOptions,
+}
+
+impl ParserBuilder {
+    pub fn new() -> ParserBuilder {
+        ParserBuilder::default()
+    }
+
+    pub fn do_optimize(mut self, do_optimize: bool) -> ParserBuilder {
+        self.options.do_optimize = do_optimize;
+        self
+    }
+
+    pub fn operators(mut self, operators: Vec<BinaryOperator>) -> ParserBuilder {
+        self.options.operators = operators;
+        self
+    }
+
+    pub fn max_inline_nodes(mut self, max_inline_nodes: usize) -> ParserBuilder {
+        self.options.max_inline_nodes = max_inline_nodes;
+        self
+    }
+
+    pub fn implicit_block(mut self, implicit_block: bool) -> ParserBuilder {
+        self.options.implicit_block = implicit_block;
+        self
+    }
+
+    pub fn check_monotonicity(mut self, check_monotonicity: bool) -> ParserBuilder {
+        self.options.check_monotonicity = check_monotonicity;
+        self
+    }
+
+    pub fn gc_cap(mut self, gc_cap: usize) -> ParserBuilder {
+        self.options.gc_cap = gc_cap;
+        self
+    }
+
+    pub fn disable_fold(mut self, kind: NodeKind) -> ParserBuilder {
+        self.options.disabled_folds.push(kind);
+        self
+    }
+
+    pub fn canonicalize_sub(mut self, canonicalize_sub: bool) -> ParserBuilder {
+        self.options.canonicalize_sub = canonicalize_sub;
+        self
+    }
+
+    pub fn max_nodes(mut self, max_nodes: usize) -> ParserBuilder {
+        self.options.max_nodes = max_nodes;
+        self
+    }
+
+    pub fn max_nesting_depth(mut self, max_nesting_depth: usize) -> ParserBuilder {
+        self.options.max_nesting_depth = max_nesting_depth;
+        self
+    }
+
+    pub fn params(mut self, params: Vec<(String, Typ)>) -> ParserBuilder {
+        self.options.params = params;
+        self
+    }
+
+    /// Builds a parser whose only parameter has the given value. It is named like the configured parameter, `arg` by default.
+    /// More than one configured parameter is an ArgumentCountMismatch, since only one value is given.
+    pub fn build(self, program: &str, arg: i64) -> Result<Parser, SoNError> {
+        if self.options.params.len() > 1 {
+            return Err(SoNError::ArgumentCountMismatch { expected: self.options.params.len(), but_got: 1 });
+        }
+        let name = self.options.params.first().map_or_else(|| "arg".into(), |(name, _)| name.clone());
+        Parser::with_options(program, ParserOptions { params: vec![(name, Typ::Int { constant: arg })], ..self.options })
+    }
+
+    /// builds a parser with the configured parameters, by default an unknown `arg`
+    pub fn build_noarg(self, program: &str) -> Result<Parser, SoNError> {
+        Parser::with_options(program, self.options)
+    }
 }
 
+/// tokens of the statement grammar that can't be used as operators
+const STRUCTURAL_TOKENS: [&str; 9] = ["{", "}", "(", ")", "[", "]", ";", "=", ","];
+
 pub(crate) const KEEP_ALIVE_NID: usize = 0;
 pub(crate) const SCOPE_NID: usize = 1;
 pub(crate) const START_NID: usize = 2;
+/// Start projects control, memory and then the arguments
+pub(crate) const FIRST_ARG_PROJ_INDEX: usize = 2;
+
+/// the 1-based (line, column) where a range of the source starts and where it ends
+pub type SourceRange = ((usize, usize), (usize, usize));
 
 impl Parser {
-    fn new_internal(program: &str, arg: Typ) -> Result<Parser, SoNError> {
-        let mut ctx = Parser { lexer: Lexer::from_string(format!("{{{}}}", program)), graph: Graph::new(), do_optimize: true, _dbg_output: "".into() };
-        ctx.add_node_unrefined(vec![], NodeKind::KeepAlive)?;
-        let scope_nid = ctx.add_node_unrefined(vec![], NodeKind::Scope { scopes: vec![] })?;
+    pub fn with_options(program: &str, options: ParserOptions) -> Result<Parser, SoNError> {
+        for operator in &options.operators {
+            let lexeme = operator.lexeme.as_str();
+            if lexeme.is_empty() || KEYWORDS.contains(lexeme) || STRUCTURAL_TOKENS.contains(&lexeme) {
+                return Err(SoNError::InvalidOperator { lexeme: lexeme.into() });
+            }
+        }
+        let mut ctx = Parser { lexer: Lexer::from_string(String::new()), graph: Graph::new(), do_optimize: options.do_optimize, _dbg_output: "".into(), operators: options.operators, max_inline_nodes: options.max_inline_nodes, implicit_block: options.implicit_block, check_monotonicity: options.check_monotonicity, gc_cap: options.gc_cap, disabled_folds: options.disabled_folds, canonicalize_sub: options.canonicalize_sub, max_nodes: options.max_nodes, max_nesting_depth: options.max_nesting_depth, depth: 0, lowering_pos: 0, declarations: vec![], unused_variables: vec![], shadowing_declarations: vec![], params: vec![], structs: HashMap::new(), functions: HashMap::new() };
+        ctx.reset(program, options.params)?;
+        Ok(ctx)
+    }
+
+    /// Replaces the program and starts over with a fresh graph. The options are kept.
+    pub fn reset(&mut self, program: &str, params: Vec<(String, Typ)>) -> Result<(), SoNError> {
+        self.lexer = Lexer::from_string(program.into());
+        self.graph = Graph::new();
+        self.graph.disabled_folds = self.disabled_folds.clone();
+        self.graph.max_nodes = self.max_nodes;
+        self._dbg_output.clear();
+        self.lowering_pos = 0;
+        self.declarations.clear();
+        self.unused_variables.clear();
+        self.shadowing_declarations.clear();
+        self.structs.clear();
+        self.functions.clear();
+        self.add_node_unrefined(vec![], NodeKind::KeepAlive)?;
+        let scope_nid = self.add_node_unrefined(vec![], NodeKind::Scope { scopes: vec![], declared_typs: vec![], consts: vec![] })?;
         assert_eq!(SCOPE_NID, scope_nid);
-        ctx.keep_node(scope_nid)?;
-        let start_nid = ctx.add_node(vec![], NodeKind::Start, Typ::Tuple { typs: vec![Ctrl, arg] })?;
+        self.keep_node(scope_nid)?;
+        let typs = [Ctrl, Typ::Mem].into_iter().chain(params.iter().map(|(_, typ)| typ.clone())).collect();
+        let start_nid = self.add_node(vec![], NodeKind::Start, Typ::Tuple { typs })?;
         assert_eq!(START_NID, start_nid);
-        ctx.keep_node(start_nid)?;
+        self.keep_node(start_nid)?;
+        self.params = params;
+        Ok(())
+    }
 
-        Ok(ctx)
+    fn new_internal(program: &str, arg: Typ) -> Result<Parser, SoNError> {
+        Self::with_options(program, ParserOptions { params: vec![("arg".into(), arg)], ..Default::default() })
     }
 
     pub fn new(program: &str, arg: i64) -> Result<Parser, SoNError> {
-        Self::new_internal(program, Typ::Int { constant: arg.clone() })
+        Self::new_internal(program, Typ::Int { constant: arg })
     }
 
     pub fn new_noarg(program: &str) -> Result<Parser, SoNError> {
         Self::new_internal(program, Typ::IntBot)
     }
 
-    fn get_var(&self, name: &str) -> Option<usize> {
-        if let NodeKind::Scope { scopes } = &self.graph.get_node(SCOPE_NID).expect("Scope node not present.").node_kind {
-            assert!(scopes.len() >= 1, "Tried to access scope, but none was there.");
+    pub fn from_file(path: impl AsRef<Path>, arg: i64) -> Result<Parser, SoNError> {
+        let path = path.as_ref();
+        let program = fs::read_to_string(path)
+            .map_err(|e| SoNError::Io { path: path.display().to_string(), message: e.to_string() })?;
+        Self::new(&program, arg)
+    }
+
+    /// reads the whole program from `reader`, e.g. stdin, before parsing it
+    pub fn from_reader(reader: impl Read, arg: i64) -> Result<Parser, SoNError> {
+        Self::new(&Self::read_program(reader)?, arg)
+    }
+
+    /// like [Parser::from_reader] but with an unknown `arg`
+    pub fn from_reader_noarg(reader: impl Read) -> Result<Parser, SoNError> {
+        Self::new_noarg(&Self::read_program(reader)?)
+    }
+
+    fn read_program(reader: impl Read) -> Result<String, SoNError> {
+        let lexer = Lexer::from_reader(reader)
+            .map_err(|e| SoNError::Io { path: "<reader>".into(), message: e.to_string() })?;
+        Ok(lexer.input)
+    }
+
+    pub(crate) fn get_var(&self, name: &str) -> Option<usize> {
+        if let NodeKind::Scope { scopes, .. } = &self.graph.get_node(SCOPE_NID).expect("Scope node not present.").node_kind {
+            assert!(!scopes.is_empty(), "Tried to access scope, but none was there.");
             for scope in scopes.iter().rev() {
-                if let Some(val) = scope.get(name.into()) {
-                    return Some(val.clone());
+                if let Some(val) = scope.get(name) {
+                    return Some(*val);
                 }
             }
             return None;
@@ -60,15 +295,39 @@ impl Parser {
         panic!("Scope node was not scope kind.")
     }
 
-    fn define_var(&mut self, name: &str, nid: usize) -> Result<(), SoNError> {
+    /// the error for reading or assigning the undefined variable name. Suggests the closest variable in scope
+    /// that is at most 2 edits away.
+    pub(crate) fn variable_undefined(&self, name: &str) -> SoNError {
+        let NodeKind::Scope { scopes, .. } = &self.graph.get_node(SCOPE_NID).expect("Scope node not present.").node_kind else {
+            panic!("Scope node was not scope kind.")
+        };
+        let suggestion = scopes.iter()
+            .flat_map(|scope| scope.keys())
+            .filter(|candidate| !candidate.starts_with('$'))
+            .map(|candidate| (edit_distance(name, candidate), candidate))
+            .filter(|(distance, _)| *distance <= 2)
+            .min()
+            .map(|(_, candidate)| candidate.clone());
+        SoNError::VariableUndefined { variable: name.into(), suggestion }
+    }
+
+    pub(crate) fn get_declared_typ(&self, name: &str) -> Option<Typ> {
+        if let NodeKind::Scope { declared_typs, .. } = &self.graph.get_node(SCOPE_NID).expect("Scope node not present.").node_kind {
+            return declared_typs.iter().rev().find_map(|scope| scope.get(name)).cloned();
+        }
+        panic!("Scope node was not scope kind.")
+    }
+
+    pub(crate) fn define_var(&mut self, name: &str, nid: usize, declared_typ: Typ) -> Result<(), SoNError> {
         self.graph.add_reverse_dependencies_br(SCOPE_NID, &vec![nid])?;
         self.graph.add_dependencies_br(SCOPE_NID, &vec![nid])?;
 
-        if let NodeKind::Scope { scopes } = &mut self.graph.get_node_mut(SCOPE_NID)?.node_kind {
-            if let Some(scope) = scopes.last_mut() {
+        if let NodeKind::Scope { scopes, declared_typs, .. } = &mut self.graph.get_node_mut(SCOPE_NID)?.node_kind {
+            if let Some(scope) = scopes.last_mut() && let Some(typs) = declared_typs.last_mut() {
                 if scope.insert(name.into(), nid).is_some() {
                     panic!("Variable was already defined. Undefine it first.");
                 }
+                typs.insert(name.into(), declared_typ);
                 return Ok(());
             }
             panic!("Tried to access scope, but none was there.")
@@ -76,20 +335,73 @@ impl Parser {
         panic!("Scope node was not scope kind.")
     }
 
-    fn undefine_var(&mut self, name: &str) -> Result<usize, SoNError> {
-        if let NodeKind::Scope { scopes } = &mut self.graph.get_node_mut(SCOPE_NID)?.node_kind {
-            if let Some(scope) = scopes.last_mut() {
-                if let Some(nid) = scope.remove(name.into()) {
-                    self.graph.remove_dependency_br(SCOPE_NID, nid)?;
-                    return Ok(nid);
-                }
-                panic!("Tried to undefine not-defined var.")
-            }
-            panic!("Tried to access scope, but none was there.")
+    pub(crate) fn is_defined_in_innermost_scope(&self, name: &str) -> bool {
+        if let NodeKind::Scope { scopes, .. } = &self.graph.get_node(SCOPE_NID).expect("Scope node not present.").node_kind {
+            return scopes.last().is_some_and(|scope| scope.contains_key(name));
+        }
+        panic!("Scope node was not scope kind.")
+    }
+
+    /// makes a variable of the innermost scope immutable
+    pub(crate) fn make_const(&mut self, name: &str) -> Result<(), SoNError> {
+        if let NodeKind::Scope { consts, .. } = &mut self.graph.get_node_mut(SCOPE_NID)?.node_kind {
+            consts.last_mut().expect("Tried to access scope, but none was there.").insert(name.into());
+            return Ok(());
+        }
+        panic!("Scope node was not scope kind.")
+    }
+
+    /// whether the innermost scope that defines the variable declared it const
+    pub(crate) fn is_const(&self, name: &str) -> bool {
+        if let NodeKind::Scope { scopes, consts, .. } = &self.graph.get_node(SCOPE_NID).expect("Scope node not present.").node_kind {
+            return scopes.iter().zip(consts).rev().find(|(scope, _)| scope.contains_key(name)).is_some_and(|(_, consts)| consts.contains(name));
         }
         panic!("Scope node was not scope kind.")
     }
 
+    /// rebinds a variable in the innermost scope that defines it
+    pub(crate) fn update_var(&mut self, name: &str, nid: usize) -> Result<(), SoNError> {
+        self.graph.add_reverse_dependencies_br(SCOPE_NID, &vec![nid])?;
+        self.graph.add_dependencies_br(SCOPE_NID, &vec![nid])?;
+
+        if let NodeKind::Scope { scopes, .. } = &mut self.graph.get_node_mut(SCOPE_NID)?.node_kind {
+            let Some(old) = scopes.iter_mut().rev().find_map(|scope| scope.get_mut(name)) else {
+                panic!("Tried to update not-defined var.")
+            };
+            let old = std::mem::replace(old, nid);
+            return self.graph.remove_dependency_br(SCOPE_NID, old);
+        }
+        panic!("Scope node was not scope kind.")
+    }
+
+    /// Iterates over the live nodes of the graph in increasing nid order.
+    ///
+    /// ```
+    /// use ruson::nodes::node::NodeKind;
+    /// use ruson::services::parser::Parser;
+    ///
+    /// let mut parser = Parser::new_noarg("return arg+1;").unwrap();
+    /// parser.parse().unwrap();
+    /// let adds = parser.nodes().filter(|n| matches!(n.node_kind, NodeKind::Add)).count();
+    /// assert_eq!(1, adds);
+    /// ```
+    pub fn nodes(&self) -> impl Iterator<Item = &Node> {
+        self.graph.graph_iter()
+    }
+
+    /// the live node in slot nid
+    pub fn node(&self, nid: usize) -> Option<&Node> {
+        self.graph.get_node(nid).ok()
+    }
+
+    /// The lines and columns the [Node::span] of each live node covers, keyed by nid.
+    /// Nodes whose span lies outside the source are left out.
+    pub fn source_map(&self) -> HashMap<usize, SourceRange> {
+        self.nodes()
+            .filter_map(|node| Some((node.nid, (self.lexer.line_col_for(node.span.0)?, self.lexer.line_col_for(node.span.1)?))))
+            .collect()
+    }
+
     pub fn src(&self) -> String {
         self.lexer.input.clone()
     }
@@ -110,11 +422,11 @@ impl Parser {
         if nid == KEEP_ALIVE_NID {
             return 0;
         }
-        if cap <= 0 {
+        if cap == 0 {
             return 0;
         }
         let inputs = self.graph.get(nid).map(|n| match n.as_ref() {
-            Some(node) if node.outputs.is_empty() => node.inputs.clone(),
+            Some(node) if node.outputs.is_empty() && !self.graph.is_kept(nid) => node.inputs.clone(),
             _ => vec![]
         });
         let mut c = cap;
@@ -126,22 +438,25 @@ impl Parser {
                 c -= self.attempt_drop_node(neigh, c);
             }
         }
-        if c > 0 {
-            if matches!(self.graph.get_mut(nid), Some(Some(n)) if n.outputs.is_empty()) {
-                c -= 1;
-                *self.graph.get_mut(nid).unwrap() = None;
-            };
+        if c > 0 && !self.graph.is_kept(nid) && matches!(self.graph.get_mut(nid), Some(Some(n)) if n.outputs.is_empty()) {
+            c -= 1;
+            self.graph.remove_node(nid);
         }
         cap - c
     }
 
-    fn drop_unused_nodes(&mut self) -> usize {
-        self.drop_unused_nodes_cap(100)
+    /// drops up to [ParserOptions::gc_cap] unused nodes
+    pub(crate) fn drop_unused_nodes(&mut self) -> usize {
+        self.drop_unused_nodes_cap(self.gc_cap)
     }
 
     pub(crate) fn add_node(&mut self, inputs: Vec<usize>, node_kind: NodeKind, typ: Typ) -> Result<usize, SoNError> {
-        let pr = format!("add_node inputs: {:?}, node_kind: {:?}, typ: {:?}", inputs, node_kind, typ);
-        println!("{}", pr);
+        let span = self.lexer.token_span_at(self.lowering_pos);
+        self.add_node_spanned(inputs, node_kind, typ, span)
+    }
+
+    /// like [Parser::add_node], but the node and the nodes its peephole creates get `span` instead of the span of the lowered token
+    pub(crate) fn add_node_spanned(&mut self, inputs: Vec<usize>, node_kind: NodeKind, typ: Typ, span: (usize, usize)) -> Result<usize, SoNError> {
         for input in inputs.iter() {
             self.keep_node(*input)?;
         }
@@ -149,9 +464,22 @@ impl Parser {
         for input in inputs.iter() {
             self.unkeep_node(*input)?;
         }
+        self.graph.span = span;
+        let result = self.new_optimized_node(inputs, node_kind, typ);
+        // nodes created by later passes don't come from a source token
+        self.graph.span = (0, 0);
+        result
+    }
+
+    fn new_optimized_node(&mut self, inputs: Vec<usize>, node_kind: NodeKind, typ: Typ) -> Result<usize, SoNError> {
         let mut nid = self.graph.new_node(inputs, node_kind, typ)?;
         if self.do_optimize {
+            let typ = self.graph.get_node(nid)?.typ();
             nid = self.peephole(nid)?;
+            // the replacement has to be at least as precise as the node it replaces
+            if self.check_monotonicity && !typ.transition_allowed(&self.graph.get_node(nid)?.typ()) {
+                return Err(SoNError::TypTransitionNotAllowed);
+            }
             self.keep_node(nid)?;
             self.drop_unused_nodes();
             self.unkeep_node(nid)?;
@@ -178,21 +506,49 @@ impl Parser {
         Ok(nid)
     }
 
-    fn ctrl(&self) -> usize {
+    pub(crate) fn ctrl(&self) -> usize {
         self.get_var("$ctrl").expect("Assertion failed: $ctrl is undefined")
     }
 
-    fn push_scope(&mut self) -> Result<(), SoNError> {
-        if let NodeKind::Scope { scopes } = &mut self.graph.get_node_mut(SCOPE_NID)?.node_kind {
+    pub(crate) fn mem(&self) -> usize {
+        self.get_var("$mem").expect("Assertion failed: $mem is undefined")
+    }
+
+    /// Runs `f` without any of the open scopes, e.g. to lower a function that must not see the variables around it.
+    pub(crate) fn with_fresh_scopes<F, R>(&mut self, f: F) -> Result<R, SoNError>
+    where
+        F: FnOnce(&mut Self) -> Result<R, SoNError>,
+    {
+        let NodeKind::Scope { scopes, declared_typs, consts } = &mut self.graph.get_node_mut(SCOPE_NID)?.node_kind else {
+            panic!("Scope node was not scope kind.")
+        };
+        let saved = (std::mem::take(scopes), std::mem::take(declared_typs), std::mem::take(consts), std::mem::take(&mut self.declarations));
+        let result = f(self);
+        let NodeKind::Scope { scopes, declared_typs, consts } = &mut self.graph.get_node_mut(SCOPE_NID)?.node_kind else {
+            panic!("Scope node was not scope kind.")
+        };
+        (*scopes, *declared_typs, *consts, self.declarations) = saved;
+        result
+    }
+
+    pub(crate) fn push_scope(&mut self) -> Result<(), SoNError> {
+        if let NodeKind::Scope { scopes, declared_typs, consts } = &mut self.graph.get_node_mut(SCOPE_NID)?.node_kind {
             scopes.push(HashMap::new());
+            declared_typs.push(HashMap::new());
+            consts.push(HashSet::new());
+            self.declarations.push(vec![]);
             return Ok(())
         }
         panic!("Scope node was not scope kind.")
     }
 
-    fn pop_scope(&mut self) -> Result<(), SoNError> {
+    pub(crate) fn pop_scope(&mut self) -> Result<(), SoNError> {
+        let declarations = self.declarations.pop().expect("Tried to pop scope, but none was there.");
+        self.unused_variables.extend(declarations.into_iter().filter(|d| !d.is_read));
         let node = self.graph.get_node_mut(SCOPE_NID)?;
-        if let NodeKind::Scope { scopes } = &mut node.node_kind {
+        if let NodeKind::Scope { scopes, declared_typs, consts } = &mut node.node_kind {
+            declared_typs.pop();
+            consts.pop();
             if let Some(scope) = scopes.pop() {
                 let defined_nids: Values<String, usize> = scope.values();
                 for &dep_nid in defined_nids {
@@ -205,68 +561,165 @@ impl Parser {
         panic!("Scope node was not scope kind.")
     }
 
-    pub fn parse(&mut self) -> Result<usize, ErrorWithContext> {
-        self.parse_internal().map_err(|e| e.attach_context(self))
+    /// remembers a declaration of the source program, so it can be reported if it is never read
+    pub(crate) fn track_declaration(&mut self, name: &str, pos: usize) {
+        let scope = self.declarations.last_mut().expect("Tried to access scope, but none was there.");
+        scope.push(Declaration { name: name.into(), pos, is_read: false });
     }
 
-    fn parse_internal(&mut self) -> Result<usize, SoNError> {
-        self.push_scope()?;
-        let ctrl_nid = self.add_node_unrefined(vec![START_NID], NodeKind::Proj { proj_index: 0, _dbg_proj_label: "$ctrl".into() })?;
-        let arg_nid = self.with_kept_node(ctrl_nid, |parser| {
-            parser.add_node_unrefined(vec![START_NID], NodeKind::Proj { proj_index: 1, _dbg_proj_label: "arg".into() })
-        })?;
-        self.define_var("$ctrl", ctrl_nid)?;
-        self.define_var("arg", arg_nid)?;
-        let nid = self.parse_block()?;
-        self.pop_scope()?;
+    /// marks the innermost declaration of name as read
+    pub(crate) fn track_read(&mut self, name: &str) {
+        if let Some(declaration) = self.declarations.iter_mut().rev().find_map(|scope| scope.iter_mut().find(|d| d.name == name)) {
+            declaration.is_read = true;
+        }
+    }
 
-        if !self.lexer.is_eof() {
-            return Err(SyntaxExpected { expected: "End of file".to_string(), but_got: self.lexer.dbg_get_any_next_token() })
+    /// Variables of the parsed program that are declared but never read, ordered by their declaration.
+    pub fn unused_variables(&self) -> Vec<String> {
+        self.unused_declarations().into_iter().map(|(name, _)| name).collect()
+    }
+
+    /// like [Parser::unused_variables], but with the position of each declaration
+    pub(crate) fn unused_declarations(&self) -> Vec<(String, usize)> {
+        let mut unused = self.unused_variables.clone();
+        unused.sort_by_key(|d| d.pos);
+        unused.into_iter().map(|d| (d.name, d.pos)).collect()
+    }
+
+    pub fn parse(&mut self) -> Result<usize, ErrorWithContext> {
+        let program = self.parse_ast().map_err(|e| e.attach_context(self))?;
+        // each peephole of the lowering is checked by add_node
+        let nid = self.lower(&program).map_err(|e| e.attach_context_at(self, self.lowering_pos))?;
+        self.with_monotonicity_check(|parser| {
+            if parser.do_optimize {
+                parser.graph.bypass_constant_ifs()?;
+            }
+            parser.drop_all_unused_nodes(nid)
+        }).map_err(|e| e.attach_context(self))?;
+        Ok(nid)
+    }
+
+    /// runs the pass `f` and checks it afterwards, if [ParserOptions::check_monotonicity] is set
+    pub(crate) fn with_monotonicity_check<F, R>(&mut self, f: F) -> Result<R, SoNError>
+    where
+        F: FnOnce(&mut Self) -> Result<R, SoNError>,
+    {
+        if !self.check_monotonicity {
+            return f(self);
         }
+        let before = self.graph.typs_by_uid();
+        let result = f(self)?;
+        self.graph.verify_monotonic(&before)?;
+        Ok(result)
+    }
+
+    /// drops every node that is unreachable from the lowered program `nid`
+    pub(crate) fn drop_all_unused_nodes(&mut self, nid: usize) -> Result<(), SoNError> {
         self.keep_node(nid)?;
-        while self.drop_unused_nodes() > 0 {
-            println!("Dropping unused nodes...");
+        while self.drop_unused_nodes() > 0 {}
+        self.unkeep_node(nid)
+    }
+
+    /// Parses the program into its statements without touching the graph.
+    pub fn parse_ast(&mut self) -> Result<Vec<Stmt>, SoNError> {
+        self.parse_program().map_err(|e| {
+            // the lexer does not skip unterminated comments, so parsing fails right at them
+            if self.lexer.is_at_unterminated_comment() { SoNError::UnterminatedComment } else { e }
+        })
+    }
+
+    /// <pre>
+    /// program: statement* | block
+    /// </pre>
+    /// The first form is parsed with [ParserOptions::implicit_block] and the second one without.
+    fn parse_program(&mut self) -> Result<Vec<Stmt>, SoNError> {
+        let stmts = if self.implicit_block {
+            self.parse_statements()?
+        } else {
+            self.lexer.skip_whitespace();
+            if !self.lexer.peek_matsch("{") {
+                return Err(SyntaxExpected { expected: "{".into(), but_got: self.lexer.dbg_peek_any_next_token() });
+            }
+            let Stmt::Block { stmts, .. } = self.parse_block()? else {
+                unreachable!("parse_block returns a block");
+            };
+            stmts
+        };
+        self.lexer.skip_whitespace();
+        if !self.lexer.is_eof() {
+            return Err(SyntaxExpected { expected: "End of file".to_string(), but_got: self.lexer.dbg_peek_any_next_token() })
         }
-        self.unkeep_node(nid)?;
-        Ok(nid)
+        Ok(stmts)
     }
 
     /// <pre>
-    /// block: '{' statement+ '}'
+    /// block: '{' statement* '}'
     /// </pre>
-    fn parse_block(&mut self) -> Result<usize, SoNError> {
+    fn parse_block(&mut self) -> Result<Stmt, SoNError> {
+        self.lexer.skip_whitespace();
+        let pos = self.lexer.position();
         assert!(self.lexer.matsch("{"));
-        self.push_scope()?;
-        let mut node = self.parse_statement()?;
-        while !self.lexer.is_eof() && !self.lexer.peek_matsch("}") {
-            let new_node = self.parse_statement();
-            if matches!(new_node, Err(DebugPropagateControlFlowUpward)) {
-                continue;
+        let stmts = self.parse_statements()?;
+        self.require("}")?;
+        Ok(Stmt::Block { stmts, pos })
+    }
+
+    /// statements up to the end of the enclosing block or the input
+    fn parse_statements(&mut self) -> Result<Vec<Stmt>, SoNError> {
+        let mut stmts = vec![];
+        loop {
+            self.lexer.skip_whitespace();
+            if self.lexer.is_eof() || self.lexer.peek_matsch("}") {
+                return Ok(stmts);
             }
-            node = new_node?;
+            stmts.push(self.parse_statement()?);
         }
-        self.require("}")?;
-        self.pop_scope()?;
-        Ok(node)
     }
 
     /// <pre>
     /// returnStatement: 'return' returnStatement ';'
-    ///   declStatement: 'int' identifier '=' expression ';'
-    ///  blockStatement: '{' statement+ '}'
-    ///   exprStatement: identifier '=' expression ';'
+    ///   declStatement: 'const'? ('int' | 'bool' | structName) identifier '=' expression ';'
+    ///    functionDecl: ('int' | 'bool' | structName) identifier '(' params ')' block
+    ///      structDecl: 'struct' identifier '{' field* '}'
+    ///  printStatement: 'print' '(' expression ')' ';'
+    ///  blockStatement: '{' statement* '}'
+    ///   exprStatement: identifier ('.' identifier | '[' expression ']')? '=' expression ';'
+    ///  emptyStatement: ';'
     /// </pre>
-    fn parse_statement(&mut self) -> Result<usize, SoNError> {
+    fn parse_statement(&mut self) -> Result<Stmt, SoNError> {
+        self.nested(Self::parse_statement_kind)
+    }
+
+    /// runs `parse` one nesting level deeper and fails if that exceeds [ParserOptions::max_nesting_depth]
+    fn nested<R>(&mut self, parse: impl FnOnce(&mut Self) -> Result<R, SoNError>) -> Result<R, SoNError> {
+        if self.depth >= self.max_nesting_depth {
+            return Err(SoNError::NestingTooDeep { max_depth: self.max_nesting_depth });
+        }
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn parse_statement_kind(&mut self) -> Result<Stmt, SoNError> {
+        self.lexer.skip_whitespace();
+        let pos = self.lexer.position();
         if self.lexer.matsch("#showGraph;") {
-            let out = format!("#showGraph@{}\n{}", self.lexer.dbg_position_string(), self.as_dotfile());
-            self._dbg_output.push_str(&out.as_str());
-            println!("{}", out);
-            return Err(DebugPropagateControlFlowUpward)
+            return Ok(Stmt::ShowGraph { pos });
+        }
+        if self.lexer.matsch(";") {
+            return Ok(Stmt::Empty { pos });
         }
         if self.lexer.peek_matschx("return") {
             return self.parse_return_stmnt();
         }
-        if self.lexer.peek_matschx("int") {
+        if self.lexer.peek_matschx("print") {
+            return self.parse_print_stmnt();
+        }
+        if self.lexer.peek_matschx("struct") {
+            return self.parse_struct_decl();
+        }
+        if self.lexer.peek_matschx("const") || self.lexer.peek_matschx("int") || self.lexer.peek_matschx("bool") || self.peek_struct_name() {
             return self.parse_decl_stmnt();
         }
         if self.lexer.peek_matsch("{") {
@@ -276,281 +729,429 @@ impl Parser {
     }
 
     /// <pre>
-    /// declStatement: 'int' identifier '=' expression ';'
+    /// typ: ('int' | 'bool') ('[' ']')? | structName
     /// </pre>
-    fn parse_decl_stmnt(&mut self) -> Result<usize, SoNError> {
-        assert!(self.lexer.matschx("int"));
-        let name = self.require_and_get_identifier()?;
-        self.require("=")?;
-        let expression = self.parse_expression()?;
-        self.require(";")?;
-        if let Some(_) = self.get_var(&name) {
-            return Err(VariableRedefinition { variable: name });
+    fn parse_typ(&mut self) -> Result<Typ, SoNError> {
+        for (keyword, elem) in [("int", Typ::IntBot), ("bool", Typ::BoolBot)] {
+            if self.lexer.matschx(keyword) {
+                if self.lexer.matsch("[") {
+                    self.require("]")?;
+                    return Ok(Typ::Array { elem: Box::new(elem), len: None });
+                }
+                return Ok(elem);
+            }
         }
-        self.define_var(&name, expression)?;
-        Ok(expression)
-    }
-
-    /// <pre>
-    /// exprStatement: identifier '=' expression ';'
-    /// </pre>
-    fn parse_expression_stmnt(&mut self) -> Result<usize, SoNError> {
+        let name_pos = self.next_token_position();
         let name = self.require_and_get_identifier()?;
-        self.require("=")?;
-        let expression = self.parse_expression()?;
-        self.require(";")?;
-        if let Some(nid) = self.get_var(&name) {
-            let nid1 = self.undefine_var(&name)?;
-            assert_eq!(nid, nid1);
-            self.define_var(&name, expression)?;
-        } else {
-            return Err(VariableUndefined { variable: name });
-        }
-        Ok(expression)
-    }
-
-    fn parse_return_stmnt(&mut self) -> Result<usize, SoNError> {
-        assert!(self.lexer.matschx("return"));
-        let primary = self.parse_expression()?;
-        self.require(";")?;
-        let ret = self.add_node_unrefined(vec![self.ctrl(), primary], NodeKind::Return);
-        ret
+        self.lookup_struct(name, name_pos)
     }
 
-    fn with_kept_node<F, R>(&mut self, node: usize, f: F) -> Result<R, SoNError>
-    where
-        F: FnOnce(&mut Self) -> Result<R, SoNError>,
-    {
-        self.keep_node(node)?;
-        let result = f(self);
-        self.unkeep_node(node)?;
-        result
+    /// position of the next token, so an error about it can point at its start
+    fn next_token_position(&mut self) -> usize {
+        self.lexer.skip_whitespace();
+        self.lexer.position()
     }
 
-    fn keep_node(&mut self, nid: usize) -> Result<(), SoNError> {
-        self.graph.add_reverse_dependencies_br(KEEP_ALIVE_NID, &vec![nid])?;
-        self.graph.add_dependencies_br(KEEP_ALIVE_NID, &vec![nid])
+    fn lookup_struct(&mut self, name: String, name_pos: usize) -> Result<Typ, SoNError> {
+        match self.structs.get(&name) {
+            Some(typ) => Ok(typ.clone()),
+            None => {
+                self.lexer.set_position(name_pos);
+                Err(SoNError::StructUndefined { name })
+            }
+        }
     }
 
-    fn unkeep_node(&mut self, nid: usize) -> Result<(), SoNError> {
-        self.graph.remove_dependency_br(KEEP_ALIVE_NID, nid)
+    /// whether the next token is the name of a declared struct that is followed by the declared name
+    fn peek_struct_name(&mut self) -> bool {
+        let Some(Token::Identifier(name)) = self.lexer.peek_nth_token(0) else {
+            return false;
+        };
+        self.structs.contains_key(&name) && matches!(self.lexer.peek_nth_token(1), Some(Token::Identifier(_)))
     }
 
     /// <pre>
-    /// expression : logicalExpression
+    /// structDecl: 'struct' identifier '{' (('int' | 'bool') identifier ';')* '}'
     /// </pre>
-    fn parse_expression(&mut self) -> Result<usize, SoNError> {
-        self.parse_logical()
+    fn parse_struct_decl(&mut self) -> Result<Stmt, SoNError> {
+        let pos = self.lexer.position();
+        assert!(self.lexer.matschx("struct"));
+        let name_pos = self.next_token_position();
+        let name = self.require_and_get_identifier()?;
+        if self.structs.contains_key(&name) {
+            self.lexer.set_position(name_pos);
+            return Err(SoNError::StructRedefinition { name });
+        }
+        self.require("{")?;
+        let mut fields: Vec<(String, Typ)> = vec![];
+        while !self.lexer.matsch("}") {
+            let typ = if self.lexer.matschx("int") {
+                Typ::IntBot
+            } else if self.lexer.matschx("bool") {
+                Typ::BoolBot
+            } else {
+                return Err(SyntaxExpected { expected: "int or bool".into(), but_got: self.lexer.dbg_peek_any_next_token() });
+            };
+            let field_pos = self.next_token_position();
+            let field = self.require_and_get_identifier()?;
+            if fields.iter().any(|(existing, _)| *existing == field) {
+                self.lexer.set_position(field_pos);
+                return Err(SoNError::FieldRedefinition { struct_name: name, field });
+            }
+            self.require(";")?;
+            fields.push((field, typ));
+        }
+        let typ = Typ::Struct { name: name.clone(), fields };
+        self.structs.insert(name, typ.clone());
+        Ok(Stmt::Struct { typ, pos })
     }
 
     /// <pre>
-    /// logicalExpression : bitwiseComparisonExpression
+    /// declStatement: 'const'? ('int' | 'bool' | structName) identifier '=' expression ';'
+    /// functionDecl: ('int' | 'bool' | structName) identifier '(' (param (',' param)*)? ')' block
+    ///        param: ('int' | 'bool' | structName) identifier
     /// </pre>
-    fn parse_logical(&mut self) -> Result<usize, SoNError> {
-        let lhs = self.parse_bitwise_comparison()?;
-        if self.lexer.matsch("&&") {
-            return self.with_kept_node(lhs, |parser| {
-                let rhs = parser.parse_logical()?;
-                parser.add_node_unrefined(vec![lhs, rhs], NodeKind::Comp { kind: CompNodeKind::LogAnd })
-            });
-        }
-        if self.lexer.matsch("||") {
-            return self.with_kept_node(lhs, |parser| {
-                let rhs = parser.parse_logical()?;
-                parser.add_node_unrefined(vec![lhs, rhs], NodeKind::Comp { kind: CompNodeKind::LogOr })
-            });
+    fn parse_decl_stmnt(&mut self) -> Result<Stmt, SoNError> {
+        let pos = self.lexer.position();
+        let is_const = self.lexer.matschx("const");
+        let declared_typ = self.parse_typ()?;
+        let name = self.require_and_get_identifier()?;
+        if !is_const && self.lexer.matsch("(") {
+            let mut params: Vec<(String, Typ)> = vec![];
+            if !self.lexer.matsch(")") {
+                loop {
+                    let typ = self.parse_typ()?;
+                    params.push((self.require_and_get_identifier()?, typ));
+                    if self.lexer.matsch(")") {
+                        break;
+                    }
+                    self.require(",")?;
+                }
+            }
+            self.lexer.skip_whitespace();
+            if !self.lexer.peek_matsch("{") {
+                return Err(SyntaxExpected { expected: "{".into(), but_got: self.lexer.dbg_peek_any_next_token() });
+            }
+            let Stmt::Block { stmts: body, .. } = self.parse_block()? else { unreachable!() };
+            return Ok(Stmt::Function { ret_typ: declared_typ, name, params, body, pos });
         }
-        Ok(lhs)
+        self.require("=")?;
+        let value = self.parse_expression()?;
+        self.require(";")?;
+        Ok(Stmt::Decl { declared_typ, name, value, is_const, pos })
     }
 
     /// <pre>
-    /// bitwiseComparisonExpression : comparisonExpression
+    /// exprStatement: identifier ('.' identifier)? '=' expression ';'
     /// </pre>
-    fn parse_bitwise_comparison(&mut self) -> Result<usize, SoNError> {
-        let lhs = self.parse_comparison()?;
-        if self.lexer.matsch("&") {
-            return self.with_kept_node(lhs, |parser| {
-                let rhs = parser.parse_bitwise_comparison()?;
-                parser.add_node_unrefined(vec![lhs, rhs], NodeKind::Comp { kind: CompNodeKind::LogAnd })
-            });
-        }
-        if self.lexer.matsch("^") {
-            return self.with_kept_node(lhs, |parser| {
-                let rhs = parser.parse_bitwise_comparison()?;
-                parser.add_node_unrefined(vec![lhs, rhs], NodeKind::Comp { kind: CompNodeKind::LogXor })
-            });
-        }
-        if self.lexer.matsch("|") {
-            return self.with_kept_node(lhs, |parser| {
-                let rhs = parser.parse_bitwise_comparison()?;
-                parser.add_node_unrefined(vec![lhs, rhs], NodeKind::Comp { kind: CompNodeKind::LogOr })
-            });
+    fn parse_expression_stmnt(&mut self) -> Result<Stmt, SoNError> {
+        let pos = self.lexer.position();
+        let name = self.require_and_get_identifier()?;
+        if self.lexer.matsch(".") {
+            let field = self.require_and_get_identifier()?;
+            self.require("=")?;
+            let value = self.parse_expression()?;
+            self.require(";")?;
+            return Ok(Stmt::FieldAssign { object: Expr::Identifier { name, pos }, field, value, pos });
         }
-        Ok(lhs)
+        if self.lexer.matsch("[") {
+            let index = self.parse_expression()?;
+            self.require("]")?;
+            self.require("=")?;
+            let value = self.parse_expression()?;
+            self.require(";")?;
+            return Ok(Stmt::IndexAssign { array: Expr::Identifier { name, pos }, index, value, pos });
+        }
+        self.require("=")?;
+        let value = self.parse_expression()?;
+        self.require(";")?;
+        Ok(Stmt::Assign { name, value, pos })
     }
 
+    fn parse_return_stmnt(&mut self) -> Result<Stmt, SoNError> {
+        let pos = self.lexer.position();
+        assert!(self.lexer.matschx("return"));
+        let value = self.parse_expression()?;
+        self.require(";")?;
+        Ok(Stmt::Return { value, pos })
+    }
 
     /// <pre>
-    /// comparisonExpression : additiveExpr
+    /// printStatement: 'print' '(' expression ')' ';'
     /// </pre>
-    fn parse_comparison(&mut self) -> Result<usize, SoNError> {
-        let lhs = self.parse_addition()?;
-        if self.lexer.matsch("<") {
-            return self.with_kept_node(lhs, |parser| {
-                let rhs = parser.parse_comparison()?;
-                parser.add_node_unrefined(vec![lhs, rhs], NodeKind::Comp { kind: CompNodeKind::LT })
-            });
-        }
-        if self.lexer.matsch(">") {
-            return self.with_kept_node(lhs, |parser| {
-                let rhs = parser.parse_comparison()?;
-                let comp = parser.add_node_unrefined(vec![lhs, rhs], NodeKind::Comp { kind: CompNodeKind::LEQ })?;
-                parser.add_node_unrefined(vec![comp], NodeKind::Not)
-            });
-        }
-        if self.lexer.matsch("<=") {
-            return self.with_kept_node(lhs, |parser| {
-                let rhs = parser.parse_comparison()?;
-                parser.add_node_unrefined(vec![lhs, rhs], NodeKind::Comp { kind: CompNodeKind::LEQ })
-            });
-        }
-        if self.lexer.matsch(">=") {
-            return self.with_kept_node(lhs, |parser| {
-                let rhs = parser.parse_comparison()?;
-                let comp = parser.add_node_unrefined(vec![lhs, rhs], NodeKind::Comp { kind: CompNodeKind::LT })?;
-                parser.add_node_unrefined(vec![comp], NodeKind::Not)
-            });
-        }
-        if self.lexer.matsch("==") {
-            return self.with_kept_node(lhs, |parser| {
-                let rhs = parser.parse_comparison()?;
-                parser.add_node_unrefined(vec![lhs, rhs], NodeKind::Comp { kind: CompNodeKind::EQ })
-            });
-        }
-        if self.lexer.matsch("!=") {
-            return self.with_kept_node(lhs, |parser| {
-                let rhs = parser.parse_comparison()?;
-                let comp = parser.add_node_unrefined(vec![lhs, rhs], NodeKind::Comp { kind: CompNodeKind::EQ })?;
-                parser.add_node_unrefined(vec![comp], NodeKind::Not)
-            });
-        }
-        Ok(lhs)
+    fn parse_print_stmnt(&mut self) -> Result<Stmt, SoNError> {
+        let pos = self.lexer.position();
+        assert!(self.lexer.matschx("print"));
+        self.require("(")?;
+        let value = self.parse_expression()?;
+        self.require(")")?;
+        self.require(";")?;
+        Ok(Stmt::Print { value, pos })
     }
 
-    /// <pre>
-    /// additiveExpr : multiplicativeExpr (('+' | '-') additiveExpr)*
-    /// </pre>
-    fn parse_addition(&mut self) -> Result<usize, SoNError> {
-        let lhs = self.parse_multiplication()?;
-        if self.lexer.matsch("+") {
-            return self.with_kept_node(lhs, |parser| {
-                let rhs = parser.parse_addition()?;
-                parser.add_node_unrefined(vec![lhs, rhs], NodeKind::Add)
-            });
+    pub(crate) fn with_kept_node<F, R>(&mut self, node: usize, f: F) -> Result<R, SoNError>
+    where
+        F: FnOnce(&mut Self) -> Result<R, SoNError>,
+    {
+        self.with_kept_nodes(&[node], f)
+    }
+
+    pub(crate) fn with_kept_nodes<F, R>(&mut self, nodes: &[usize], f: F) -> Result<R, SoNError>
+    where
+        F: FnOnce(&mut Self) -> Result<R, SoNError>,
+    {
+        for &node in nodes {
+            self.keep_node(node)?;
         }
-        if self.lexer.matsch("-") {
-            return self.with_kept_node(lhs, |parser| {
-                let rhs = parser.parse_addition()?;
-                parser.add_node_unrefined(vec![lhs, rhs], NodeKind::Sub)
-            });
+        let result = f(self);
+        for &node in nodes {
+            self.unkeep_node(node)?;
         }
-        Ok(lhs)
+        result
+    }
+
+    /// Keeps nid from being collected. Nodes can be kept more than once and stay alive until they are unkept as often.
+    pub(crate) fn keep_node(&mut self, nid: usize) -> Result<(), SoNError> {
+        self.graph.keep(nid)
     }
 
+    pub(crate) fn unkeep_node(&mut self, nid: usize) -> Result<(), SoNError> {
+        self.graph.unkeep(nid)
+    }
 
     /// <pre>
-    /// multiplicativeExpr : unaryExpr (('*' | '/') multiplicativeExpr)*
+    /// expression : unaryExpr (binaryOperator unaryExpr)*
     /// </pre>
-    fn parse_multiplication(&mut self) -> Result<usize, SoNError> {
-        let lhs = self.parse_unary()?;
-        if self.lexer.matsch("*") {
-            return self.with_kept_node(lhs, |parser| {
-                let rhs = parser.parse_multiplication()?;
-                parser.add_node_unrefined(vec![lhs, rhs], NodeKind::Mul)
-            });
-        }
-        if self.lexer.matsch("/") {
-            return self.with_kept_node(lhs, |parser| {
-                let rhs = parser.parse_multiplication()?;
-                parser.add_node_unrefined(vec![lhs, rhs], NodeKind::Div)
-            });
+    fn parse_expression(&mut self) -> Result<Expr, SoNError> {
+        self.parse_binary(0)
+    }
+
+    /// Precedence climbing over the operator table.
+    /// Only operators that bind at least as strong as `min_precedence` are consumed.
+    fn parse_binary(&mut self, min_precedence: u8) -> Result<Expr, SoNError> {
+        self.nested(|parser| parser.parse_binary_operators(min_precedence))
+    }
+
+    fn parse_binary_operators(&mut self, min_precedence: u8) -> Result<Expr, SoNError> {
+        // the position of the lhs itself would miss an opening parenthesis
+        self.lexer.skip_whitespace();
+        let pos = self.lexer.position();
+        let mut lhs = self.parse_unary()?;
+        while let Some(operator) = self.peek_binary_operator() && operator.precedence >= min_precedence {
+            self.lexer.skip_whitespace();
+            let op_pos = self.lexer.position();
+            assert!(self.lexer.matsch(&operator.lexeme));
+            let next_min_precedence = match operator.associativity {
+                Associativity::Left => operator.precedence + 1,
+                Associativity::Right => operator.precedence,
+            };
+            let rhs = self.parse_binary(next_min_precedence)?;
+            lhs = Expr::Binary { operator, lhs: Box::new(lhs), rhs: Box::new(rhs), pos, op_pos, end: self.lexer.position_before_whitespace() };
         }
         Ok(lhs)
     }
 
+    /// Does NOT change the lexer position. Prefers the longest matching lexeme, so `<=` wins over `<`.
+    fn peek_binary_operator(&mut self) -> Option<BinaryOperator> {
+        self.operators.iter()
+            .filter(|operator| self.lexer.peek_matsch(&operator.lexeme))
+            .max_by_key(|operator| operator.lexeme.len())
+            .cloned()
+    }
+
     /// <pre>
-    /// unaryExpr : ('-') unaryExpr | primaryExpr
+    /// unaryExpr : ('-' | '!' | '+') unaryExpr | primaryExpr
     /// </pre>
-    fn parse_unary(&mut self) -> Result<usize, SoNError> {
-        if self.lexer.matsch("-") {
-            let unary = self.parse_unary()?;
-            self.add_node_unrefined(vec![unary], NodeKind::Minus)
+    /// A unary plus is just its operand. The operand takes the operators binding stronger than the prefix, like `**`.
+    fn parse_unary(&mut self) -> Result<Expr, SoNError> {
+        self.lexer.skip_whitespace();
+        let pos = self.lexer.position();
+        if self.lexer.matsch("+") {
+            self.parse_binary(PREFIX_PRECEDENCE + 1)
+        } else if self.lexer.matsch("-") {
+            let operand = self.parse_binary(PREFIX_PRECEDENCE + 1)?;
+            Ok(Expr::Minus { operand: Box::new(operand), pos })
         } else if self.lexer.matsch("!") {
-            let unary = self.parse_unary()?;
-            self.add_node_unrefined(vec![unary], NodeKind::Not)
+            let operand = self.parse_binary(PREFIX_PRECEDENCE + 1)?;
+            Ok(Expr::Not { operand: Box::new(operand), pos })
         } else {
             self.parse_primary()
         }
     }
 
     /// <pre>
-    /// primaryExpr : integerLiteral | identifier | '(' expression ')'
+    /// primaryExpr : integerLiteral | floatLiteral | 'true' | 'false' | identifier | '(' expression ')' | castExpr | ifExpr
+    ///      ifExpr : 'if' '(' expression ')' expression 'else' expression
     /// </pre>
-    fn parse_primary(&mut self) -> Result<usize, SoNError> {
+    fn parse_primary(&mut self) -> Result<Expr, SoNError> {
         self.lexer.skip_whitespace();
+        let pos = self.lexer.position();
         if self.lexer.peek_is_number() {
-            return self.parse_number_literal()
+            return match self.lexer.parse_number_literal()? {
+                NumberLiteral::Int(value) => Ok(Expr::Number { value, pos }),
+                NumberLiteral::Float(value) => Ok(Expr::Float { value, pos }),
+            };
+        }
+        if self.lexer.matschx("if") {
+            self.require("(")?;
+            let cond = self.parse_expression()?;
+            self.require(")")?;
+            let then = self.parse_expression()?;
+            if !self.lexer.matschx("else") {
+                return Err(SyntaxExpected { expected: "else".into(), but_got: self.lexer.dbg_peek_any_next_token() });
+            }
+            let otherwise = self.parse_expression()?;
+            return Ok(Expr::If { cond: Box::new(cond), then: Box::new(then), otherwise: Box::new(otherwise), pos });
+        }
+        for (keyword, value) in [("true", true), ("false", false)] {
+            if self.lexer.matschx(keyword) {
+                return Ok(Expr::Bool { value, pos });
+            }
+        }
+        for (keyword, to) in [("int", Typ::IntBot), ("bool", Typ::BoolBot)] {
+            if self.peek_prefix_cast(keyword) {
+                self.require("(")?;
+                self.require(keyword)?;
+                self.require(")")?;
+                let operand = self.parse_unary()?;
+                return Ok(Expr::Cast { to, operand: Box::new(operand), pos });
+            }
+        }
+        if self.lexer.matschx("int") {
+            return self.parse_cast(Typ::IntBot, pos);
+        }
+        if self.lexer.matschx("bool") {
+            return self.parse_cast(Typ::BoolBot, pos);
+        }
+        if self.lexer.matschx("new") {
+            for (keyword, elem) in [("int", Typ::IntBot), ("bool", Typ::BoolBot)] {
+                if self.lexer.matschx(keyword) {
+                    self.require("[")?;
+                    let len = self.parse_expression()?;
+                    self.require("]")?;
+                    return self.parse_postfix(Expr::NewArray { elem, len: Box::new(len), pos });
+                }
+            }
+            let name_pos = self.next_token_position();
+            let name = self.require_and_get_identifier()?;
+            let typ = self.lookup_struct(name, name_pos)?;
+            return self.parse_postfix(Expr::New { typ, pos });
         }
         if self.lexer.matsch("(") {
-            let node = self.parse_expression()?;
+            let expression = self.parse_expression()?;
             self.require(")")?;
-            return Ok(node);
+            return self.parse_postfix(expression);
         }
         let name = self.require_and_get_identifier()?;
-        if let Some(nid) = self.get_var(&name) {
-            Ok(nid)
-        } else {
-            Err(VariableUndefined { variable: name })
+        if self.lexer.matsch("(") {
+            let mut args = vec![];
+            if !self.lexer.matsch(")") {
+                loop {
+                    args.push(self.parse_expression()?);
+                    if self.lexer.matsch(")") {
+                        break;
+                    }
+                    self.require(",")?;
+                }
+            }
+            return self.parse_postfix(Expr::Call { name, args, pos });
         }
+        self.parse_postfix(Expr::Identifier { name, pos })
     }
 
-    fn parse_number_literal(&mut self) -> Result<usize, SoNError> {
-        let value = self.lexer.parse_number()?;
-        self.add_node(vec![], NodeKind::Constant, Typ::Int { constant: value })
+    /// <pre>
+    /// postfix: primary ('.' identifier | '[' expression ']')*
+    /// </pre>
+    fn parse_postfix(&mut self, mut object: Expr) -> Result<Expr, SoNError> {
+        loop {
+            self.lexer.skip_whitespace();
+            let pos = self.lexer.position();
+            if self.lexer.matsch(".") {
+                let field = self.require_and_get_identifier()?;
+                object = Expr::Field { object: Box::new(object), field, pos };
+            } else if self.lexer.matsch("[") {
+                let index = self.parse_expression()?;
+                self.require("]")?;
+                object = Expr::Index { array: Box::new(object), index: Box::new(index), pos };
+            } else {
+                return Ok(object);
+            }
+        }
     }
 
-    /// require this syntax
-    fn require(&mut self, syntax: &str) -> Result<(), SoNError> {
-        if self.lexer.matsch(syntax) {
-            Ok(())
+    /// whether the next tokens are `(keyword)`, which starts a prefix cast rather than a parenthesized expression
+    fn peek_prefix_cast(&mut self, keyword: &str) -> bool {
+        matches!(self.lexer.peek_nth_token(0), Some(Token::Symbol('(')))
+            && matches!(self.lexer.peek_nth_token(1), Some(Token::Identifier(name)) if name == keyword)
+            && matches!(self.lexer.peek_nth_token(2), Some(Token::Symbol(')')))
+    }
+
+    /// <pre>
+    /// castExpr : ('int' | 'bool') '(' expression ')' | '(' ('int' | 'bool') ')' unaryExpr
+    /// </pre>
+    fn parse_cast(&mut self, to: Typ, pos: usize) -> Result<Expr, SoNError> {
+        self.require("(")?;
+        let operand = self.parse_expression()?;
+        self.require(")")?;
+        Ok(Expr::Cast { to, operand: Box::new(operand), pos })
+    }
+
+    /// require this syntax
+    fn require(&mut self, syntax: &str) -> Result<(), SoNError> {
+        if self.lexer.matsch(syntax) {
+            Ok(())
         } else {
             Err(SyntaxExpected {
                 expected: syntax.to_string(),
-                but_got: self.lexer.dbg_get_any_next_token(),
+                but_got: self.lexer.dbg_peek_any_next_token(),
             })
         }
     }
 
     fn require_and_get_identifier(&mut self) -> Result<String, SoNError> {
         self.lexer.skip_whitespace();
-        if let Some(c) = self.lexer.peek() && Lexer::is_id_start(&c)
-            && let name = self.lexer.parse_id()
-            && !KEYWORDS.contains(&name) {
+        let prev_position = self.lexer.position();
+        if let Some(c) = self.lexer.peek() && Lexer::is_id_start(&c) {
+            let name = self.lexer.parse_id();
+            if KEYWORDS.contains(&name) {
+                self.lexer.set_position(prev_position);
+                return Err(SoNError::ReservedKeyword { keyword: name });
+            }
             Ok(name)
         } else {
-            Err(SyntaxExpected { expected: "Identifier".to_string(), but_got: self.lexer.dbg_get_any_next_token() })
+            self.lexer.set_position(prev_position);
+            Err(SyntaxExpected { expected: "Identifier".to_string(), but_got: self.lexer.dbg_peek_any_next_token() })
+        }
+    }
+}
+
+/// the Levenshtein distance: the fewest single character insertions, deletions and substitutions turning a into b
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != *cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(row[j + 1] + 1);
         }
     }
+    row[b.len()]
 }
 
 
 
 #[cfg(test)]
 mod tests {
+    use crate::ast::expr::Expr;
+    use crate::ast::stmt::Stmt;
     use crate::errors::son_error::{ErrorWithContext, SoNError};
     use crate::nodes::bound_node::BoundNode;
-    use crate::nodes::node::NodeKind;
-    use crate::services::parser::{Parser, KEEP_ALIVE_NID, SCOPE_NID, START_NID};
+    use crate::nodes::node::{CompNodeKind, NodeKind};
+    use crate::services::operators::{Associativity, BinaryOperator};
+    use crate::services::parser::{Parser, ParserBuilder, ParserOptions, KEEP_ALIVE_NID, SCOPE_NID, START_NID};
     use crate::typ::typ::Typ;
 
     #[test]
@@ -564,314 +1165,2324 @@ mod tests {
     }
 
     #[test]
-    fn should_parse_return() {
+    fn should_not_fold_when_optimization_is_disabled_via_options() {
         // Arrange
-        let mut parser = Parser::new_noarg("return 1;").unwrap();
-        parser.do_optimize = false;
+        let mut parser = Parser::with_options("return 1+1;", ParserOptions { do_optimize: false, ..Default::default() }).unwrap();
 
         // Act
         let result = parser.parse().unwrap();
 
         // Assert
-        let node = parser.graph.get(result).unwrap().as_ref().unwrap();
-        assert!(matches!(node.node_kind, NodeKind::Return));
-        assert!(matches!(node.outputs.as_slice(), []));
-        match node.inputs.as_slice() {
-            [_, x] => {
-                let dnode = parser.graph.get(*x).unwrap().as_ref().unwrap();
-                assert!(matches!(dnode.typ(), Typ::Int { constant: 1 }));
-                assert!(matches!(dnode.outputs.as_slice(), [y] if y.eq(&result) ));
+        let node = parser.graph.get_node(result).unwrap();
+        assert_eq!("return 1+1;", format!("{:}", BoundNode::new(node, &parser.graph)));
+    }
+
+    #[test]
+    fn should_parse_custom_operator() {
+        // Arrange
+        let mut operators = BinaryOperator::defaults();
+        operators.push(BinaryOperator::new(":", 10, Associativity::Left, NodeKind::Sub));
+        let options = ParserOptions { do_optimize: false, operators, ..Default::default() };
+        let mut parser = Parser::with_options("return 2*3:4;", options).unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get_node(result).unwrap();
+        assert_eq!("return 2*(3-4);", format!("{:}", BoundNode::new(node, &parser.graph)));
+    }
+
+    #[test]
+    fn should_reject_custom_operator_colliding_with_keyword_or_structural_token() {
+        for lexeme in ["return", ";", "="] {
+            // Arrange
+            let mut operators = BinaryOperator::defaults();
+            operators.push(BinaryOperator::new(lexeme, 10, Associativity::Left, NodeKind::Sub));
+            let options = ParserOptions { operators, ..Default::default() };
+
+            // Act
+            let result = Parser::with_options("return 1;", options);
+
+            // Assert
+            assert!(matches!(result, Err(SoNError::InvalidOperator { lexeme: l }) if l == lexeme));
+        }
+    }
+
+    #[test]
+    fn should_parse_ast_without_building_nodes() {
+        // Arrange
+        let mut parser = Parser::new_noarg("int a=1+2; return a;").unwrap();
+        let live_count = parser.graph.live_count();
+
+        // Act
+        let program = parser.parse_ast().unwrap();
+
+        // Assert
+        assert_eq!(live_count, parser.graph.live_count());
+        match program.as_slice() {
+            [Stmt::Decl { declared_typ: Typ::IntBot, name, value: Expr::Binary { operator, lhs, rhs, .. }, .. }, Stmt::Return { value: Expr::Identifier { name: returned, .. }, .. }] => {
+                assert_eq!("a", name);
+                assert_eq!("+", operator.lexeme);
+                assert!(matches!(**lhs, Expr::Number { value: 1, .. }));
+                assert!(matches!(**rhs, Expr::Number { value: 2, .. }));
+                assert_eq!("a", returned);
             }
-            _ => assert!(false)
+            _ => panic!("unexpected ast {:?}", program),
         }
-        println!("Parsing result is: {}", format!("{:}", BoundNode::new(&node, &parser.graph)));
     }
 
     #[test]
-    fn should_drop_unused_nodes_but_never_the_keepalive_node() {
+    fn should_not_fold_disabled_node_kind() {
         // Arrange
-        let mut parser = Parser::new_noarg("return 1;").unwrap();
-        parser.do_optimize = false;
+        let builder = ParserBuilder::new().disable_fold(NodeKind::Mul);
+        let mut mul = builder.clone().build_noarg("return 2*3;").unwrap();
+        let mut add = builder.build_noarg("return 2+3;").unwrap();
 
         // Act
-        let _result = parser.parse().unwrap();
+        let mul_result = mul.parse().unwrap();
+        let add_result = add.parse().unwrap();
 
         // Assert
-        assert_eq!(6, parser.graph.iter().filter(|n| n.is_some()).count());
-        assert!(matches!( parser.graph.get(KEEP_ALIVE_NID).unwrap().as_ref().unwrap().node_kind, NodeKind::KeepAlive))
+        let product = mul.graph.get_node(mul.graph.get_node(mul_result).unwrap().inputs[1]).unwrap();
+        assert!(matches!(product.node_kind, NodeKind::Mul));
+        assert_eq!(Typ::IntBot, product.typ());
+        let node = add.graph.get_node(add_result).unwrap();
+        assert_eq!("return 5;", format!("{:}", BoundNode::new(node, &add.graph)));
     }
 
     #[test]
-    fn should_not_drop_any_node_when_cap_is_0() {
+    fn should_span_literal_of_constant() {
         // Arrange
-        let mut parser = Parser::new_noarg("return 1;").unwrap();
-        parser.do_optimize = false;
+        let src = "int a = 7;\nreturn arg + 42;";
+        let mut parser = Parser::new_noarg(src).unwrap();
 
         // Act
-        let _result = parser.parse().unwrap();
-        let dropped_nodes = parser.drop_unused_nodes_cap(0);
+        let result = parser.parse().unwrap();
 
         // Assert
-        assert_eq!(0, dropped_nodes);
-        assert_eq!(6, parser.graph.iter().filter(|n| n.is_some()).count());
+        let add = parser.graph.get_node(parser.graph.inputs_of(result).unwrap()[1]).unwrap();
+        let constant = parser.graph.get_node(add.inputs[1]).unwrap();
+        let (start, end) = constant.span;
+        assert!(matches!(constant.typ(), Typ::Int { constant: 42 }));
+        assert_eq!("42", &src[start..end]);
     }
 
     #[test]
-    fn should_only_drop_one_node_when_cap_is_1() {
+    fn should_span_whole_binary_expression() {
         // Arrange
-        let mut parser = Parser::new_noarg("return 1;").unwrap();
-        parser.do_optimize = false;
+        let src = "int a = arg;\nreturn (a + 1) * 3 - a;";
+        let mut parser = Parser::new_noarg(src).unwrap();
 
         // Act
-        let _result = parser.parse().unwrap();
-        let dropped_nodes = parser.drop_unused_nodes_cap(1);
+        let result = parser.parse().unwrap();
 
         // Assert
-        assert_eq!(1, dropped_nodes);
-        assert_eq!(5, parser.graph.iter().filter(|n| n.is_some()).count());
-        assert!(matches!( parser.graph.get(START_NID).unwrap().as_ref().unwrap().node_kind, NodeKind::Start))
+        let sub = parser.graph.get_node(parser.graph.inputs_of(result).unwrap()[1]).unwrap();
+        let mul = parser.graph.get_node(sub.inputs[0]).unwrap();
+        assert!(matches!((&sub.node_kind, &mul.node_kind), (NodeKind::Sub, NodeKind::Mul)));
+        assert_eq!("(a + 1) * 3 - a", &src[sub.span.0..sub.span.1]);
+        assert_eq!("(a + 1) * 3", &src[mul.span.0..mul.span.1]);
     }
 
     #[test]
-    fn should_fail_when_invalid_syntax_is_used() {
+    fn should_not_span_nodes_created_after_lowering() {
         // Arrange
-        let mut parser = Parser::new_noarg("ret 1;").unwrap();
-        parser.do_optimize = false;
+        let mut parser = Parser::new_noarg("int a = 7;\nreturn arg + 42;").unwrap();
+        parser.parse().unwrap();
 
         // Act
-        let result = parser.parse();
+        let nid = parser.graph.new_node(vec![], NodeKind::Constant, Typ::Int { constant: 99 }).unwrap();
 
         // Assert
-        assert!(matches!(result, Err(ErrorWithContext{error: SoNError::SyntaxExpected {expected, ..},..}) if expected == "="));
+        assert_eq!((0, 0), parser.graph.get_node(nid).unwrap().span);
     }
 
     #[test]
-    fn should_check_for_semicolon() {
+    fn should_map_node_to_its_line() {
         // Arrange
-        let mut parser = Parser::new_noarg("return 1").unwrap();
-        parser.do_optimize = false;
+        let mut parser = Parser::new_noarg("int a = arg;\nreturn a * 3;").unwrap();
+        let result = parser.parse().unwrap();
 
         // Act
-        let result = parser.parse();
+        let source_map = parser.source_map();
 
         // Assert
-        assert!(matches!(result, Err(ErrorWithContext{error: SoNError::SyntaxExpected {expected, ..},..}) if expected == ";"));
+        let mul = parser.graph.inputs_of(result).unwrap()[1];
+        assert!(matches!(parser.graph.get_node(mul).unwrap().node_kind, NodeKind::Mul));
+        assert_eq!(((2, 8), (2, 13)), source_map[&mul]);
+        assert_eq!(((2, 12), (2, 13)), source_map[&parser.graph.inputs_of(mul).unwrap()[1]]);
     }
 
     #[test]
-    fn should_fail_at_brace() {
+    fn should_reject_deeply_nested_parentheses() {
         // Arrange
-        let mut parser = Parser::new_noarg("return 1;}").unwrap();
-        parser.do_optimize = false;
+        let src = format!("return {}1{};", "(".repeat(5000), ")".repeat(5000));
+        let mut parser = Parser::new_noarg(&src).unwrap();
 
         // Act
         let result = parser.parse();
 
         // Assert
-        assert!(matches!(result, Err(ErrorWithContext{error: SoNError::SyntaxExpected {expected, ..},..}) if expected == "End of file"));
+        assert!(matches!(result, Err(ErrorWithContext { error: SoNError::NestingTooDeep { max_depth: 64 }, .. })));
     }
 
     #[test]
-    fn should_delete_nodes_that_arent_kept_alive() {
+    fn should_parse_nesting_up_to_the_limit() {
         // Arrange
-        let mut parser = Parser::new_noarg("return 1;").unwrap();
-        parser.do_optimize = false;
-        let nid = parser.add_node_unrefined(vec![], NodeKind::Start).unwrap(); // this node is not kept
+        let src = format!("{}return {}arg{};{}", "{".repeat(20), "(".repeat(42), ")".repeat(42), "}".repeat(20));
+        let mut parser = Parser::new_noarg(&src).unwrap();
+        let mut limited = ParserBuilder::new().max_nesting_depth(3).build_noarg("{ { return 1; } }").unwrap();
 
         // Act
-        let _result = parser.parse();
+        let result = parser.parse().unwrap();
+        let limited_result = limited.parse();
 
         // Assert
-        assert!(!parser.graph.node_exists_unique(nid, nid));
+        let node = parser.graph.get_node(result).unwrap();
+        assert_eq!("return arg;", format!("{:}", BoundNode::new(node, &parser.graph)));
+        assert!(matches!(limited_result, Err(ErrorWithContext { error: SoNError::NestingTooDeep { max_depth: 3 }, .. })));
     }
 
     #[test]
-    fn should_parse_one_plus_one() {
+    fn should_parse_long_operator_chains() {
         // Arrange
-        let mut parser = Parser::new_noarg("return 1+1;").unwrap();
-        parser.do_optimize = false;
+        let mut parser = Parser::new_noarg(&format!("return {};", vec!["1"; 2000].join("+"))).unwrap();
+        let mut with_arg = Parser::new_noarg(&format!("return arg{};", "+1".repeat(1999))).unwrap();
 
         // Act
         let result = parser.parse().unwrap();
+        let with_arg_result = with_arg.parse().unwrap();
 
         // Assert
-        let node = parser.graph.get(result).unwrap().as_ref().unwrap();
-        assert_eq!("return (1+1);", format!("{:}", BoundNode::new(&node, &parser.graph)));
+        let node = parser.graph.get_node(result).unwrap();
+        assert_eq!("return 2000;", format!("{:}", BoundNode::new(node, &parser.graph)));
+        let node = with_arg.graph.get_node(with_arg_result).unwrap();
+        assert_eq!("return arg+1999;", format!("{:}", BoundNode::new(node, &with_arg.graph)));
     }
 
     #[test]
-    fn should_parse_one_minus_one() {
+    fn should_gather_long_chains_of_the_same_term() {
         // Arrange
-        let mut parser = Parser::new_noarg("return 1-1;").unwrap();
-        parser.do_optimize = false;
+        let mut parser = Parser::new_noarg(&format!("return {};", vec!["arg"; 4000].join("+"))).unwrap();
+        let mut with_constants = Parser::new_noarg(&format!("return {};", vec!["arg+1"; 2000].join("+"))).unwrap();
 
         // Act
         let result = parser.parse().unwrap();
+        let with_constants_result = with_constants.parse().unwrap();
 
         // Assert
-        let node = parser.graph.get(result).unwrap().as_ref().unwrap();
-        assert_eq!("return (1-1);", format!("{:}", BoundNode::new(&node, &parser.graph)));
+        let node = parser.graph.get_node(result).unwrap();
+        assert_eq!("return arg*4000;", format!("{:}", BoundNode::new(node, &parser.graph)));
+        let node = with_constants.graph.get_node(with_constants_result).unwrap();
+        assert_eq!("return arg*2000+2000;", format!("{:}", BoundNode::new(node, &with_constants.graph)));
     }
 
     #[test]
-    fn should_parse_one_times_one() {
+    fn should_stop_at_max_nodes() {
         // Arrange
-        let mut parser = Parser::new_noarg("return 1*1;").unwrap();
-        parser.do_optimize = false;
+        let src = "return arg*2 + arg*3 + arg*4 + arg*5;";
+        let mut limited = ParserBuilder::new().max_nodes(12).build_noarg(src).unwrap();
+        let mut parser = ParserBuilder::new().max_nodes(20).build_noarg(src).unwrap();
 
         // Act
-        let result = parser.parse().unwrap();
+        let limited_result = limited.parse();
+        let result = parser.parse();
 
         // Assert
-        let node = parser.graph.get(result).unwrap().as_ref().unwrap();
-        assert_eq!("return (1*1);", format!("{:}", BoundNode::new(&node, &parser.graph)));
+        assert!(matches!(limited_result, Err(ErrorWithContext { error: SoNError::GraphSizeLimitExceeded { max_nodes: 12 }, .. })));
+        assert!(result.is_ok());
+        assert!(parser.graph.live_count() <= 20);
     }
 
     #[test]
-    fn should_parse_one_div_one() {
+    fn should_collect_at_most_gc_cap_nodes_at_once() {
         // Arrange
-        let mut parser = Parser::new_noarg("return 1/1;").unwrap();
-        parser.do_optimize = false;
+        let mut parser = ParserBuilder::new().do_optimize(false).gc_cap(2).build_noarg("").unwrap();
+        for constant in 0..5 {
+            parser.graph.new_node(vec![], NodeKind::Constant, Typ::Int { constant }).unwrap();
+        }
 
         // Act
-        let result = parser.parse().unwrap();
+        let first = parser.drop_unused_nodes();
+        parser.gc_cap = 100;
+        let second = parser.drop_unused_nodes();
 
         // Assert
-        let node = parser.graph.get(result).unwrap().as_ref().unwrap();
-        assert_eq!("return (1/1);", format!("{:}", BoundNode::new(&node, &parser.graph)));
+        assert_eq!(2, first);
+        assert_eq!(3, second);
     }
 
     #[test]
-    fn should_parse_mul_and_add() {
+    fn should_collect_node_only_after_last_unkeep() {
         // Arrange
-        let mut parser = Parser::new_noarg("return 1*2+3;").unwrap();
-        parser.do_optimize = false;
+        let mut parser = ParserBuilder::new().do_optimize(false).build_noarg("").unwrap();
+        let nid = parser.graph.new_node(vec![], NodeKind::Constant, Typ::IntBot).unwrap();
+        parser.keep_node(nid).unwrap();
+        parser.keep_node(nid).unwrap();
+
+        // Act
+        parser.unkeep_node(nid).unwrap();
+        parser.drop_unused_nodes();
+        let survives_one_unkeep = parser.graph.node_exists(nid);
+        parser.unkeep_node(nid).unwrap();
+        parser.drop_unused_nodes();
+
+        // Assert
+        assert!(survives_one_unkeep);
+        assert!(!parser.graph.node_exists(nid));
+    }
+
+    #[test]
+    fn should_refcount_nested_kept_nodes() {
+        // Arrange
+        let mut parser = ParserBuilder::new().do_optimize(false).build_noarg("").unwrap();
+        let nid = parser.graph.new_node(vec![], NodeKind::Constant, Typ::IntBot).unwrap();
 
         // Act
+        let inner_kept = parser.with_kept_node(nid, |parser| {
+            parser.with_kept_node(nid, |parser| Ok(parser.graph.is_kept(nid)))?;
+            parser.drop_unused_nodes();
+            Ok(parser.graph.is_kept(nid) && parser.graph.node_exists(nid))
+        }).unwrap();
+
+        // Assert
+        assert!(inner_kept);
+        assert!(!parser.graph.is_kept(nid));
+        assert!(parser.graph.get_node(KEEP_ALIVE_NID).unwrap().inputs.is_empty());
+    }
+
+    #[test]
+    fn should_pass_monotonicity_check_when_optimizing() {
+        // Arrange
+        let src = "int f(int x) { return x*2; } int a = if(arg < 3) f(arg) + 1 else -arg; return a*1 + f(4);";
+        let mut parser = ParserBuilder::new().check_monotonicity(true).build_noarg(src).unwrap();
+
+        // Act
+        let parsed = parser.parse();
+        let inlined = parser.inline_calls();
+
+        // Assert
+        assert!(parsed.is_ok());
+        assert!(inlined.is_ok());
+    }
+
+    #[test]
+    fn should_catch_pass_rewiring_use_to_less_precise_node() {
+        // Arrange
+        let mut parser = ParserBuilder::new().check_monotonicity(true).do_optimize(false).build_noarg("return arg+1;").unwrap();
+        parser.parse().unwrap();
+        let add = parser.nodes().find(|n| matches!(n.node_kind, NodeKind::Add)).unwrap().nid;
+        let arg = parser.graph.inputs_of(add).unwrap()[0];
+
+        // Act
+        let result = parser.with_monotonicity_check(|parser| parser.graph.set_input(add, 1, arg));
+
+        // Assert
+        assert!(matches!(result, Err(SoNError::TypTransitionNotAllowed)));
+    }
+
+    #[test]
+    fn should_parse_from_file() {
+        // Arrange
+        let path = std::env::temp_dir().join(format!("ruson_from_file_{}.son", std::process::id()));
+        std::fs::write(&path, "return arg;").unwrap();
+
+        // Act
+        let parser = Parser::from_file(&path, 3);
+        std::fs::remove_file(&path).unwrap();
+        let mut parser = parser.unwrap();
         let result = parser.parse().unwrap();
 
         // Assert
-        let node = parser.graph.get(result).unwrap().as_ref().unwrap();
-        assert_eq!("return ((1*2)+3);", format!("{:}", BoundNode::new(&node, &parser.graph)));
+        let node = parser.graph.get_node(result).unwrap();
+        assert_eq!("return 3;", format!("{:}", BoundNode::new(node, &parser.graph)));
     }
 
     #[test]
-    fn should_parse_mul_and_mul() {
+    fn should_parse_from_reader() {
         // Arrange
-        let mut parser = Parser::new_noarg("return 1*2*3;").unwrap();
-        parser.do_optimize = false;
+        let reader = std::io::Cursor::new(b"int a = arg * 2;\nreturn a;".to_vec());
 
         // Act
+        let mut parser = Parser::from_reader(reader, 4).unwrap();
         let result = parser.parse().unwrap();
 
         // Assert
-        let node = parser.graph.get(result).unwrap().as_ref().unwrap();
-        assert_eq!("return (1*(2*3));", format!("{:}", BoundNode::new(&node, &parser.graph)));
+        let node = parser.graph.get_node(result).unwrap();
+        assert_eq!("return 8;", format!("{:}", BoundNode::new(node, &parser.graph)));
     }
 
     #[test]
-    fn should_parse_complex_expression() {
+    fn should_parse_from_reader_without_arg() {
         // Arrange
-        let mut parser = Parser::new_noarg("return 1+2*3+-5;").unwrap();
-        parser.do_optimize = false;
+        let reader = std::io::Cursor::new(b"return arg * 2;".to_vec());
 
         // Act
+        let mut parser = Parser::from_reader_noarg(reader).unwrap();
         let result = parser.parse().unwrap();
 
         // Assert
         let node = parser.graph.get_node(result).unwrap();
-        assert_eq!("return (1+((2*3)+(-5)));", format!("{:}", BoundNode::new(&node, &parser.graph)));
+        assert_eq!("return arg*2;", format!("{:}", BoundNode::new(node, &parser.graph)));
     }
 
     #[test]
-    fn should_peephole_computed_types() {
+    fn should_return_io_error_for_invalid_utf8_from_reader() {
+        // Act
+        let result = Parser::from_reader(std::io::Cursor::new(vec![0xff, 0xfe]), 0);
+
+        // Assert
+        assert!(matches!(result, Err(SoNError::Io { .. })));
+    }
+
+    #[test]
+    fn should_return_io_error_for_missing_file() {
+        // Act
+        let result = Parser::from_file("does/not/exist.son", 0);
+
+        // Assert
+        assert!(matches!(result, Err(SoNError::Io { path, .. }) if path == "does/not/exist.son"));
+    }
+
+    #[test]
+    fn should_parse_empty_block() {
         // Arrange
-        let mut parser = Parser::new_noarg("return 1+2*3+-5;").unwrap();
+        let mut parser = Parser::new_noarg("{}").unwrap();
+
+        // Act
+        let result = parser.parse();
+
+        // Assert
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn should_skip_stray_semicolons() {
+        // Arrange
+        let mut parser = Parser::new_noarg(";; int a=1;; return a;;").unwrap();
 
         // Act
         let result = parser.parse().unwrap();
 
         // Assert
         let node = parser.graph.get_node(result).unwrap();
-        assert_eq!("return 2;", format!("{:}", BoundNode::new(&node, &parser.graph)));
+        assert_eq!("return 1;", format!("{:}", BoundNode::new(node, &parser.graph)));
     }
 
     #[test]
-    fn should_define_var() {
+    fn should_parse_only_semicolons() {
         // Arrange
-        let mut parser = Parser::new_noarg("").unwrap();
-        parser.push_scope().unwrap();
-        let nid = parser.add_node_unrefined(vec![], NodeKind::Constant).unwrap();
+        let mut parser = Parser::new_noarg(";;").unwrap();
 
         // Act
-        parser.define_var("x", nid).unwrap();
+        let result = parser.parse();
 
         // Assert
-        assert!(matches!(parser.graph.get_node(nid).unwrap().outputs.as_slice(), [a] if a == &SCOPE_NID));
-        assert!(matches!(parser.graph.get_node(SCOPE_NID).unwrap().inputs.as_slice(), [a] if a == &nid));
-        if let NodeKind::Scope { scopes } = &parser.graph.get_node(SCOPE_NID).unwrap().node_kind {
-            if let [ map ] = scopes.as_slice() && let Some(a) = map.get("x") && a == &nid {
-                return;
-            }
-        }
-        panic!();
+        assert!(result.is_ok());
     }
 
     #[test]
-    fn should_define_var_in_program() {
+    fn should_keep_prints_in_order() {
         // Arrange
-        let mut parser = Parser::new_noarg("int a=1; return a;").unwrap();
+        let mut parser = Parser::new_noarg("print(1); print(arg); return 0;").unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let ret = parser.node(result).unwrap();
+        let second = parser.node(ret.inputs[0]).unwrap();
+        assert!(matches!(second.node_kind, NodeKind::Print));
+        assert_eq!("print(arg);", format!("{:}", BoundNode::new(second, &parser.graph)));
+        let first = parser.node(second.inputs[0]).unwrap();
+        assert!(matches!(first.node_kind, NodeKind::Print));
+        assert_eq!(first.nid, second.inputs[1], "the second print has to depend on the memory of the first");
+        assert_eq!("print(1);", format!("{:}", BoundNode::new(first, &parser.graph)));
+        assert!(matches!(parser.node(first.inputs[0]).unwrap().node_kind, NodeKind::Proj { proj_index: 0, .. } | NodeKind::Start));
+    }
+
+    #[test]
+    fn should_project_every_parameter() {
+        // Arrange
+        let params = vec![("x".into(), Typ::IntBot), ("y".into(), Typ::IntBot)];
+        let mut parser = ParserBuilder::new().params(params).build_noarg("return x + y;").unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let ret = parser.node(result).unwrap();
+        let add = parser.node(ret.inputs[1]).unwrap();
+        assert!(matches!(add.node_kind, NodeKind::Add));
+        let labels: Vec<_> = add.inputs.iter().map(|&i| &parser.node(i).unwrap().node_kind).collect();
+        assert!(matches!(labels.as_slice(), [NodeKind::Proj { proj_index: 2, _dbg_proj_label: x }, NodeKind::Proj { proj_index: 3, _dbg_proj_label: y }] if x == "x" && y == "y"));
+        assert!(matches!(parser.graph.get_node(START_NID).unwrap().typ(), Typ::Tuple { typs } if typs.len() == 4));
+    }
+
+    #[test]
+    fn should_bind_value_to_configured_parameter_name() {
+        // Arrange
+        let mut parser = ParserBuilder::new().params(vec![("n".into(), Typ::IntBot)]).build("return n+1;", 4).unwrap();
 
         // Act
         let result = parser.parse().unwrap();
 
         // Assert
         let node = parser.graph.get(result).unwrap().as_ref().unwrap();
-        assert_eq!("return 1;", format!("{:}", BoundNode::new(&node, &parser.graph)));
+        assert_eq!("return 5;", format!("{:}", BoundNode::new(node, &parser.graph)));
     }
 
     #[test]
-    fn should_return_error_on_variable_redefinition() {
+    fn should_reject_single_value_for_several_parameters() {
         // Arrange
-        let mut parser = Parser::new_noarg("int a=1;int a=1;").unwrap();
+        let params = vec![("x".into(), Typ::IntBot), ("y".into(), Typ::IntBot)];
 
         // Act
-        let result = parser.parse();
+        let result = ParserBuilder::new().params(params).build("return x + y;", 4);
 
         // Assert
-        assert!(matches!(result, Err(ErrorWithContext{error: SoNError::VariableRedefinition { variable: v },..}) if v == "a"));
+        assert!(matches!(result, Err(SoNError::ArgumentCountMismatch { expected: 2, but_got: 1 })));
     }
 
     #[test]
-    fn should_return_error_on_undefined_variable() {
+    fn should_reject_duplicate_parameter() {
         // Arrange
-        let mut parser = Parser::new_noarg("a=1;").unwrap();
+        let params = vec![("x".into(), Typ::IntBot), ("x".into(), Typ::BoolBot)];
+        let mut parser = ParserBuilder::new().params(params).build_noarg("return x;").unwrap();
 
         // Act
         let result = parser.parse();
 
         // Assert
-        assert!(matches!(result, Err(ErrorWithContext{error: SoNError::VariableUndefined { variable: v },..}) if v == "a"));
+        assert!(matches!(result, Err(ErrorWithContext { error: SoNError::VariableRedefinition { variable }, .. }) if variable == "x"));
     }
 
     #[test]
-    fn should_have_ctrl_and_arg_defined() {
+    fn should_load_stored_field_as_constant() {
         // Arrange
-        let mut parser = Parser::new("return arg;", 84).unwrap();
-        parser.do_optimize = false;
+        let mut parser = Parser::new_noarg("struct P { int x; int y; } P p = new P; p.x = 3; p.y = arg; return p.x;").unwrap();
 
         // Act
         let result = parser.parse().unwrap();
 
         // Assert
-        let node = parser.graph.get_node(result).unwrap();
-        let ctrl = parser.graph.get_node(*node.inputs.get(0).unwrap()).unwrap().clone();
-        let arg = parser.graph.get_node(*node.inputs.get(1).unwrap()).unwrap().clone();
+        assert_eq!("return 3;", format!("{:}", BoundNode::new(parser.node(result).unwrap(), &parser.graph)));
+    }
 
-        assert!(matches!(ctrl.node_kind, NodeKind::Proj {..}));
-        assert!(matches!(arg.node_kind, NodeKind::Proj {..}));
-        assert!(matches!(ctrl.typ(), Typ::Ctrl));
-        assert!(matches!(arg.typ(), Typ::Int { constant: 84 }));
+    #[test]
+    fn should_load_zeroed_field_of_new_struct() {
+        // Arrange
+        let mut parser = Parser::new_noarg("struct P { int x; bool b; } P p = new P; P q = new P; q.b = arg < 1; return p.b;").unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        assert_eq!("return false;", format!("{:}", BoundNode::new(parser.node(result).unwrap(), &parser.graph)));
+    }
+
+    #[test]
+    fn should_keep_load_of_unknown_store() {
+        // Arrange
+        let mut parser = ParserBuilder::new().do_optimize(false).build_noarg("struct P { int x; } P p = new P; p.x = arg; return p.x+1;").unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        assert_eq!("return new P.x+1;", format!("{:}", BoundNode::new(parser.node(result).unwrap(), &parser.graph)));
+    }
+
+    #[test]
+    fn should_reject_undefined_field() {
+        // Arrange
+        let mut parser = Parser::new_noarg("struct P { int x; } P p = new P; return p.y;").unwrap();
+
+        // Act
+        let result = parser.parse();
+
+        // Assert
+        assert!(matches!(result, Err(ErrorWithContext { error: SoNError::FieldUndefined { struct_name, field }, .. }) if struct_name == "P" && field == "y"));
+    }
+
+    #[test]
+    fn should_call_defined_function() {
+        // Arrange
+        let mut parser = Parser::new_noarg("int sq(int x){ return x*x; } return sq(5);").unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let ret = parser.node(result).unwrap();
+        assert_eq!("return sq(5);", format!("{:}", BoundNode::new(ret, &parser.graph)));
+        let call_end = parser.node(ret.inputs[1]).unwrap();
+        assert!(matches!(call_end.node_kind, NodeKind::CallEnd));
+        let call = parser.node(call_end.inputs[0]).unwrap();
+        assert!(matches!(&call.node_kind, NodeKind::Call { name, argc: 1 } if name == "sq"));
+        assert_eq!(ret.inputs[0], call.nid);
+        assert!(matches!(&parser.node(call.inputs[2]).unwrap().node_kind, NodeKind::Fun { name } if name == "sq"));
+        let body = parser.nodes().find(|n| matches!(n.node_kind, NodeKind::Return) && n.nid != ret.nid).unwrap();
+        assert_eq!("return x*x;", format!("{:}", BoundNode::new(body, &parser.graph)));
+    }
+
+    #[test]
+    fn should_not_see_outer_variables_in_function() {
+        // Arrange
+        let mut parser = Parser::new_noarg("int a=1; int f(){ return a; } return f();").unwrap();
+
+        // Act
+        let result = parser.parse();
+
+        // Assert
+        assert!(matches!(result, Err(ErrorWithContext { error: SoNError::VariableUndefined { variable, .. }, .. }) if variable == "a"));
+    }
+
+    #[test]
+    fn should_parse_program_starting_with_byte_order_mark() {
+        // Arrange
+        let mut parser = Parser::new_noarg("\u{FEFF}return 1;").unwrap();
+
+        // Act
+        let result = parser.parse();
+
+        // Assert
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn should_report_column_after_byte_order_mark() {
+        // Arrange
+        let mut parser = Parser::new_noarg("\u{FEFF}return 1+;").unwrap();
+
+        // Act
+        let result = parser.parse();
+
+        // Assert
+        assert!(matches!(result, Err(ErrorWithContext { line: 1, col: 10, .. })));
+    }
+
+    #[test]
+    fn should_reject_keyword_as_variable_name() {
+        // Arrange
+        let mut parser = Parser::new_noarg("int int = 1; return 1;").unwrap();
+
+        // Act
+        let result = parser.parse();
+
+        // Assert
+        assert!(matches!(result, Err(ErrorWithContext { error: SoNError::ReservedKeyword { keyword }, line: 1, col: 5, .. }) if keyword == "int"));
+    }
+
+    #[test]
+    fn should_suggest_close_variable_when_undefined() {
+        // Arrange
+        let mut parser = Parser::new_noarg("int abc=1; return ar+1;").unwrap();
+
+        // Act
+        let result = parser.parse();
+
+        // Assert
+        assert!(matches!(result, Err(ErrorWithContext { error: SoNError::VariableUndefined { variable, suggestion: Some(suggestion) }, .. })
+            if variable == "ar" && suggestion == "arg"));
+    }
+
+    #[test]
+    fn should_not_suggest_distant_variable_when_undefined() {
+        // Arrange
+        let mut parser = Parser::new_noarg("int a=1; xyzw=2; return a;").unwrap();
+
+        // Act
+        let result = parser.parse();
+
+        // Assert
+        assert!(matches!(result, Err(ErrorWithContext { error: SoNError::VariableUndefined { suggestion: None, .. }, .. })));
+    }
+
+    #[test]
+    fn should_reject_function_without_return() {
+        // Arrange
+        let mut parser = Parser::new_noarg("int a=1;\nint f(int x){ int y = x; } return f(a);").unwrap();
+
+        // Act
+        let result = parser.parse();
+
+        // Assert
+        assert!(matches!(result, Err(ErrorWithContext { error: SoNError::MissingReturn { function }, line: 2, col: 1, .. }) if function == "f"));
+    }
+
+    #[test]
+    fn should_reject_returned_value_of_other_typ() {
+        // Arrange
+        let mut parser = Parser::new_noarg("int f(int x){ return x < 1; } return f(arg);").unwrap();
+
+        // Act
+        let result = parser.parse();
+
+        // Assert
+        assert!(matches!(result, Err(ErrorWithContext { error: SoNError::TypeMismatch { variable: None, .. }, line: 1, col: 15, .. })));
+    }
+
+    #[test]
+    fn should_reject_unknown_function() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return sq(5);").unwrap();
+
+        // Act
+        let result = parser.parse();
+
+        // Assert
+        assert!(matches!(result, Err(ErrorWithContext { error: SoNError::UnknownFunction { name }, .. }) if name == "sq"));
+    }
+
+    #[test]
+    fn should_fold_indexing_of_constant_initialized_array() {
+        // Arrange
+        let mut parser = Parser::new_noarg("int[] a = new int[3]; a[0] = 7; a[1] = arg; a[2] = 9; return a[0] + a[2];").unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        assert_eq!("return 16;", format!("{:}", BoundNode::new(parser.node(result).unwrap(), &parser.graph)));
+    }
+
+    #[test]
+    fn should_keep_load_of_unknown_index() {
+        // Arrange
+        let mut parser = Parser::new_noarg("int[] a = new int[2]; a[0] = 7; return a[arg];").unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        assert_eq!("return new int[2][arg];", format!("{:}", BoundNode::new(parser.node(result).unwrap(), &parser.graph)));
+    }
+
+    #[test]
+    fn should_reject_constant_index_out_of_bounds() {
+        // Arrange
+        let mut parser = Parser::new_noarg("int[] a = new int[3]; a[1] = 2; return a[3];").unwrap();
+
+        // Act
+        let result = parser.parse();
+
+        // Assert
+        assert!(matches!(result, Err(ErrorWithContext { error: SoNError::IndexOutOfBounds { index: 3, len: 3 }, .. })));
+    }
+
+    #[test]
+    fn should_assign_variable_named_like_a_struct() {
+        // Arrange
+        let mut parser = Parser::new_noarg("struct P { int x; } int P = 1; P = 2; return P;").unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        assert_eq!("return 2;", format!("{:}", BoundNode::new(parser.node(result).unwrap(), &parser.graph)));
+    }
+
+    #[test]
+    fn should_parse_like_a_fresh_parser_after_reset() {
+        // Arrange
+        let mut parser = Parser::new("int a=arg*2; return a+1;", 1).unwrap();
+        parser.parse().unwrap();
+        let mut fresh = Parser::new("return arg+arg*3;", 5).unwrap();
+        let fresh_result = fresh.parse().unwrap();
+
+        // Act
+        parser.reset("return arg+arg*3;", vec![("arg".into(), Typ::Int { constant: 5 })]).unwrap();
+        let result = parser.parse().unwrap();
+
+        // Assert
+        assert!(matches!(parser.graph.get_node(KEEP_ALIVE_NID).unwrap().node_kind, NodeKind::KeepAlive));
+        assert!(matches!(parser.graph.get_node(SCOPE_NID).unwrap().node_kind, NodeKind::Scope { .. }));
+        assert!(matches!(parser.graph.get_node(START_NID).unwrap().node_kind, NodeKind::Start));
+        assert_eq!(fresh.graph.live_count(), parser.graph.live_count());
+        assert!(parser.graph.structurally_equal(result, &fresh.graph, fresh_result));
+    }
+
+    #[test]
+    fn should_skip_nested_comments() {
+        // Arrange
+        let mut parser = Parser::new_noarg("int a=1; /* a=2; /* nested */ a=3; */ return a;").unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get_node(result).unwrap();
+        assert_eq!("return 1;", format!("{:}", BoundNode::new(node, &parser.graph)));
+    }
+
+    #[test]
+    fn should_return_error_on_unterminated_nested_comment() {
+        // Arrange
+        let mut parser = Parser::new_noarg("int a=1; /* a=2; /* nested */ a=3; return a;").unwrap();
+
+        // Act
+        let result = parser.parse();
+
+        // Assert
+        assert!(matches!(result, Err(ErrorWithContext { error: SoNError::UnterminatedComment, line: 1, col: 10, .. })));
+    }
+
+    #[test]
+    fn should_parse_float_literal() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return 1.5;").unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get_node(result).unwrap();
+        let value = parser.graph.get_node(node.inputs[1]).unwrap();
+        assert_eq!(Typ::Float { constant: 1.5 }, value.typ());
+        assert_eq!("return 1.5;", format!("{:}", BoundNode::new(node, &parser.graph)));
+    }
+
+    #[test]
+    fn should_not_fold_when_built_without_optimization() {
+        // Arrange
+        let mut parser = ParserBuilder::new().do_optimize(false).build("return 1+arg;", 2).unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get_node(result).unwrap();
+        assert_eq!("return 1+arg;", format!("{:}", BoundNode::new(node, &parser.graph)));
+    }
+
+    #[test]
+    fn should_fold_float_division_by_zero_to_inf() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return 1.0/0.0;").unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get_node(result).unwrap();
+        assert_eq!(Typ::Float { constant: f64::INFINITY }, parser.graph.get_node(node.inputs[1]).unwrap().typ());
+    }
+
+    #[test]
+    fn should_fold_float_multiplication() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return 2.5*2.0;").unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get_node(result).unwrap();
+        assert_eq!("return 5.0;", format!("{:}", BoundNode::new(node, &parser.graph)));
+    }
+
+    #[test]
+    fn should_reject_mixed_int_and_float_arithmetic() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return arg+1.5;").unwrap();
+
+        // Act
+        let result = parser.parse();
+
+        // Assert
+        assert!(matches!(result, Err(ErrorWithContext { error: SoNError::TypeMismatch { .. }, .. })));
+    }
+
+    #[test]
+    fn should_expose_live_nodes() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return arg;").unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        assert_eq!(parser.graph.live_nids(), parser.nodes().map(|n| n.nid).collect::<Vec<_>>());
+        assert!(matches!(parser.node(result).unwrap().node_kind, NodeKind::Return));
+        assert!(parser.node(parser.graph.len()).is_none());
+    }
+
+    #[test]
+    fn should_report_unused_variable() {
+        // Arrange
+        let mut parser = Parser::new_noarg("int a=1; int b=2; return a;").unwrap();
+
+        // Act
+        parser.parse().unwrap();
+
+        // Assert
+        assert_eq!(vec!["b".to_string()], parser.unused_variables());
+    }
+
+    #[test]
+    fn should_not_report_reassigned_variable_that_is_read() {
+        // Arrange
+        let mut parser = Parser::new_noarg("int a=1; int b=2; { int c=b; } b=4; return b;").unwrap();
+
+        // Act
+        parser.parse().unwrap();
+
+        // Assert
+        assert_eq!(vec!["a".to_string(), "c".to_string()], parser.unused_variables());
+    }
+
+    #[test]
+    fn should_parse_return() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return 1;").unwrap();
+        parser.do_optimize = false;
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get(result).unwrap().as_ref().unwrap();
+        assert!(matches!(node.node_kind, NodeKind::Return));
+        assert!(matches!(node.outputs.as_slice(), []));
+        match node.inputs.as_slice() {
+            [_, x] => {
+                let dnode = parser.graph.get(*x).unwrap().as_ref().unwrap();
+                assert!(matches!(dnode.typ(), Typ::Int { constant: 1 }));
+                assert!(matches!(dnode.outputs.as_slice(), [y] if y.eq(&result) ));
+            }
+            _ => panic!()
+        }
+        println!("Parsing result is: {}", BoundNode::new(node, &parser.graph));
+    }
+
+    #[test]
+    fn should_drop_unused_nodes_but_never_the_keepalive_node() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return 1;").unwrap();
+        parser.do_optimize = false;
+
+        // Act
+        let _result = parser.parse().unwrap();
+
+        // Assert
+        assert_eq!(6, parser.graph.iter().filter(|n| n.is_some()).count());
+        assert!(matches!( parser.graph.get(KEEP_ALIVE_NID).unwrap().as_ref().unwrap().node_kind, NodeKind::KeepAlive))
+    }
+
+    #[test]
+    fn should_not_list_dropped_nodes_as_live() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return 1;").unwrap();
+        parser.do_optimize = false;
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let live_nids = parser.graph.live_nids();
+        assert_eq!(6, live_nids.len());
+        assert_eq!(6, parser.graph.live_count());
+        assert!(live_nids.contains(&result));
+        assert!(live_nids.iter().all(|&nid| parser.graph.node_exists(nid)));
+        assert!(parser.graph.iter().enumerate().filter(|(_, n)| n.is_none()).all(|(nid, _)| !live_nids.contains(&nid)));
+    }
+
+    #[test]
+    fn should_not_drop_any_node_when_cap_is_0() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return 1;").unwrap();
+        parser.do_optimize = false;
+
+        // Act
+        let _result = parser.parse().unwrap();
+        let dropped_nodes = parser.drop_unused_nodes_cap(0);
+
+        // Assert
+        assert_eq!(0, dropped_nodes);
+        assert_eq!(6, parser.graph.iter().filter(|n| n.is_some()).count());
+    }
+
+    #[test]
+    fn should_only_drop_one_node_when_cap_is_1() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return 1;").unwrap();
+        parser.do_optimize = false;
+
+        // Act
+        let _result = parser.parse().unwrap();
+        let dropped_nodes = parser.drop_unused_nodes_cap(1);
+
+        // Assert
+        assert_eq!(1, dropped_nodes);
+        assert_eq!(5, parser.graph.iter().filter(|n| n.is_some()).count());
+        assert!(matches!( parser.graph.get(START_NID).unwrap().as_ref().unwrap().node_kind, NodeKind::Start))
+    }
+
+    #[test]
+    fn should_fail_when_invalid_syntax_is_used() {
+        // Arrange
+        let mut parser = Parser::new_noarg("ret 1;").unwrap();
+        parser.do_optimize = false;
+
+        // Act
+        let result = parser.parse();
+
+        // Assert
+        assert!(matches!(result, Err(ErrorWithContext{error: SoNError::SyntaxExpected {expected, ..},..}) if expected == "="));
+    }
+
+    #[test]
+    fn should_report_the_position_of_the_offending_token() {
+        // Arrange
+        let mut parser = Parser::new_noarg("int a = 1;\nint b = ;").unwrap();
+
+        // Act
+        let result = parser.parse();
+
+        // Assert
+        match result {
+            Err(ErrorWithContext { error: SoNError::SyntaxExpected { but_got, .. }, line, col, .. }) => {
+                assert_eq!(";", but_got);
+                assert_eq!((2, 9), (line, col));
+            }
+            _ => panic!("expected a syntax error, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn should_report_the_position_of_a_missing_identifier() {
+        // Arrange
+        let mut parser = Parser::new_noarg("int a = 1;\n    int 2 = a;\nreturn a;").unwrap();
+
+        // Act
+        let result = parser.parse();
+
+        // Assert
+        match result {
+            Err(ErrorWithContext { error: SoNError::SyntaxExpected { but_got, .. }, line, col, .. }) => {
+                assert_eq!("2", but_got);
+                assert_eq!((2, 9), (line, col));
+            }
+            _ => panic!("expected a syntax error, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn should_report_the_position_of_an_undefined_variable() {
+        // Arrange
+        let mut parser = Parser::new_noarg("int a = 1;\nint b = 2;\nreturn a + c;").unwrap();
+
+        // Act
+        let result = parser.parse();
+
+        // Assert
+        match result {
+            Err(ErrorWithContext { error: SoNError::VariableUndefined { .. }, line, col, .. }) => assert_eq!((3, 12), (line, col)),
+            _ => panic!("expected an undefined variable, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn should_report_lowering_error_without_moving_the_lexer() {
+        // Arrange
+        let mut parser = Parser::new_noarg("int a = 1;\nreturn a + (a / 0);").unwrap();
+
+        // Act
+        let result = parser.parse();
+
+        // Assert
+        assert!(matches!(result, Err(ErrorWithContext { error: SoNError::DivisionByZero, line: 2, col: 15, .. })));
+        assert!(parser.lexer.is_eof());
+    }
+
+    #[test]
+    fn should_report_the_position_of_a_constant_division_by_zero() {
+        // Arrange
+        let mut parser = Parser::new_noarg("int a = 1;\n\n  int b = a / 0;\nreturn b;").unwrap();
+
+        // Act
+        let result = parser.parse();
+
+        // Assert
+        match result {
+            Err(ErrorWithContext { error: SoNError::DivisionByZero, line, col, .. }) => assert_eq!((3, 13), (line, col)),
+            _ => panic!("expected a division by zero, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn should_report_folding_errors_at_the_operator() {
+        // Arrange
+        let mut division = Parser::new_noarg("return 5 / (1 - 1);").unwrap();
+        let mut power = Parser::new_noarg("return 2**64;").unwrap();
+
+        // Act
+        let division_result = division.parse();
+        let power_result = power.parse();
+
+        // Assert
+        assert!(matches!(division_result, Err(ErrorWithContext { error: SoNError::DivisionByZero, line: 1, col: 10, .. })));
+        assert!(matches!(power_result, Err(ErrorWithContext { error: SoNError::PowOutOfRange, line: 1, col: 9, .. })));
+        assert!(division_result.unwrap_err().to_string().ends_with("\n  |          ^"));
+    }
+
+    #[test]
+    fn should_fold_power_of_constants() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return 2 ** 10;").unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get_node(result).unwrap();
+        assert_eq!("return 1024;", format!("{:}", BoundNode::new(node, &parser.graph)));
+    }
+
+    #[test]
+    fn should_reject_negative_exponent() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return 2 ** -1;").unwrap();
+
+        // Act
+        let result = parser.parse();
+
+        // Assert
+        assert!(matches!(result, Err(ErrorWithContext { error: SoNError::PowOutOfRange, .. })));
+    }
+
+    #[test]
+    fn should_bind_power_right_associative_and_above_prefix() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return -arg ** 3 ** 2;").unwrap();
+        parser.do_optimize = false;
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get_node(result).unwrap();
+        assert_eq!("return -arg**3**2;", format!("{:}", BoundNode::new(node, &parser.graph)));
+        assert!(matches!(parser.evaluate_i64(&[2]), Ok(-512)));
+    }
+
+    #[test]
+    fn should_fold_intrinsics_of_constants() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return abs(-5) + min(3, 7) * max(2, 4);").unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get_node(result).unwrap();
+        assert_eq!("return 17;", format!("{:}", BoundNode::new(node, &parser.graph)));
+    }
+
+    #[test]
+    fn should_fold_comparison_of_non_negative_abs() {
+        // Arrange
+        let mut parser = Parser::new_noarg("bool a = abs((arg & 7) + -4) < 0;\nbool b = min(arg & 3, 10) <= 3;\nreturn a == b;").unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get_node(result).unwrap();
+        assert_eq!("return false;", format!("{:}", BoundNode::new(node, &parser.graph)));
+    }
+
+    #[test]
+    fn should_keep_overflow_of_abs_of_unknown_arg() {
+        // Arrange
+        let src = "return abs(arg) < 0;";
+        let mut optimized = Parser::new_noarg(src).unwrap();
+        let mut unoptimized = ParserBuilder::new().do_optimize(false).build_noarg(src).unwrap();
+
+        // Act
+        let result = optimized.parse().unwrap();
+        unoptimized.parse().unwrap();
+
+        // Assert
+        let node = optimized.graph.get_node(result).unwrap();
+        assert_eq!("return abs(arg) < 0;", format!("{:}", BoundNode::new(node, &optimized.graph)));
+        assert!(matches!(optimized.evaluate_args(&[i64::MIN]), Err(SoNError::IntegerOverflow)));
+        assert!(matches!(unoptimized.evaluate_args(&[i64::MIN]), Err(SoNError::IntegerOverflow)));
+        assert!(matches!(optimized.evaluate_args(&[-3]), Ok(Typ::Bool { constant: false })));
+        assert!(matches!(unoptimized.evaluate_args(&[-3]), Ok(Typ::Bool { constant: false })));
+    }
+
+    #[test]
+    fn should_reject_abs_of_smallest_int() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return abs(-9223372036854775807 - 1);").unwrap();
+
+        // Act
+        let result = parser.parse();
+
+        // Assert
+        assert!(matches!(result, Err(ErrorWithContext { error: SoNError::IntegerOverflow, .. })));
+    }
+
+    #[test]
+    fn should_print_intrinsics_as_calls() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return max(abs(arg), 2) - min(arg, 1);").unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get_node(result).unwrap();
+        assert_eq!("return max(abs(arg), 2)-min(arg, 1);", format!("{:}", BoundNode::new(node, &parser.graph)));
+        assert!(matches!(parser.evaluate_i64(&[-3]), Ok(6)));
+    }
+
+    #[test]
+    fn should_gather_constants_around_sub() {
+        // Arrange
+        let mut sub_add = Parser::new_noarg("return arg - 3 + 5;").unwrap();
+        let mut add_sub = Parser::new_noarg("return arg + 7 - 2;").unwrap();
+        let mut sub = Parser::new_noarg("return arg - 3;").unwrap();
+
+        // Act
+        let results = [sub_add.parse().unwrap(), add_sub.parse().unwrap(), sub.parse().unwrap()];
+
+        // Assert
+        let printed = [&sub_add, &add_sub, &sub].iter().zip(results)
+            .map(|(parser, result)| format!("{:}", BoundNode::new(parser.graph.get_node(result).unwrap(), &parser.graph)))
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["return arg+2;", "return arg+5;", "return arg-3;"], printed);
+    }
+
+    #[test]
+    fn should_keep_sub_without_canonicalization() {
+        // Arrange
+        let mut parser = ParserBuilder::new().canonicalize_sub(false).build_noarg("return arg - 3 + 5;").unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get_node(result).unwrap();
+        assert_eq!("return arg-3+5;", format!("{:}", BoundNode::new(node, &parser.graph)));
+    }
+
+    #[test]
+    fn should_check_for_semicolon() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return 1").unwrap();
+        parser.do_optimize = false;
+
+        // Act
+        let result = parser.parse();
+
+        // Assert
+        assert!(matches!(result, Err(ErrorWithContext{error: SoNError::SyntaxExpected {expected, ..},..}) if expected == ";"));
+    }
+
+    #[test]
+    fn should_report_the_column_of_a_missing_semicolon() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return 1").unwrap();
+
+        // Act
+        let result = parser.parse();
+
+        // Assert
+        assert!(matches!(result, Err(ErrorWithContext { error: SoNError::SyntaxExpected { .. }, line: 1, col: 9, .. })));
+    }
+
+    #[test]
+    fn should_parse_braced_program_without_implicit_block() {
+        // Arrange
+        let mut parser = ParserBuilder::new().implicit_block(false).build_noarg(" { int a = arg; return a + 1; } ").unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get_node(result).unwrap();
+        assert_eq!("return arg+1;", format!("{:}", BoundNode::new(node, &parser.graph)));
+    }
+
+    #[test]
+    fn should_require_braces_without_implicit_block() {
+        // Arrange
+        let mut parser = ParserBuilder::new().implicit_block(false).build_noarg("return 1;").unwrap();
+
+        // Act
+        let result = parser.parse();
+
+        // Assert
+        assert!(matches!(result, Err(ErrorWithContext { error: SoNError::SyntaxExpected { expected, .. }, line: 1, col: 1, .. }) if expected == "{"));
+    }
+
+    #[test]
+    fn should_fail_at_brace() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return 1;}").unwrap();
+        parser.do_optimize = false;
+
+        // Act
+        let result = parser.parse();
+
+        // Assert
+        assert!(matches!(result, Err(ErrorWithContext{error: SoNError::SyntaxExpected {expected, ..},..}) if expected == "End of file"));
+    }
+
+    #[test]
+    fn should_delete_nodes_that_arent_kept_alive() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return 1;").unwrap();
+        parser.do_optimize = false;
+        let nid = parser.add_node_unrefined(vec![], NodeKind::Start).unwrap(); // this node is not kept
+
+        // Act
+        let _result = parser.parse();
+
+        // Assert
+        assert!(!parser.graph.node_exists_unique(nid, nid));
+    }
+
+    #[test]
+    fn should_parse_one_plus_one() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return 1+1;").unwrap();
+        parser.do_optimize = false;
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get(result).unwrap().as_ref().unwrap();
+        assert_eq!("return 1+1;", format!("{:}", BoundNode::new(node, &parser.graph)));
+    }
+
+    #[test]
+    fn should_parse_one_minus_one() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return 1-1;").unwrap();
+        parser.do_optimize = false;
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get(result).unwrap().as_ref().unwrap();
+        assert_eq!("return 1-1;", format!("{:}", BoundNode::new(node, &parser.graph)));
+    }
+
+    #[test]
+    fn should_fold_unary_plus() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return +5;").unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get(result).unwrap().as_ref().unwrap();
+        assert_eq!("return 5;", format!("{:}", BoundNode::new(node, &parser.graph)));
+    }
+
+    #[test]
+    fn should_parse_binary_plus_before_unary_plus() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return 2++3*+-1;").unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get(result).unwrap().as_ref().unwrap();
+        assert_eq!("return -1;", format!("{:}", BoundNode::new(node, &parser.graph)));
+    }
+
+    #[test]
+    fn should_parse_one_times_one() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return 1*1;").unwrap();
+        parser.do_optimize = false;
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get(result).unwrap().as_ref().unwrap();
+        assert_eq!("return 1*1;", format!("{:}", BoundNode::new(node, &parser.graph)));
+    }
+
+    #[test]
+    fn should_parse_one_div_one() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return 1/1;").unwrap();
+        parser.do_optimize = false;
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get(result).unwrap().as_ref().unwrap();
+        assert_eq!("return 1/1;", format!("{:}", BoundNode::new(node, &parser.graph)));
+    }
+
+    #[test]
+    fn should_parse_mul_and_add() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return 1*2+3;").unwrap();
+        parser.do_optimize = false;
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get(result).unwrap().as_ref().unwrap();
+        assert_eq!("return 1*2+3;", format!("{:}", BoundNode::new(node, &parser.graph)));
+    }
+
+    #[test]
+    fn should_parse_mul_and_mul() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return 1*2*3;").unwrap();
+        parser.do_optimize = false;
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get(result).unwrap().as_ref().unwrap();
+        assert_eq!("return 1*2*3;", format!("{:}", BoundNode::new(node, &parser.graph)));
+    }
+
+    #[test]
+    fn should_parse_complex_expression() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return 1+2*3+-5;").unwrap();
+        parser.do_optimize = false;
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get_node(result).unwrap();
+        assert_eq!("return 1+2*3+-5;", format!("{:}", BoundNode::new(node, &parser.graph)));
+    }
+
+    #[test]
+    fn should_parse_sub_left_associative() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return 1-2-3;").unwrap();
+        parser.do_optimize = false;
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get_node(result).unwrap();
+        assert_eq!("return 1-2-3;", format!("{:}", BoundNode::new(node, &parser.graph)));
+    }
+
+    #[test]
+    fn should_keep_parens_of_right_nested_sub() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return 1-(2-3);").unwrap();
+        parser.do_optimize = false;
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get_node(result).unwrap();
+        assert_eq!("return 1-(2-3);", format!("{:}", BoundNode::new(node, &parser.graph)));
+    }
+
+    #[test]
+    fn should_bind_relational_weaker_than_arithmetic() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return 1+2 <= 3*4;").unwrap();
+        parser.do_optimize = false;
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get_node(result).unwrap();
+        assert_eq!("return 1+2 <= 3*4;", format!("{:}", BoundNode::new(node, &parser.graph)));
+    }
+
+    #[test]
+    fn should_bind_logical_and_stronger_than_logical_or() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return 1<2 || 2!=3 && 3<4;").unwrap();
+        parser.do_optimize = false;
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let ret = parser.graph.get_node(result).unwrap();
+        let or = parser.graph.get_node(ret.inputs[1]).unwrap();
+        assert_eq!(NodeKind::Comp { kind: CompNodeKind::LogOr }, or.node_kind);
+        let and = parser.graph.get_node(or.inputs[1]).unwrap();
+        assert_eq!(NodeKind::Comp { kind: CompNodeKind::LogAnd }, and.node_kind);
+        assert_eq!(NodeKind::Not, parser.graph.get_node(and.inputs[0]).unwrap().node_kind);
+    }
+
+    #[test]
+    fn should_bind_bitwise_weaker_than_comparison() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return arg&1 == 1;").unwrap();
+        parser.do_optimize = false;
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let ret = parser.graph.get_node(result).unwrap();
+        let and = parser.graph.get_node(ret.inputs[1]).unwrap();
+        assert_eq!(NodeKind::Comp { kind: CompNodeKind::LogAnd }, and.node_kind);
+        let eq = parser.graph.get_node(and.inputs[1]).unwrap();
+        assert_eq!(NodeKind::Comp { kind: CompNodeKind::EQ }, eq.node_kind);
+    }
+
+    #[test]
+    fn should_absorb_or_into_and() {
+        // Arrange
+        let mut parser = Parser::new_noarg("bool a = arg < 1;\nbool b = arg == 2;\nreturn a && (a || b);").unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get_node(result).unwrap();
+        assert_eq!("return arg < 1;", format!("{:}", BoundNode::new(node, &parser.graph)));
+    }
+
+    #[test]
+    fn should_absorb_and_into_or() {
+        // Arrange
+        let mut parser = Parser::new_noarg("bool a = arg < 1;\nbool b = arg == 2;\nreturn (b && a) || a;").unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get_node(result).unwrap();
+        assert_eq!("return arg < 1;", format!("{:}", BoundNode::new(node, &parser.graph)));
+    }
+
+    #[test]
+    fn should_fold_conjunction_with_complement_to_false() {
+        // Arrange
+        let mut parser = Parser::new_noarg("bool a = arg < 1;\nreturn a && !a;").unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get_node(result).unwrap();
+        assert_eq!("return false;", format!("{:}", BoundNode::new(node, &parser.graph)));
+    }
+
+    #[test]
+    fn should_fold_disjunction_with_complement_to_true() {
+        // Arrange
+        let mut parser = Parser::new_noarg("bool a = arg < 1;\nreturn !a || a;").unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get_node(result).unwrap();
+        assert_eq!("return true;", format!("{:}", BoundNode::new(node, &parser.graph)));
+    }
+
+    #[test]
+    fn should_fold_comparison_of_masked_arg() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return (arg & 1) < 2;").unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get_node(result).unwrap();
+        assert_eq!("return true;", format!("{:}", BoundNode::new(node, &parser.graph)));
+    }
+
+    #[test]
+    fn should_fold_comparison_of_disjoint_ranges() {
+        // Arrange
+        let mut parser = Parser::new_noarg("int a = arg & 3;\nint b = (arg & 7) + 4;\nreturn a < b;").unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get_node(result).unwrap();
+        assert_eq!("return true;", format!("{:}", BoundNode::new(node, &parser.graph)));
+    }
+
+    #[test]
+    fn should_fold_comparison_of_disjoint_ranges_to_false() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return (arg & 3) + 8 <= (arg & 7);").unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get_node(result).unwrap();
+        assert_eq!("return false;", format!("{:}", BoundNode::new(node, &parser.graph)));
+    }
+
+    #[test]
+    fn should_fold_comparison_of_range_difference() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return (arg & 3) - (arg & 7) < 4;").unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get_node(result).unwrap();
+        assert_eq!("return true;", format!("{:}", BoundNode::new(node, &parser.graph)));
+    }
+
+    #[test]
+    fn should_fold_equality_of_disjoint_ranges_to_false() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return (arg & 4) + 5 == 9 - (arg & 1) - 8;").unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get_node(result).unwrap();
+        assert_eq!("return false;", format!("{:}", BoundNode::new(node, &parser.graph)));
+    }
+
+    #[test]
+    fn should_fold_comparison_over_shared_masks() {
+        // Arrange
+        let src = format!("int a = arg & 1;\n{}return a < 100;", "a = a + (a & 1);\n".repeat(40));
+        let mut parser = Parser::new_noarg(&src).unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get_node(result).unwrap();
+        assert_eq!("return true;", format!("{:}", BoundNode::new(node, &parser.graph)));
+    }
+
+    #[test]
+    fn should_fold_comparison_over_shared_differences() {
+        // Arrange
+        let src = format!("int a = arg & 1;\n{}return a <= 1;", "a = a - (a & 1);\n".repeat(40));
+        let mut parser = Parser::new_noarg(&src).unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get_node(result).unwrap();
+        assert_eq!("return true;", format!("{:}", BoundNode::new(node, &parser.graph)));
+    }
+
+    #[test]
+    fn should_not_bound_cast_of_int_operand() {
+        // Arrange
+        let mut parser = ParserBuilder::new().do_optimize(false).build_noarg("return (int)arg < 2;").unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get_node(result).unwrap();
+        assert!(matches!(parser.graph.get_node(node.inputs[1]).unwrap().typ(), Typ::BoolBot));
+    }
+
+    #[test]
+    fn should_not_fold_comparison_of_overlapping_ranges() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return (arg & 3) < (arg & 7);").unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get_node(result).unwrap();
+        assert!(matches!(parser.graph.get_node(node.inputs[1]).unwrap().typ(), Typ::BoolBot));
+    }
+
+    #[test]
+    fn should_push_negation_into_conjunction_of_negation() {
+        // Arrange
+        let mut parser = Parser::new_noarg("bool a = arg < 1;\nbool b = arg == 2;\nreturn !(!a && b);").unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get_node(result).unwrap();
+        assert_eq!("return arg < 1 || !(arg == 2);", format!("{:}", BoundNode::new(node, &parser.graph)));
+    }
+
+    #[test]
+    fn should_fold_division_of_nonzero_by_itself() {
+        // Arrange
+        let mut parser = Parser::new_noarg("int a = 5;\nreturn a / a;").unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get_node(result).unwrap();
+        assert_eq!("return 1;", format!("{:}", BoundNode::new(node, &parser.graph)));
+    }
+
+    #[test]
+    fn should_fold_division_of_nonzero_range_by_itself() {
+        // Arrange
+        let mut parser = Parser::new_noarg("int a = (arg & 3) + 1;\nreturn a / a;").unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get_node(result).unwrap();
+        assert_eq!("return 1;", format!("{:}", BoundNode::new(node, &parser.graph)));
+    }
+
+    #[test]
+    fn should_keep_division_of_range_with_zero_by_itself() {
+        // Arrange
+        let mut parser = Parser::new_noarg("int a = arg & 3;\nreturn a / a;").unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get_node(result).unwrap();
+        assert_eq!("return (arg & 3)/(arg & 3);", format!("{:}", BoundNode::new(node, &parser.graph)));
+    }
+
+    #[test]
+    fn should_keep_division_of_unknown_by_itself() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return arg / arg;").unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get_node(result).unwrap();
+        assert_eq!("return arg/arg;", format!("{:}", BoundNode::new(node, &parser.graph)));
+    }
+
+    #[test]
+    fn should_fold_bitwise_and_with_zero() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return arg & 0;").unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get_node(result).unwrap();
+        assert_eq!("return 0;", format!("{:}", BoundNode::new(node, &parser.graph)));
+    }
+
+    #[test]
+    fn should_fold_bitwise_or_with_zero() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return arg | 0;").unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get_node(result).unwrap();
+        assert_eq!("return arg;", format!("{:}", BoundNode::new(node, &parser.graph)));
+    }
+
+    #[test]
+    fn should_fold_bitwise_operations_with_all_bits_set() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return (arg & -1) ^ (0 | arg + 1) | -1;").unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get_node(result).unwrap();
+        assert_eq!("return -1;", format!("{:}", BoundNode::new(node, &parser.graph)));
+    }
+
+    #[test]
+    fn should_share_node_of_equal_constants() {
+        // Arrange
+        let mut parser = ParserBuilder::new().do_optimize(false).build_noarg("return 1+1;").unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let add = parser.graph.get_node(parser.graph.get_node(result).unwrap().inputs[1]).unwrap();
+        assert_eq!(add.inputs[0], add.inputs[1]);
+        assert_eq!(2, parser.graph.get_node(add.inputs[0]).unwrap().outputs.len());
+    }
+
+    #[test]
+    fn should_read_const_variable() {
+        // Arrange
+        let mut parser = Parser::new_noarg("const int x = 1;\nreturn x + arg;").unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get_node(result).unwrap();
+        assert_eq!("return arg+1;", format!("{:}", BoundNode::new(node, &parser.graph)));
+    }
+
+    #[test]
+    fn should_reject_assignment_to_const_variable() {
+        // Arrange
+        let mut parser = Parser::new_noarg("const int x = 1;\nx = 2;\nreturn x;").unwrap();
+
+        // Act
+        let result = parser.parse();
+
+        // Assert
+        match result {
+            Err(ErrorWithContext { error: SoNError::AssignToConst { variable }, line, col, .. }) => {
+                assert_eq!("x", variable);
+                assert_eq!((2, 1), (line, col));
+            }
+            _ => panic!("expected an assignment to a const, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn should_fold_saturating_add_at_the_upper_bound() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return 9223372036854775807 +| 1;").unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get_node(result).unwrap();
+        assert_eq!("return 9223372036854775807;", format!("{:}", BoundNode::new(node, &parser.graph)));
+    }
+
+    #[test]
+    fn should_fold_saturating_operations_at_the_bounds() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return (-9223372036854775807 -| 2) + (4611686018427387904 *| 2);").unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get_node(result).unwrap();
+        assert_eq!("return -1;", format!("{:}", BoundNode::new(node, &parser.graph)));
+    }
+
+    #[test]
+    fn should_wrap_default_add() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return 9223372036854775807 + 1;").unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get_node(result).unwrap();
+        assert_eq!("return -9223372036854775808;", format!("{:}", BoundNode::new(node, &parser.graph)));
+    }
+
+    #[test]
+    fn should_wrap_default_mul() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return 4611686018427387904 * 2;").unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get_node(result).unwrap();
+        assert_eq!("return -9223372036854775808;", format!("{:}", BoundNode::new(node, &parser.graph)));
+    }
+
+    #[test]
+    fn should_wrap_negation_and_division_of_smallest_int() {
+        // Arrange
+        let mut parser = Parser::new_noarg("int min = -9223372036854775807 - 1; return -min + min / -1;").unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get_node(result).unwrap();
+        assert_eq!("return 0;", format!("{:}", BoundNode::new(node, &parser.graph)));
+    }
+
+    #[test]
+    fn should_keep_saturating_add_of_unknown() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return (arg +| 0) +| 1;").unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get_node(result).unwrap();
+        assert_eq!("return arg+|1;", format!("{:}", BoundNode::new(node, &parser.graph)));
+    }
+
+    #[test]
+    fn should_peephole_computed_types() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return 1+2*3+-5;").unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get_node(result).unwrap();
+        assert_eq!("return 2;", format!("{:}", BoundNode::new(node, &parser.graph)));
+    }
+
+    #[test]
+    fn should_define_var() {
+        // Arrange
+        let mut parser = Parser::new_noarg("").unwrap();
+        parser.push_scope().unwrap();
+        let nid = parser.add_node_unrefined(vec![], NodeKind::Constant).unwrap();
+
+        // Act
+        parser.define_var("x", nid, Typ::IntBot).unwrap();
+
+        // Assert
+        assert!(matches!(parser.graph.get_node(nid).unwrap().outputs.as_slice(), [a] if a == &SCOPE_NID));
+        assert!(matches!(parser.graph.get_node(SCOPE_NID).unwrap().inputs.as_slice(), [a] if a == &nid));
+        if let NodeKind::Scope { scopes, .. } = &parser.graph.get_node(SCOPE_NID).unwrap().node_kind
+            && let [ map ] = scopes.as_slice() && let Some(a) = map.get("x") && a == &nid {
+                return;
+            }
+        panic!();
+    }
+
+    #[test]
+    fn should_define_var_in_program() {
+        // Arrange
+        let mut parser = Parser::new_noarg("int a=1; return a;").unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get(result).unwrap().as_ref().unwrap();
+        assert_eq!("return 1;", format!("{:}", BoundNode::new(node, &parser.graph)));
+    }
+
+    #[test]
+    fn should_restore_shadowed_variable_after_block() {
+        // Arrange
+        let mut parser = Parser::new_noarg("int x = 1;\n{ int x = 2; }\nreturn x;").unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get_node(result).unwrap();
+        assert_eq!("return 1;", format!("{:}", BoundNode::new(node, &parser.graph)));
+        assert!(matches!(parser.evaluate_i64(&[0]), Ok(1)));
+    }
+
+    #[test]
+    fn should_keep_outer_value_bound_in_shadowing_block() {
+        // Arrange
+        let mut parser = Parser::new_noarg("int x = 1;\n{ int x = 1; int y = x; }\nreturn x;").unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get_node(result).unwrap();
+        assert_eq!("return 1;", format!("{:}", BoundNode::new(node, &parser.graph)));
+    }
+
+    #[test]
+    fn should_assign_variable_of_outer_scope() {
+        // Arrange
+        let mut parser = Parser::new_noarg("int x = 1;\n{ x = arg; }\nreturn x;").unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get_node(result).unwrap();
+        assert_eq!("return arg;", format!("{:}", BoundNode::new(node, &parser.graph)));
+    }
+
+    #[test]
+    fn should_return_error_on_redefinition_in_same_block() {
+        // Arrange
+        let mut parser = Parser::new_noarg("int a=1;{int a=2;int a=3;}return a;").unwrap();
+
+        // Act
+        let result = parser.parse();
+
+        // Assert
+        assert!(matches!(result, Err(ErrorWithContext{error: SoNError::VariableRedefinition { variable: v },..}) if v == "a"));
+    }
+
+    #[test]
+    fn should_return_error_on_variable_redefinition() {
+        // Arrange
+        let mut parser = Parser::new_noarg("int a=1;int a=1;").unwrap();
+
+        // Act
+        let result = parser.parse();
+
+        // Assert
+        assert!(matches!(result, Err(ErrorWithContext{error: SoNError::VariableRedefinition { variable: v },..}) if v == "a"));
+    }
+
+    #[test]
+    fn should_reassign_int_variable_with_int() {
+        // Arrange
+        let mut parser = Parser::new_noarg("int x=1; x=2; return x;").unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get_node(result).unwrap();
+        assert_eq!("return 2;", format!("{:}", BoundNode::new(node, &parser.graph)));
+    }
+
+    #[test]
+    fn should_reassign_int_variable_with_unknown_int() {
+        // Arrange
+        let mut parser = Parser::new_noarg("int x=1; x=arg*arg; return x;").unwrap();
+
+        // Act
+        let result = parser.parse();
+
+        // Assert
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn should_return_error_on_reassignment_with_other_typ() {
+        // Arrange
+        let mut parser = Parser::new_noarg("int x=1; x = 1<2;").unwrap();
+
+        // Act
+        let result = parser.parse();
+
+        // Assert
+        assert!(matches!(result, Err(ErrorWithContext{error: SoNError::TypeMismatch { variable: Some(v), .. },..}) if v == "x"));
+    }
+
+    #[test]
+    fn should_return_error_on_declaration_with_other_typ() {
+        // Arrange
+        let mut parser = Parser::new_noarg("bool b = 1+2;").unwrap();
+
+        // Act
+        let result = parser.parse();
+
+        // Assert
+        assert!(matches!(result, Err(ErrorWithContext{error: SoNError::TypeMismatch { variable: Some(v), .. },..}) if v == "b"));
+    }
+
+    #[test]
+    fn should_return_error_on_undefined_variable() {
+        // Arrange
+        let mut parser = Parser::new_noarg("a=1;").unwrap();
+
+        // Act
+        let result = parser.parse();
+
+        // Assert
+        assert!(matches!(result, Err(ErrorWithContext{error: SoNError::VariableUndefined { variable: v, .. },..}) if v == "a"));
+    }
+
+    #[test]
+    fn should_have_ctrl_and_arg_defined() {
+        // Arrange
+        let mut parser = Parser::new("return arg;", 84).unwrap();
+        parser.do_optimize = false;
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get_node(result).unwrap();
+        let ctrl = parser.graph.get_node(*node.inputs.first().unwrap()).unwrap().clone();
+        let arg = parser.graph.get_node(*node.inputs.get(1).unwrap()).unwrap().clone();
+
+        assert!(matches!(ctrl.node_kind, NodeKind::Proj {..}));
+        assert!(matches!(arg.node_kind, NodeKind::Proj {..}));
+        assert!(matches!(ctrl.typ(), Typ::Ctrl));
+        assert!(matches!(arg.typ(), Typ::Int { constant: 84 }));
+    }
+
+    #[test]
+    fn should_fold_cast_of_bool_to_int() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return int(1<2);").unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get_node(result).unwrap();
+        assert_eq!("return 1;", format!("{:}", BoundNode::new(node, &parser.graph)));
+    }
+
+    #[test]
+    fn should_fold_cast_of_int_to_bool() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return bool(0);").unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get_node(result).unwrap();
+        assert_eq!("return false;", format!("{:}", BoundNode::new(node, &parser.graph)));
+    }
+
+    #[test]
+    fn should_fold_prefix_cast_of_true_to_int() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return (int)true;").unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get_node(result).unwrap();
+        assert_eq!("return 1;", format!("{:}", BoundNode::new(node, &parser.graph)));
+    }
+
+    #[test]
+    fn should_fold_prefix_cast_of_zero_to_bool() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return (bool)0;").unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get_node(result).unwrap();
+        assert_eq!("return false;", format!("{:}", BoundNode::new(node, &parser.graph)));
+    }
+
+    #[test]
+    fn should_bind_prefix_cast_tighter_than_binary_operators() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return (int)false + (arg);").unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get_node(result).unwrap();
+        assert_eq!("return arg;", format!("{:}", BoundNode::new(node, &parser.graph)));
+    }
+
+    #[test]
+    fn should_merge_if_expression_with_phi() {
+        // Arrange
+        let mut parser = Parser::new_noarg("int x = if(arg) 1 else 2; return x;").unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get_node(result).unwrap();
+        assert_eq!("return if (arg) 1 else 2;", format!("{:}", BoundNode::new(node, &parser.graph)));
+    }
+
+    #[test]
+    fn should_render_diamond_in_graph_form() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return if(arg < 1) arg + 1 else 2;").unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get_node(result).unwrap();
+        assert_eq!("return phi(Region(True, False); arg+1, 2);", format!("{:}", BoundNode::new(node, &parser.graph).graph_form()));
+        let iff = parser.graph.graph_iter().find(|n| matches!(n.node_kind, NodeKind::If)).unwrap();
+        assert_eq!("if(arg < 1)", format!("{:}", BoundNode::new(iff, &parser.graph).graph_form()));
+    }
+
+    #[test]
+    fn should_fold_if_expression_with_constant_condition() {
+        // Arrange
+        let mut parser = Parser::new("int x = if(arg) 1 else 2; return x;", 0).unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get_node(result).unwrap();
+        assert_eq!("return 2;", format!("{:}", BoundNode::new(node, &parser.graph)));
+    }
+
+    #[test]
+    fn should_collapse_phi_of_identical_inputs() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return if(arg < 1) arg else arg;").unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get_node(result).unwrap();
+        assert_eq!("return arg;", format!("{:}", BoundNode::new(node, &parser.graph)));
+        assert!(!parser.nodes().any(|n| matches!(n.node_kind, NodeKind::Phi)));
+    }
+
+    #[test]
+    fn should_select_phi_input_of_constant_predicate() {
+        // Arrange
+        let mut parser = ParserBuilder::new().do_optimize(false).build_noarg("return if(1) arg else 2;").unwrap();
+        let result = parser.parse().unwrap();
+        let ret = parser.graph.get_node(result).unwrap();
+        let phi = parser.graph.get_node(ret.inputs[1]).unwrap();
+        assert!(matches!(phi.node_kind, NodeKind::Phi));
+        let (phi, then) = (phi.nid, phi.inputs[1]);
+
+        // Act
+        let selected = parser.idealize_node(phi).unwrap();
+
+        // Assert
+        assert_eq!(then, selected);
+    }
+
+    #[test]
+    fn should_print_nested_if_expressions_as_nested_phis() {
+        // Arrange
+        let mut parser = Parser::new_noarg("int x = if(arg < 1) if(arg) 1 else 2 else 3; return x;").unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get_node(result).unwrap();
+        assert_eq!("return if (arg < 1) if (arg) 1 else 2 else 3;", format!("{:}", BoundNode::new(node, &parser.graph)));
+    }
+
+    #[test]
+    fn should_drop_untaken_branch_of_constant_if() {
+        // Arrange
+        let mut parser = Parser::new_noarg("int a = if(true) 1 else 2;\nreturn a;").unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get_node(result).unwrap();
+        assert_eq!("return 1;", format!("{:}", BoundNode::new(node, &parser.graph)));
+        assert!(matches!(parser.graph.get_node(node.inputs[0]).unwrap().node_kind, NodeKind::Proj { proj_index: 0, .. }));
+        assert!(!parser.nodes().any(|n| matches!(n.node_kind, NodeKind::If | NodeKind::Region | NodeKind::Phi)));
+    }
+
+    #[test]
+    fn should_connect_taken_branch_to_control_before_constant_if() {
+        // Arrange
+        let mut parser = Parser::new_noarg("int f(int x) { return x; }\nint a = if(0) f(1) else f(2);\nreturn a;").unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get_node(result).unwrap();
+        assert_eq!("return f(2);", format!("{:}", BoundNode::new(node, &parser.graph)));
+        let call = parser.graph.get_node(node.inputs[0]).unwrap();
+        assert!(matches!(parser.graph.get_node(call.inputs[0]).unwrap().node_kind, NodeKind::Proj { proj_index: 0, .. }));
+        assert!(!parser.nodes().any(|n| matches!(n.node_kind, NodeKind::If | NodeKind::Region | NodeKind::Phi)));
+        assert_eq!(1, parser.nodes().filter(|n| matches!(n.node_kind, NodeKind::Call { .. })).count());
+    }
+
+    #[test]
+    fn should_reject_if_expression_with_mismatched_branches() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return if(arg) 1 else arg < 2;").unwrap();
+
+        // Act
+        let result = parser.parse();
+
+        // Assert
+        assert!(matches!(result, Err(ErrorWithContext { error: SoNError::TypeMismatch { .. }, .. })));
+    }
+
+    #[test]
+    fn should_merge_memory_of_if_expression_branches() {
+        // Arrange
+        let mut parser = Parser::new_noarg("int f(int x) { print(x); return x; }\nint y = if(arg) f(1) else 2;\nprint(y);\nreturn y;").unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get_node(result).unwrap();
+        let print = parser.graph.get_node(node.inputs[0]).unwrap();
+        assert_eq!(NodeKind::Print, print.node_kind);
+        assert_eq!(NodeKind::Phi, parser.graph.get_node(print.inputs[1]).unwrap().node_kind);
+    }
+
+    #[test]
+    fn should_keep_cast_of_unknown_int() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return bool(arg);").unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get_node(result).unwrap();
+        assert_eq!("return bool(arg);", format!("{:}", BoundNode::new(node, &parser.graph)));
+    }
+
+    #[test]
+    fn should_print_not_of_comparison_with_parens() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return !(arg < 1);").unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get_node(result).unwrap();
+        assert_eq!("return !(arg < 1);", format!("{:}", BoundNode::new(node, &parser.graph)));
+    }
+
+    #[test]
+    fn should_print_not_of_variable_without_parens() {
+        // Arrange
+        let mut parser = Parser::new_noarg("return !arg;").unwrap();
+
+        // Act
+        let result = parser.parse().unwrap();
+
+        // Assert
+        let node = parser.graph.get_node(result).unwrap();
+        assert_eq!("return !arg;", format!("{:}", BoundNode::new(node, &parser.graph)));
     }
 
     #[test]
@@ -884,7 +3495,7 @@ mod tests {
 
         // Assert
         let node = parser.graph.get_node(result).unwrap();
-        assert_eq!("return arg;", format!("{:}", BoundNode::new(&node, &parser.graph)));
+        assert_eq!("return arg;", format!("{:}", BoundNode::new(node, &parser.graph)));
     }
 
     #[test]
@@ -897,7 +3508,7 @@ mod tests {
 
         // Assert
         let node = parser.graph.get_node(result).unwrap();
-        assert_eq!("return (arg+(arg*arg));", format!("{:}", BoundNode::new(&node, &parser.graph)));
+        assert_eq!("return arg+arg*arg;", format!("{:}", BoundNode::new(node, &parser.graph)));
     }
 
     #[test]
@@ -910,7 +3521,7 @@ mod tests {
 
         // Assert
         let node = parser.graph.get_node(result).unwrap();
-        assert_eq!("return ((arg+(arg/123))+10);", format!("{:}", BoundNode::new(&node, &parser.graph)));
+        assert_eq!("return arg+arg/123+10;", format!("{:}", BoundNode::new(node, &parser.graph)));
     }
 
     #[test]
@@ -923,6 +3534,6 @@ mod tests {
 
         // Assert
         let node = parser.graph.get_node(result).unwrap();
-        assert_eq!("return ((arg+(arg/123))+(arg*2));", format!("{:}", BoundNode::new(&node, &parser.graph)));
+        assert_eq!("return arg/123+arg*3;", format!("{:}", BoundNode::new(node, &parser.graph)));
     }
 }
@@ -0,0 +1,124 @@
+use crate::ast::stmt::Stmt;
+use crate::errors::son_error::SoNError;
+use crate::services::parser::Parser;
+
+/// The graph right after one top-level statement was lowered and optimized.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GraphSnapshot {
+    pub dot: String,
+    pub ir: String,
+    pub live_nodes: usize,
+}
+
+enum State {
+    NotStarted,
+    Lowering { stmts: std::vec::IntoIter<Stmt>, node: usize },
+    Done,
+}
+
+/// Lowers one top-level statement per call to next. See [Parser::parse_stepwise].
+pub struct StepwiseParse<'a> {
+    parser: &'a mut Parser,
+    state: State,
+}
+
+impl Parser {
+    /// Yields a snapshot after each top-level statement is lowered and optimized. Consuming the
+    /// whole iterator leaves the same graph as [Parser::parse]. The iterator ends after the first error.
+    pub fn parse_stepwise(&mut self) -> impl Iterator<Item=Result<GraphSnapshot, SoNError>> {
+        StepwiseParse { parser: self, state: State::NotStarted }
+    }
+
+    fn snapshot(&self) -> GraphSnapshot {
+        GraphSnapshot { dot: self.as_dotfile(), ir: self.dump_ir(), live_nodes: self.graph.live_count() }
+    }
+}
+
+impl StepwiseParse<'_> {
+    fn start(&mut self) -> Result<(), SoNError> {
+        let stmts = self.parser.parse_ast()?;
+        self.parser.begin_lowering()?;
+        // the block lower_block would open around the statements
+        self.parser.push_scope()?;
+        self.state = State::Lowering { stmts: stmts.into_iter(), node: self.parser.ctrl() };
+        Ok(())
+    }
+
+    fn step(&mut self) -> Result<Option<GraphSnapshot>, SoNError> {
+        if let State::NotStarted = self.state {
+            self.start()?;
+        }
+        let State::Lowering { stmts, node } = &mut self.state else {
+            return Ok(None);
+        };
+        if let Some(stmt) = stmts.next() {
+            *node = self.parser.lower_block_statement(&stmt, *node)?;
+            return Ok(Some(self.parser.snapshot()));
+        }
+        let node = *node;
+        self.state = State::Done;
+        self.parser.pop_scope()?;
+        self.parser.end_lowering()?;
+        self.parser.drop_all_unused_nodes(node)?;
+        Ok(None)
+    }
+}
+
+impl Iterator for StepwiseParse<'_> {
+    type Item = Result<GraphSnapshot, SoNError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let result = self.step();
+        if result.is_err() {
+            self.state = State::Done;
+        }
+        result.transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::errors::son_error::SoNError;
+    use crate::services::parser::Parser;
+
+    #[test]
+    fn should_snapshot_each_top_level_statement() {
+        // Arrange
+        let mut parser = Parser::new_noarg("int a=arg+1; int b=a*2; return b-a;").unwrap();
+
+        // Act
+        let snapshots = parser.parse_stepwise().collect::<Result<Vec<_>, _>>().unwrap();
+
+        // Assert
+        assert_eq!(3, snapshots.len());
+        assert!(snapshots.windows(2).all(|w| w[0].live_nodes <= w[1].live_nodes));
+    }
+
+    #[test]
+    fn should_build_same_graph_as_parse() {
+        // Arrange
+        let src = "int a=arg+1; { int c=a*3; a=c; } #showGraph; return if(a<2) a else 2;";
+        let mut stepwise = Parser::new_noarg(src).unwrap();
+        let mut parser = Parser::new_noarg(src).unwrap();
+
+        // Act
+        assert_eq!(4, stepwise.parse_stepwise().map(Result::unwrap).count());
+        parser.parse().unwrap();
+
+        // Assert
+        assert_eq!(parser.dump_ir(), stepwise.dump_ir());
+    }
+
+    #[test]
+    fn should_stop_after_first_error() {
+        // Arrange
+        let mut parser = Parser::new_noarg("int a=1; a=b; return a;").unwrap();
+
+        // Act
+        let steps = parser.parse_stepwise().collect::<Vec<_>>();
+
+        // Assert
+        assert_eq!(2, steps.len());
+        assert!(matches!(steps[1], Err(SoNError::VariableUndefined { .. })));
+    }
+}
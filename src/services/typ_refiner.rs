@@ -1,56 +1,131 @@
 use crate::errors::son_error::SoNError;
 use crate::nodes::node::{CompNodeKind, Graph, Node, NodeKind};
 use crate::typ::typ::Typ;
+use std::collections::HashMap;
 
 impl Graph {
-    pub fn compute_refined_typ(&self, node: &Node) -> Result<Typ, SoNError> {
-        match &node.node_kind {
-            NodeKind::Add => {
-                let lhs = self.get_node(*node.inputs.get(0).unwrap())?;
-                let rhs = self.get_node(*node.inputs.get(1).unwrap())?;
+    /// folds two int or two float constants. Mixing ints and floats is a TypeMismatch.
+    /// `fold_int` returns None when the constants can't be folded, which only happens for a division by zero.
+    fn refine_arithmetic(&self, node: &Node, fold_int: fn(i64, i64) -> Option<i64>, fold_float: fn(f64, f64) -> f64) -> Result<Typ, SoNError> {
+        self.refine_arithmetic_or(node, fold_int, fold_float, SoNError::DivisionByZero)
+    }
 
-                if let Typ::Int { constant: clhs } = lhs.typ() && let Typ::Int { constant: crhs } = rhs.typ() {
-                    return Ok(Typ::Int { constant: clhs + crhs }); // T_CONSTFLD
-                }
-                Ok(node.typ())
-            }
-            NodeKind::Sub => {
-                let lhs = self.get_node(*node.inputs.get(0).unwrap())?;
-                let rhs = self.get_node(*node.inputs.get(1).unwrap())?;
+    /// like [Graph::refine_arithmetic], but fails with `error` when `fold_int` returns None
+    fn refine_arithmetic_or(&self, node: &Node, fold_int: fn(i64, i64) -> Option<i64>, fold_float: fn(f64, f64) -> f64, error: SoNError) -> Result<Typ, SoNError> {
+        let lhs = self.get_node(*node.inputs.first().unwrap())?.typ();
+        let rhs = self.get_node(*node.inputs.get(1).unwrap())?.typ();
 
-                if let Typ::Int { constant: clhs } = lhs.typ() && let Typ::Int { constant: crhs } = rhs.typ() {
-                    return Ok(Typ::Int { constant: clhs - crhs }); // T_CONSTFLD
-                }
-                Ok(node.typ())
+        match (&lhs, &rhs) {
+            (Typ::Int { constant: clhs }, Typ::Int { constant: crhs }) => fold_int(*clhs, *crhs).map(|constant| Typ::Int { constant }).ok_or(error), // T_CONSTFLD
+            (Typ::Float { constant: clhs }, Typ::Float { constant: crhs }) => Ok(Typ::Float { constant: fold_float(*clhs, *crhs) }), // T_CONSTFLD
+            _ if lhs.is_int() && rhs.is_float() || lhs.is_float() && rhs.is_int() => {
+                Err(SoNError::TypeMismatch { variable: None, expected: format!("{:?}", lhs), but_got: format!("{:?}", rhs) })
             }
-            NodeKind::Mul => {
-                let lhs = self.get_node(*node.inputs.get(0).unwrap())?;
-                let rhs = self.get_node(*node.inputs.get(1).unwrap())?;
+            _ if lhs.is_float() || rhs.is_float() => Ok(Typ::FloatBot),
+            _ => Ok(Typ::IntBot),
+        }
+    }
 
-                if let Typ::Int { constant: clhs } = lhs.typ() && let Typ::Int { constant: crhs } = rhs.typ() {
-                    return Ok(Typ::Int { constant: clhs * crhs }); // T_CONSTFLD
-                }
-                Ok(node.typ())
-            }
-            NodeKind::Div => {
-                let lhs = self.get_node(*node.inputs.get(0).unwrap())?;
-                let rhs = self.get_node(*node.inputs.get(1).unwrap())?;
+    /// The smallest and largest int value the node can have, derived from its inputs. None, if it may be any int.
+    /// There is no range typ, so this recomputes the bounds of constants, masks with `&`, `abs`, `min`, `max`, sums and differences.
+    pub(crate) fn int_bounds(&self, node: &Node) -> Result<Option<(i64, i64)>, SoNError> {
+        self.int_bounds_memo(node, &mut HashMap::new())
+    }
 
-                if let Typ::Int { constant: clhs } = lhs.typ() && let Typ::Int { constant: crhs } = rhs.typ() {
-                    return Ok(Typ::Int { constant: clhs / crhs }); // T_CONSTFLD
+    /// [Graph::int_bounds] that visits each node once. Shared subexpressions would otherwise be recomputed per use.
+    fn int_bounds_memo(&self, node: &Node, memo: &mut HashMap<usize, Option<(i64, i64)>>) -> Result<Option<(i64, i64)>, SoNError> {
+        if let Some(&bounds) = memo.get(&node.nid) {
+            return Ok(bounds);
+        }
+        let bounds = match (&node.node_kind, node.typ()) {
+            (_, Typ::Int { constant }) => Some((constant, constant)),
+            (NodeKind::Cast { to: Typ::IntBot }, _) => {
+                let operand = self.get_node(node.inputs[0])?;
+                // a bool becomes 0 or 1, an int stays as it is
+                if operand.typ().is_bool() { Some((0, 1)) } else { self.int_bounds_memo(operand, memo)? }
+            }
+            (NodeKind::Comp { kind: CompNodeKind::LogAnd }, typ) if typ.is_int() => {
+                let lhs = self.int_bounds_memo(self.get_node(node.inputs[0])?, memo)?;
+                let rhs = self.int_bounds_memo(self.get_node(node.inputs[1])?, memo)?;
+                // a non-negative operand keeps only some of its bits
+                match (lhs, rhs) {
+                    (Some((0.., lmax)), Some((0.., rmax))) => Some((0, lmax.min(rmax))),
+                    (Some((0.., max)), _) | (_, Some((0.., max))) => Some((0, max)),
+                    _ => None,
                 }
-                Ok(node.typ())
             }
+            // abs of the smallest int is an error at runtime, which folding must not hide by claiming a range
+            (NodeKind::Abs, typ) if typ.is_int() => match self.int_bounds_memo(self.get_node(node.inputs[0])?, memo)? {
+                Some((min, max)) if min >= 0 => Some((min, max)),
+                Some((min, max)) if min > i64::MIN && max <= 0 => Some((-max, -min)),
+                Some((min, max)) if min > i64::MIN => Some((0, max.max(-min))),
+                _ => None,
+            },
+            (NodeKind::Min | NodeKind::Max, typ) if typ.is_int() => {
+                let lhs = self.int_bounds_memo(self.get_node(node.inputs[0])?, memo)?;
+                let rhs = self.int_bounds_memo(self.get_node(node.inputs[1])?, memo)?;
+                let pick = if matches!(node.node_kind, NodeKind::Min) { i64::min } else { i64::max };
+                lhs.zip(rhs).map(|((lmin, lmax), (rmin, rmax))| (pick(lmin, rmin), pick(lmax, rmax)))
+            }
+            (NodeKind::Add, typ) if typ.is_int() => {
+                let lhs = self.int_bounds_memo(self.get_node(node.inputs[0])?, memo)?;
+                let rhs = self.int_bounds_memo(self.get_node(node.inputs[1])?, memo)?;
+                // the sum wraps around if either bound overflows
+                lhs.zip(rhs).and_then(|((lmin, lmax), (rmin, rmax))| lmin.checked_add(rmin).zip(lmax.checked_add(rmax)))
+            }
+            (NodeKind::Sub, typ) if typ.is_int() => {
+                let lhs = self.int_bounds_memo(self.get_node(node.inputs[0])?, memo)?;
+                let rhs = self.int_bounds_memo(self.get_node(node.inputs[1])?, memo)?;
+                lhs.zip(rhs).and_then(|((lmin, lmax), (rmin, rmax))| lmin.checked_sub(rmax).zip(lmax.checked_sub(rmin)))
+            }
+            _ => None,
+        };
+        memo.insert(node.nid, bounds);
+        Ok(bounds)
+    }
+
+    pub fn compute_refined_typ(&self, node: &Node) -> Result<Typ, SoNError> {
+        let typ = self.fold_typ(node)?;
+        if typ.is_constant() && !matches!(node.node_kind, NodeKind::Constant) && self.disabled_folds.contains(&node.node_kind) {
+            // the kind of the result is still known, just not its value
+            return Ok(typ.bottom_of_kind());
+        }
+        Ok(typ)
+    }
+
+    fn fold_typ(&self, node: &Node) -> Result<Typ, SoNError> {
+        match &node.node_kind {
+            // the default int arithmetic wraps around like the evaluator does, AddSat and friends saturate instead
+            NodeKind::Add => self.refine_arithmetic(node, |a, b| Some(a.wrapping_add(b)), |a, b| a + b),
+            NodeKind::Sub => self.refine_arithmetic(node, |a, b| Some(a.wrapping_sub(b)), |a, b| a - b),
+            NodeKind::Mul => self.refine_arithmetic(node, |a, b| Some(a.wrapping_mul(b)), |a, b| a * b),
+            // float division follows IEEE, so x/0.0 is inf or NaN
+            NodeKind::Div => self.refine_arithmetic(node, |a, b| if b == 0 { None } else { Some(a.wrapping_div(b)) }, |a, b| a / b),
+            NodeKind::AddSat => self.refine_arithmetic(node, |a, b| Some(a.saturating_add(b)), |a, b| a + b),
+            NodeKind::SubSat => self.refine_arithmetic(node, |a, b| Some(a.saturating_sub(b)), |a, b| a - b),
+            NodeKind::MulSat => self.refine_arithmetic(node, |a, b| Some(a.saturating_mul(b)), |a, b| a * b),
+            NodeKind::Pow => self.refine_arithmetic_or(node, |a, b| u32::try_from(b).ok().and_then(|b| a.checked_pow(b)), f64::powf, SoNError::PowOutOfRange),
+            NodeKind::Min => self.refine_arithmetic(node, |a, b| Some(a.min(b)), f64::min),
+            NodeKind::Max => self.refine_arithmetic(node, |a, b| Some(a.max(b)), f64::max),
+            NodeKind::Abs => match self.get_node(node.inputs[0])?.typ() {
+                Typ::Int { constant } => constant.checked_abs().map(|constant| Typ::Int { constant }).ok_or(SoNError::IntegerOverflow), // T_CONSTFLD
+                Typ::Float { constant } => Ok(Typ::Float { constant: constant.abs() }), // T_CONSTFLD
+                typ if typ.is_float() => Ok(Typ::FloatBot),
+                typ if typ.is_int() => Ok(Typ::IntBot),
+                typ => Err(SoNError::TypeMismatch { variable: None, expected: "int or float".into(), but_got: format!("{:?}", typ) }),
+            },
             NodeKind::Minus => {
-                let lhs = self.get_node(*node.inputs.get(0).unwrap())?;
+                let lhs = self.get_node(*node.inputs.first().unwrap())?;
 
-                if let Typ::Int { constant: clhs } = lhs.typ() {
-                    return Ok(Typ::Int { constant: -clhs }); // T_CONSTFLD
+                match lhs.typ() {
+                    Typ::Int { constant } => Ok(Typ::Int { constant: constant.wrapping_neg() }), // T_CONSTFLD
+                    Typ::Float { constant } => Ok(Typ::Float { constant: -constant }), // T_CONSTFLD
+                    typ if typ.is_float() => Ok(Typ::FloatBot),
+                    _ => Ok(Typ::IntBot),
                 }
-                Ok(node.typ())
             }
             NodeKind::Proj { proj_index, .. } => {
-                let lhs = self.get_node(*node.inputs.get(0).unwrap())?;
+                let lhs = self.get_node(*node.inputs.first().unwrap())?;
 
                 if let Typ::Tuple { typs } = lhs.typ() {
                     return Ok(typs.get(*proj_index).unwrap().clone());
@@ -62,9 +137,24 @@ impl Graph {
             | NodeKind::Start
             | NodeKind::KeepAlive
             | NodeKind::Scope { .. }
+            | NodeKind::Load { .. }
+            | NodeKind::New
+            | NodeKind::Fun { .. }
+            | NodeKind::CallEnd
+            | NodeKind::NewArray
+            | NodeKind::Offset
             => Ok(node.typ()),
+            NodeKind::Call { .. } => Ok(Typ::Ctrl),
+            NodeKind::Print => Ok(Typ::Ctrl),
+            NodeKind::If => Ok(Typ::Tuple { typs: vec![Typ::Ctrl, Typ::Ctrl] }),
+            NodeKind::Region => Ok(Typ::Ctrl),
+            NodeKind::Phi => {
+                let then = self.get_node(node.inputs[1])?.typ();
+                Ok(then.meet(&self.get_node(node.inputs[2])?.typ()))
+            }
+            NodeKind::Store { .. } => Ok(Typ::Mem),
             NodeKind::Comp { kind } => {
-                let lhs = self.get_node(*node.inputs.get(0).unwrap())?;
+                let lhs = self.get_node(*node.inputs.first().unwrap())?;
                 let rhs = self.get_node(*node.inputs.get(1).unwrap())?;
                 match kind {
                     CompNodeKind::LT => {
@@ -110,10 +200,28 @@ impl Graph {
                         }
                     }
                 }
-                Ok(node.typ())
+                let ordered = matches!(kind, CompNodeKind::LT | CompNodeKind::LEQ | CompNodeKind::EQ);
+                let mut memo = HashMap::new();
+                if ordered && let Some((lmin, lmax)) = self.int_bounds_memo(lhs, &mut memo)? && let Some((rmin, rmax)) = self.int_bounds_memo(rhs, &mut memo)? {
+                    let folded = match kind {
+                        CompNodeKind::LT if lmax < rmin => Some(true),
+                        CompNodeKind::LT if lmin >= rmax => Some(false),
+                        CompNodeKind::LEQ if lmax <= rmin => Some(true),
+                        CompNodeKind::LEQ if lmin > rmax => Some(false),
+                        CompNodeKind::EQ if lmax < rmin || rmax < lmin => Some(false),
+                        _ => None,
+                    };
+                    if let Some(constant) = folded {
+                        return Ok(Typ::Bool { constant }); // T_CONSTFLD over the bounds
+                    }
+                }
+                match kind {
+                    CompNodeKind::LT | CompNodeKind::LEQ | CompNodeKind::EQ => Ok(Typ::BoolBot),
+                    CompNodeKind::LogAnd | CompNodeKind::LogOr | CompNodeKind::LogXor => Ok(lhs.typ().meet(&rhs.typ())),
+                }
             }
             NodeKind::Not => {
-                let lhs = self.get_node(*node.inputs.get(0).unwrap())?;
+                let lhs = self.get_node(*node.inputs.first().unwrap())?;
 
                 if let Typ::Int { constant: clhs } = lhs.typ() {
                     return Ok(Typ::Int { constant: !clhs }); // T_CONSTFLD
@@ -123,7 +231,18 @@ impl Graph {
                     return Ok(Typ::Bool { constant: !clhs }); // T_CONSTFLD
                 }
 
-                Ok(node.typ())
+                Ok(lhs.typ())
+            }
+            NodeKind::Cast { to } => {
+                let lhs = self.get_node(*node.inputs.first().unwrap())?;
+
+                match (to, lhs.typ()) {
+                    (Typ::IntBot, Typ::Bool { constant }) => Ok(Typ::Int { constant: constant as i64 }), // T_CONSTFLD
+                    (Typ::BoolBot, Typ::Int { constant }) => Ok(Typ::Bool { constant: constant != 0 }), // T_CONSTFLD
+                    (Typ::IntBot, Typ::Int { .. }) | (Typ::BoolBot, Typ::Bool { .. }) => Ok(lhs.typ()),
+                    (_, Typ::Int { .. } | Typ::IntTop | Typ::IntBot | Typ::Bool { .. } | Typ::BoolTop | Typ::BoolBot | Typ::Bot) => Ok(to.clone()),
+                    (_, typ) => Err(SoNError::TypeMismatch { variable: None, expected: "int or bool".into(), but_got: format!("{:?}", typ) }),
+                }
             }
         }
     }
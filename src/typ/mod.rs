@@ -1 +1,2 @@
+#[allow(clippy::module_inception)]
 pub mod typ;
\ No newline at end of file
@@ -1,7 +1,11 @@
 use std::cmp::PartialEq;
+use std::fmt::{Display, Formatter};
+use std::hash::{Hash, Hasher};
 use Typ::*;
 
-#[derive(Debug, Clone, PartialEq)]
+/// With the `serde` feature, typs can be (de)serialized. Note that JSON can't represent NaN or infinite floats.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Typ {
     /// ALL: We have to honor what the programmer wrote and emit the code.
     Bot,
@@ -16,24 +20,154 @@ pub enum Typ {
     TupleTop,
     TupleBot,
     Ctrl,
+    /// the state of memory, threaded through every node that reads or writes it
+    Mem,
     Bool { constant: bool },
     BoolTop,
     BoolBot,
+    /// Concrete value floats. Constants are compared bitwise, so NaN equals itself and 0.0 differs from -0.0.
+    Float { constant: f64 },
+    FloatTop,
+    FloatBot,
+    /// A pointer to an instance of the struct `name`. Fields are int or bool.
+    Struct { name: String, fields: Vec<(String, Typ)> },
+    /// A pointer to an array of int or bool elements. `len` is only known for arrays allocated with a constant length.
+    Array { elem: Box<Typ>, len: Option<usize> },
+}
+
+impl PartialEq for Typ {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Int { constant }, Int { constant: o_constant }) => constant == o_constant,
+            (Bool { constant }, Bool { constant: o_constant }) => constant == o_constant,
+            (Float { constant }, Float { constant: o_constant }) => constant.to_bits() == o_constant.to_bits(),
+            (Tuple { typs }, Tuple { typs: o_typs }) => typs == o_typs,
+            (Struct { name, fields }, Struct { name: o_name, fields: o_fields }) => name == o_name && fields == o_fields,
+            (Array { elem, len }, Array { elem: o_elem, len: o_len }) => elem == o_elem && len == o_len,
+            _ => std::mem::discriminant(self) == std::mem::discriminant(other),
+        }
+    }
+}
+
+impl Eq for Typ {}
+
+/// consistent with [PartialEq], so floats are hashed by their bits
+impl Hash for Typ {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            Int { constant } => constant.hash(state),
+            Bool { constant } => constant.hash(state),
+            Float { constant } => constant.to_bits().hash(state),
+            Tuple { typs } => typs.hash(state),
+            Struct { name, fields } => (name, fields).hash(state),
+            Array { elem, len } => (elem, len).hash(state),
+            _ => {}
+        }
+    }
+}
+
+/// refinements after which [Typ::widen] gives up on a typ that is still changing
+pub const WIDENING_ITERATIONS: usize = 2;
+
+/// Constants are written like `int(5)`, the top and bottom of a kind of typ like `int⊤` and `int⊥`.
+impl Display for Typ {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Bot => write!(f, "⊥"),
+            Top => write!(f, "⊤"),
+            Int { constant } => write!(f, "int({})", constant),
+            IntTop => write!(f, "int⊤"),
+            IntBot => write!(f, "int⊥"),
+            Tuple { typs } => write!(f, "[{}]", typs.iter().map(|typ| typ.to_string()).collect::<Vec<_>>().join(", ")),
+            TupleTop => write!(f, "tuple⊤"),
+            TupleBot => write!(f, "tuple⊥"),
+            Ctrl => write!(f, "ctrl"),
+            Mem => write!(f, "mem"),
+            Bool { constant } => write!(f, "bool({})", constant),
+            BoolTop => write!(f, "bool⊤"),
+            BoolBot => write!(f, "bool⊥"),
+            Float { constant } => write!(f, "float({:?})", constant),
+            FloatTop => write!(f, "float⊤"),
+            FloatBot => write!(f, "float⊥"),
+            Struct { name, .. } => write!(f, "*{}", name),
+            Array { elem, len: Some(len) } => write!(f, "{}[{}]", elem, len),
+            Array { elem, len: None } => write!(f, "{}[]", elem),
+        }
+    }
 }
 
 impl Typ {
     /// Simple types are implemented fully here.  "Simple" means: the code and
     /// type hierarchy are simple, not that the Type is conceptually simple.
     pub fn is_simple(&self) -> bool {
-        matches!(self, Bot | Top | Ctrl)
+        matches!(self, Bot | Top | Ctrl | Mem)
     }
 
     pub fn is_constant(&self) -> bool {
-        matches!(self, Top | Int { .. })
+        matches!(self, Top | Int { .. } | Bool { .. } | Float { .. })
+    }
+
+    pub fn is_int(&self) -> bool {
+        matches!(self, Int { .. } | IntTop | IntBot)
+    }
+
+    pub fn is_bool(&self) -> bool {
+        matches!(self, Bool { .. } | BoolTop | BoolBot)
+    }
+
+    /// whether a constant condition holds. Ints hold unless they are 0. None, if the typ isn't constant.
+    pub fn constant_condition(&self) -> Option<bool> {
+        match self {
+            Int { constant } => Some(*constant != 0),
+            Bool { constant } => Some(*constant),
+            _ => None,
+        }
+    }
+
+    pub fn is_float(&self) -> bool {
+        matches!(self, Float { .. } | FloatTop | FloatBot)
+    }
+
+    /// The partial order of the lattice: whether self is at least as precise as other, so meeting them gives other.
+    /// Top is below everything and Bot above, e.g. `Int { constant: 5 }.le(&IntBot)`.
+    pub fn le(&self, other: &Typ) -> bool {
+        self.meet(other) == *other
+    }
+
+    /// whether this is the top of the whole lattice or of the ints, bools, floats or tuples
+    pub fn is_top(&self) -> bool {
+        matches!(self, Top | IntTop | BoolTop | FloatTop | TupleTop)
+    }
+
+    /// whether this is the bottom of the whole lattice or of the ints, bools, floats or tuples
+    pub fn is_bottom(&self) -> bool {
+        matches!(self, Bot | IntBot | BoolBot | FloatBot | TupleBot)
+    }
+
+    /// The typ of a loop-carried value for the next `iteration` of a fixpoint: the meet with the typ `next` computed
+    /// for it. Once more than [WIDENING_ITERATIONS] refinements still change the typ, it jumps to the bottom of its kind,
+    /// so the fixpoint terminates instead of going through every value.
+    pub fn widen(&self, next: &Typ, iteration: usize) -> Typ {
+        let meet = self.meet(next);
+        if iteration < WIDENING_ITERATIONS || meet == *self {
+            return meet;
+        }
+        meet.bottom_of_kind()
+    }
+
+    /// the bottom of the ints, bools or floats this belongs to, e.g. IntBot for `int(5)`. Other typs are returned as is.
+    pub fn bottom_of_kind(&self) -> Typ {
+        match self {
+            typ if typ.is_int() => IntBot,
+            typ if typ.is_bool() => BoolBot,
+            typ if typ.is_float() => FloatBot,
+            typ => typ.clone(),
+        }
     }
 
     pub fn transition_allowed(&self, other: &Typ) -> bool {
-        self.meet(&other) == *self
+        self.meet(other) == *self
     }
 
     pub fn join(&self, other: &Typ) -> Typ {
@@ -51,9 +185,14 @@ impl Typ {
             TupleTop => TupleBot,
             TupleBot => TupleTop,
             Ctrl => Ctrl,
+            Mem => Mem,
             Bool { .. } => self.clone(),
             BoolTop => BoolBot,
             BoolBot => BoolTop,
+            Float { .. } => self.clone(),
+            FloatTop => FloatBot,
+            FloatBot => FloatTop,
+            Struct { .. } | Array { .. } => self.clone(),
         }
     }
 
@@ -81,14 +220,29 @@ impl Typ {
                 Int { .. } | IntTop | IntBot => IntBot,
                 _ => Bot
             }
-            Tuple { .. } | TupleTop | TupleBot => {
-                if self == other {
-                    return self.clone();
-                }
-                panic!("not implemented yet")
+            Tuple { typs } => match other {
+                Top | TupleTop => self.clone(),
+                // tuples of different lengths only meet in TupleBot
+                Tuple { typs: o_typs } if typs.len() == o_typs.len() => Tuple { typs: typs.iter().zip(o_typs).map(|(typ, o_typ)| typ.meet(o_typ)).collect() },
+                Tuple { .. } | TupleBot => TupleBot,
+                _ => Bot,
+            },
+            TupleTop => match other {
+                Top => self.clone(),
+                Tuple { .. } | TupleTop | TupleBot => other.clone(),
+                _ => Bot
+            },
+            TupleBot => match other {
+                Top => self.clone(),
+                Tuple { .. } | TupleTop | TupleBot => TupleBot,
+                _ => Bot
             },
             Ctrl => match other {
-                Top => Ctrl,
+                Top | Ctrl => Ctrl,
+                _ => Bot
+            },
+            Mem => match other {
+                Top | Mem => Mem,
                 _ => Bot
             },
             Bool { constant } => match other {
@@ -111,13 +265,45 @@ impl Typ {
                 Bool { .. } | BoolTop | BoolBot => BoolBot,
                 _ => Bot
             }
+            Float { .. } => match other {
+                Float { .. } => if self == other {
+                    self.clone()
+                } else {
+                    FloatBot
+                },
+                FloatTop | Top => self.clone(),
+                FloatBot => FloatBot,
+                _ => Bot,
+            },
+            FloatTop => match other {
+                Top => self.clone(),
+                Float { .. } | FloatTop | FloatBot => other.clone(),
+                _ => Bot
+            }
+            FloatBot => match other {
+                Top => self.clone(),
+                Float { .. } | FloatTop | FloatBot => FloatBot,
+                _ => Bot
+            }
+            Struct { .. } => if self == other || *other == Top {
+                self.clone()
+            } else {
+                Bot
+            },
+            Array { elem, len } => match other {
+                Top => self.clone(),
+                // arrays of different lengths have the same typ if their length is unknown
+                Array { elem: o_elem, len: o_len } if elem == o_elem => Array { elem: elem.clone(), len: if len == o_len { *len } else { None } },
+                _ => Bot,
+            },
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::typ::typ::Typ::{Bot, Ctrl, Int, IntTop, Top, TupleTop};
+    use crate::typ::typ::{Typ, WIDENING_ITERATIONS};
+    use crate::typ::typ::Typ::{BoolBot, Bot, Ctrl, Float, FloatBot, FloatTop, Int, IntBot, IntTop, Top, TupleTop};
 
     #[test]
     fn should_meet_top_and_bot() {
@@ -164,6 +350,164 @@ mod tests {
         assert!(matches!(result, Top));
     }
 
+    /// a sample of every kind of typ, including two distinct constants of each kind
+    fn sample_typs() -> Vec<Typ> {
+        vec![
+            Bot, Top,
+            Int { constant: 0 }, Int { constant: 1 }, IntTop, IntBot,
+            Typ::Tuple { typs: vec![Ctrl, Typ::Mem] }, Typ::Tuple { typs: vec![Ctrl, IntBot] }, Typ::Tuple { typs: vec![Ctrl] }, TupleTop, Typ::TupleBot,
+            Ctrl, Typ::Mem,
+            Typ::Bool { constant: false }, Typ::Bool { constant: true }, Typ::BoolTop, BoolBot,
+            Float { constant: 0.5 }, Float { constant: f64::NAN }, FloatTop, FloatBot,
+            Typ::Struct { name: "P".into(), fields: vec![("x".into(), IntBot)] }, Typ::Struct { name: "Q".into(), fields: vec![] },
+            Typ::Array { elem: Box::new(IntBot), len: Some(2) }, Typ::Array { elem: Box::new(IntBot), len: None }, Typ::Array { elem: Box::new(BoolBot), len: None },
+        ]
+    }
+
+    #[test]
+    fn should_meet_commutatively() {
+        // Arrange & Act & Assert
+        for a in sample_typs() {
+            for b in sample_typs() {
+                assert_eq!(a.meet(&b), b.meet(&a), "{} meet {}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn should_meet_associatively() {
+        // Arrange & Act & Assert
+        for a in sample_typs() {
+            for b in sample_typs() {
+                for c in sample_typs() {
+                    assert_eq!(a.meet(&b).meet(&c), a.meet(&b.meet(&c)), "{} meet {} meet {}", a, b, c);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn should_meet_idempotently() {
+        // Arrange & Act & Assert
+        for a in sample_typs() {
+            assert_eq!(a, a.meet(&a), "{}", a);
+        }
+    }
+
+    #[test]
+    fn should_be_own_double_dual() {
+        // Arrange & Act & Assert
+        for a in sample_typs() {
+            assert_eq!(a, a.dual().dual(), "{}", a);
+        }
+    }
+
+    #[test]
+    fn should_join_as_dual_of_meet() {
+        // Arrange & Act & Assert
+        for a in sample_typs() {
+            for b in sample_typs() {
+                assert_eq!(a.join(&b), a.dual().meet(&b.dual()).dual(), "{} join {}", a, b);
+                assert_eq!(a.join(&b), b.join(&a), "{} join {}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn should_meet_to_a_bound_of_both() {
+        // Arrange & Act & Assert
+        for a in sample_typs() {
+            for b in sample_typs() {
+                let meet = a.meet(&b);
+                assert!(a.le(&meet) && b.le(&meet), "{} meet {} = {}", a, b, meet);
+            }
+        }
+    }
+
+    #[test]
+    fn should_widen_loop_counter_to_int_bot() {
+        // Arrange
+        let mut typ = Int { constant: 0 };
+        let mut iteration = 0;
+
+        // Act
+        loop {
+            // a counter `i = i + 1` computes the next typ from the current one
+            let next = match typ {
+                Int { constant } => Int { constant: constant + 1 },
+                _ => typ.clone(),
+            };
+            let widened = typ.widen(&next, iteration);
+            if widened == typ {
+                break;
+            }
+            typ = widened;
+            iteration += 1;
+        }
+
+        // Assert
+        assert_eq!(IntBot, typ);
+        assert!(iteration <= WIDENING_ITERATIONS + 1);
+    }
+
+    #[test]
+    fn should_not_widen_before_iteration_bound() {
+        // Arrange & Act
+        let result = Top.widen(&Int { constant: 3 }, WIDENING_ITERATIONS - 1);
+
+        // Assert
+        assert_eq!(Int { constant: 3 }, result);
+        assert_eq!(IntBot, Top.widen(&Int { constant: 3 }, WIDENING_ITERATIONS));
+    }
+
+    #[test]
+    fn should_display_typs() {
+        // Arrange
+        let cases = [
+            (Int { constant: 5 }, "int(5)"),
+            (IntBot, "int⊥"),
+            (IntTop, "int⊤"),
+            (Bot, "⊥"),
+            (Top, "⊤"),
+            (Ctrl, "ctrl"),
+            (Typ::Mem, "mem"),
+            (Typ::Bool { constant: true }, "bool(true)"),
+            (BoolBot, "bool⊥"),
+            (Float { constant: 1.0 }, "float(1.0)"),
+            (Typ::Tuple { typs: vec![Ctrl, Typ::Mem, IntBot] }, "[ctrl, mem, int⊥]"),
+            (Typ::Array { elem: Box::new(IntBot), len: Some(3) }, "int⊥[3]"),
+        ];
+
+        for (typ, expected) in cases {
+            // Act
+            let result = typ.to_string();
+
+            // Assert
+            assert_eq!(expected, result);
+        }
+    }
+
+    #[test]
+    fn should_order_constant_below_int_bot() {
+        // Arrange & Act & Assert
+        assert!(Int { constant: 5 }.le(&IntBot));
+        assert!(!IntBot.le(&Int { constant: 5 }));
+        assert!(Top.le(&Int { constant: 5 }));
+        assert!(Int { constant: 5 }.le(&Int { constant: 5 }));
+        assert!(!Int { constant: 5 }.le(&Int { constant: 6 }));
+        assert!(!Int { constant: 5 }.le(&BoolBot));
+    }
+
+    #[test]
+    fn should_detect_top_and_bottom() {
+        // Arrange & Act & Assert
+        assert!(Top.is_top());
+        assert!(IntTop.is_top() && IntTop.dual().is_bottom());
+        assert!(Bot.is_bottom());
+        assert!(!Int { constant: 5 }.is_top() && !Int { constant: 5 }.is_bottom());
+        assert!(!Ctrl.is_top() && !Ctrl.is_bottom());
+    }
+
     #[test]
     fn should_allow_transition_from_bot_to_int() {
         // Arrange & Act
@@ -181,5 +525,61 @@ mod tests {
         // Assert
         assert!(!result);
     }
-}
 
+    #[test]
+    fn should_meet_float_top_and_float() {
+        // Arrange & Act
+        let result = FloatTop.meet(&Float { constant: 1.5 });
+
+        // Assert
+        assert_eq!(Float { constant: 1.5 }, result);
+    }
+
+    #[test]
+    fn should_meet_different_floats_to_float_bot() {
+        // Arrange & Act
+        let result = Float { constant: 1.5 }.meet(&Float { constant: 2.5 });
+
+        // Assert
+        assert_eq!(FloatBot, result);
+    }
+
+    #[test]
+    fn should_treat_nan_constants_as_equal() {
+        // Arrange & Act
+        let result = Float { constant: f64::NAN }.transition_allowed(&Float { constant: f64::NAN });
+
+        // Assert
+        assert!(result);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn should_round_trip_through_json() {
+        use crate::typ::typ::Typ;
+
+        for typ in [
+            Int { constant: -7 },
+            Typ::Tuple { typs: vec![Ctrl, Int { constant: 3 }, Typ::Tuple { typs: vec![Typ::BoolBot] }] },
+            Ctrl,
+            Top,
+            Bot,
+            IntTop,
+            Typ::IntBot,
+            TupleTop,
+            Typ::TupleBot,
+            Typ::BoolTop,
+            Typ::BoolBot,
+            FloatTop,
+            FloatBot,
+            Float { constant: 1.5 },
+        ] {
+            // Act
+            let json = serde_json::to_string(&typ).unwrap();
+            let result: Typ = serde_json::from_str(&json).unwrap();
+
+            // Assert
+            assert_eq!(typ, result, "{}", json);
+        }
+    }
+}
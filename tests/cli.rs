@@ -0,0 +1,110 @@
+use ruson::cli::{compile_reader_to_dot, compile_to_dot, evaluate_line, repl, CliOptions};
+use ruson::errors::son_error::SoNError;
+
+#[test]
+fn should_compile_program_with_arg_to_dot() {
+    // Arrange
+    let options = CliOptions::parse(["--arg".to_string(), "3".to_string(), "--no-optimize".to_string()]).unwrap();
+
+    // Act
+    let dot = compile_to_dot("return arg+1;", &options).unwrap();
+
+    // Assert
+    assert!(!options.optimize);
+    assert!(dot.starts_with("digraph mygraph{"));
+    assert!(dot.contains("return arg+1;"));
+}
+
+#[test]
+fn should_compile_program_from_reader_to_dot() {
+    // Arrange
+    let options = CliOptions::parse(["--no-optimize".to_string()]).unwrap();
+    let reader = std::io::Cursor::new(b"return arg+1;".to_vec());
+
+    // Act
+    let dot = compile_reader_to_dot(reader, &options).unwrap();
+
+    // Assert
+    assert!(dot.starts_with("digraph mygraph{"));
+    assert!(dot.contains("return arg+1;"));
+}
+
+#[test]
+fn should_report_parse_error_with_context() {
+    // Arrange
+    let options = CliOptions::parse(["prog.simple".to_string()]).unwrap();
+
+    // Act
+    let result = compile_to_dot("return 1+;", &options);
+
+    // Assert
+    assert_eq!(Some("prog.simple".to_string()), options.input);
+    assert!(matches!(result, Err(e) if matches!(e.error, SoNError::SyntaxExpected { .. }) && e.line == 1));
+}
+
+#[test]
+fn should_reject_non_integer_arg() {
+    // Act
+    let result = CliOptions::parse(["--arg".to_string(), "x".to_string()]);
+
+    // Assert
+    assert!(result.is_err());
+}
+
+#[test]
+fn should_evaluate_expression_line() {
+    // Arrange
+    let options = CliOptions::parse(["--repl".to_string()]).unwrap();
+
+    // Act
+    let output = evaluate_line("2*3+arg", &options);
+
+    // Assert
+    assert!(options.repl);
+    assert_eq!("return arg+6;\n=> int(6)", output);
+}
+
+#[test]
+fn should_keep_repl_going_after_error() {
+    // Arrange
+    let input = "1+;\nreturn 1<2;\n".as_bytes();
+    let mut output = vec![];
+
+    // Act
+    repl(input, &mut output, &CliOptions::default()).unwrap();
+
+    // Assert
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.contains("SyntaxExpected"));
+    assert!(output.ends_with("return true;\n=> bool(true)\n> \n"));
+}
+
+#[test]
+fn should_evaluate_repl_line_with_print() {
+    // Arrange
+    let input = "print(arg); return arg+3;\n1<2\n".as_bytes();
+    let mut output = vec![];
+
+    // Act
+    repl(input, &mut output, &CliOptions::default()).unwrap();
+
+    // Assert
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.contains("=> int(3)\n"));
+    assert!(output.ends_with("return true;\n=> bool(true)\n> \n"));
+}
+
+#[test]
+fn should_keep_repl_going_after_endless_recursion() {
+    // Arrange
+    let input = "int f(int x){ return f(x)+1; } return f(1);\n1<2\n".as_bytes();
+    let mut output = vec![];
+
+    // Act
+    repl(input, &mut output, &CliOptions::default()).unwrap();
+
+    // Assert
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.contains("=> ExecutionLimitExceeded\n"));
+    assert!(output.ends_with("return true;\n=> bool(true)\n> \n"));
+}